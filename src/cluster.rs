@@ -0,0 +1,334 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::{
+    ConfigMap, Event, Namespace, PersistentVolumeClaim, Pod, Secret, Service,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::{
+    api::{Api, DeleteParams, ListParams, LogParams, Patch, PatchParams, PostParams},
+    config::{KubeConfigOptions, Kubeconfig},
+    runtime::wait::await_condition,
+    Client, Config, ResourceExt,
+};
+use log::info;
+use tokio::sync::OnceCell;
+
+//condition of pod waited by the cluster, matched against None once the pod is gone
+pub type PodCondition = Box<dyn Fn(Option<&Pod>) -> bool + Send + Sync>;
+pub type PvcCondition = Box<dyn Fn(Option<&PersistentVolumeClaim>) -> bool + Send + Sync>;
+
+//operations on kubernetes used by allocation and deallocation
+#[async_trait]
+pub trait Cluster: Send + Sync {
+    async fn get_namespace(&self, name: &str) -> Result<Namespace>;
+    async fn get_or_create_configmap(&self, configmap: &ConfigMap) -> Result<ConfigMap>;
+    async fn create_pod(&self, pod: &Pod) -> Result<Pod>;
+    async fn get_pod(&self, name: &str) -> Result<Option<Pod>>;
+    async fn list_pods(&self, list_params: &ListParams) -> Result<Vec<Pod>>;
+    async fn set_pod_finalizers(&self, name: &str, finalizers: Vec<String>) -> Result<()>;
+    //resolves once the condition matches, callers are responsible for the timeout
+    async fn wait_pod(&self, name: &str, condition: PodCondition) -> Result<Option<Pod>>;
+    async fn delete_pod(&self, name: &str, delete_params: &DeleteParams) -> Result<()>;
+    //events whose involved object is the pod, used to explain failed allocations
+    async fn list_pod_events(&self, name: &str) -> Result<Vec<Event>>;
+    async fn pod_logs(&self, name: &str, container: &str, tail_lines: i64) -> Result<String>;
+    async fn get_pvc(&self, name: &str) -> Result<Option<PersistentVolumeClaim>>;
+    async fn create_pvc(&self, pvc: &PersistentVolumeClaim) -> Result<()>;
+    async fn wait_pvc(
+        &self,
+        name: &str,
+        condition: PvcCondition,
+    ) -> Result<Option<PersistentVolumeClaim>>;
+    async fn delete_pvc(&self, name: &str, delete_params: &DeleteParams) -> Result<()>;
+    async fn create_service(&self, service: &Service) -> Result<()>;
+    async fn list_services(&self, list_params: &ListParams) -> Result<Vec<Service>>;
+    async fn delete_service(&self, name: &str, delete_params: &DeleteParams) -> Result<()>;
+    async fn create_secret(&self, secret: &Secret) -> Result<()>;
+    async fn set_secret_owner_references(
+        &self,
+        name: &str,
+        owner_references: Vec<OwnerReference>,
+    ) -> Result<()>;
+    async fn delete_secret(&self, name: &str, delete_params: &DeleteParams) -> Result<()>;
+}
+
+//credentials of the kubeconfig context, or inferred like kubectl when no context is given
+pub async fn kube_config(context: Option<&str>) -> Result<Config> {
+    match context {
+        Some(context) => context_config(Kubeconfig::read()?, context).await,
+        None => Ok(Config::infer().await?),
+    }
+}
+
+//the namespace of context is ignored by the cluster, which always uses its own namespace
+async fn context_config(kubeconfig: Kubeconfig, context: &str) -> Result<Config> {
+    Ok(Config::from_custom_kubeconfig(
+        kubeconfig,
+        &KubeConfigOptions {
+            context: Some(context.to_string()),
+            ..Default::default()
+        },
+    )
+    .await?)
+}
+
+//cluster backed by the kubernetes api, the client is created on first use
+//the context only selects credentials, resources are always created in the namespace
+pub struct KubeCluster {
+    namespace: String,
+    context: Option<String>,
+    client: OnceCell<Client>,
+}
+
+impl KubeCluster {
+    pub fn new(namespace: &str) -> Self {
+        KubeCluster {
+            namespace: namespace.to_string(),
+            context: None,
+            client: OnceCell::new(),
+        }
+    }
+
+    pub fn with_context(mut self, context: Option<&str>) -> Self {
+        self.context = context.map(|c| c.to_string());
+        self
+    }
+
+    pub fn with_client(client: Client, namespace: &str) -> Self {
+        KubeCluster {
+            namespace: namespace.to_string(),
+            context: None,
+            client: OnceCell::new_with(Some(client)),
+        }
+    }
+
+    async fn client(&self) -> Result<Client> {
+        let client = self
+            .client
+            .get_or_try_init(|| async {
+                let config = kube_config(self.context.as_deref()).await?;
+                Ok::<Client, anyhow::Error>(Client::try_from(config)?)
+            })
+            .await?;
+        Ok(client.clone())
+    }
+
+    async fn pods(&self) -> Result<Api<Pod>> {
+        Ok(Api::namespaced(self.client().await?, &self.namespace))
+    }
+
+    async fn pvcs(&self) -> Result<Api<PersistentVolumeClaim>> {
+        Ok(Api::namespaced(self.client().await?, &self.namespace))
+    }
+
+    async fn services(&self) -> Result<Api<Service>> {
+        Ok(Api::namespaced(self.client().await?, &self.namespace))
+    }
+
+    async fn secrets(&self) -> Result<Api<Secret>> {
+        Ok(Api::namespaced(self.client().await?, &self.namespace))
+    }
+}
+
+#[async_trait]
+impl Cluster for KubeCluster {
+    async fn get_namespace(&self, name: &str) -> Result<Namespace> {
+        let namespace_api: Api<Namespace> = Api::all(self.client().await?);
+        Ok(namespace_api.get(name).await?)
+    }
+
+    async fn get_or_create_configmap(&self, configmap: &ConfigMap) -> Result<ConfigMap> {
+        let configmap_api: Api<ConfigMap> = Api::namespaced(self.client().await?, &self.namespace);
+        let name = configmap.name_any();
+        if let Some(existing) = configmap_api.get_opt(&name).await? {
+            return Ok(existing);
+        }
+        match configmap_api
+            .create(&PostParams::default(), configmap)
+            .await
+        {
+            Ok(created) => {
+                info!("owner configmap {} has been created", name);
+                Ok(created)
+            }
+            //created concurrently by another allocation
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(configmap_api.get(&name).await?),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn create_pod(&self, pod: &Pod) -> Result<Pod> {
+        Ok(self
+            .pods()
+            .await?
+            .create(&PostParams::default(), pod)
+            .await?)
+    }
+
+    async fn get_pod(&self, name: &str) -> Result<Option<Pod>> {
+        Ok(self.pods().await?.get_opt(name).await?)
+    }
+
+    async fn list_pods(&self, list_params: &ListParams) -> Result<Vec<Pod>> {
+        Ok(self.pods().await?.list(list_params).await?.items)
+    }
+
+    async fn set_pod_finalizers(&self, name: &str, finalizers: Vec<String>) -> Result<()> {
+        let patch = serde_json::json!({ "metadata": { "finalizers": finalizers } });
+        self.pods()
+            .await?
+            .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await?;
+        Ok(())
+    }
+
+    async fn wait_pod(&self, name: &str, condition: PodCondition) -> Result<Option<Pod>> {
+        Ok(await_condition(self.pods().await?, name, condition).await?)
+    }
+
+    async fn delete_pod(&self, name: &str, delete_params: &DeleteParams) -> Result<()> {
+        self.pods().await?.delete(name, delete_params).await?;
+        Ok(())
+    }
+
+    async fn list_pod_events(&self, name: &str) -> Result<Vec<Event>> {
+        let event_api: Api<Event> = Api::namespaced(self.client().await?, &self.namespace);
+        let list_params = ListParams::default().fields(&format!(
+            "involvedObject.kind=Pod,involvedObject.name={}",
+            name
+        ));
+        Ok(event_api.list(&list_params).await?.items)
+    }
+
+    async fn pod_logs(&self, name: &str, container: &str, tail_lines: i64) -> Result<String> {
+        let log_params = LogParams {
+            container: Some(container.to_string()),
+            tail_lines: Some(tail_lines),
+            ..Default::default()
+        };
+        Ok(self.pods().await?.logs(name, &log_params).await?)
+    }
+
+    async fn get_pvc(&self, name: &str) -> Result<Option<PersistentVolumeClaim>> {
+        Ok(self.pvcs().await?.get_opt(name).await?)
+    }
+
+    async fn create_pvc(&self, pvc: &PersistentVolumeClaim) -> Result<()> {
+        self.pvcs()
+            .await?
+            .create(&PostParams::default(), pvc)
+            .await?;
+        Ok(())
+    }
+
+    async fn wait_pvc(
+        &self,
+        name: &str,
+        condition: PvcCondition,
+    ) -> Result<Option<PersistentVolumeClaim>> {
+        Ok(await_condition(self.pvcs().await?, name, condition).await?)
+    }
+
+    async fn delete_pvc(&self, name: &str, delete_params: &DeleteParams) -> Result<()> {
+        self.pvcs().await?.delete(name, delete_params).await?;
+        Ok(())
+    }
+
+    async fn create_service(&self, service: &Service) -> Result<()> {
+        self.services()
+            .await?
+            .create(&PostParams::default(), service)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_services(&self, list_params: &ListParams) -> Result<Vec<Service>> {
+        Ok(self.services().await?.list(list_params).await?.items)
+    }
+
+    async fn delete_service(&self, name: &str, delete_params: &DeleteParams) -> Result<()> {
+        self.services().await?.delete(name, delete_params).await?;
+        Ok(())
+    }
+
+    async fn create_secret(&self, secret: &Secret) -> Result<()> {
+        self.secrets()
+            .await?
+            .create(&PostParams::default(), secret)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_secret_owner_references(
+        &self,
+        name: &str,
+        owner_references: Vec<OwnerReference>,
+    ) -> Result<()> {
+        let patch = serde_json::json!({ "metadata": { "ownerReferences": owner_references } });
+        self.secrets()
+            .await?
+            .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_secret(&self, name: &str, delete_params: &DeleteParams) -> Result<()> {
+        self.secrets().await?.delete(name, delete_params).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{context_config, KubeCluster};
+    use kube::config::Kubeconfig;
+
+    const KUBECONFIG: &str = r#"apiVersion: v1
+kind: Config
+current-context: ctx-a
+clusters:
+- name: cluster-a
+  cluster:
+    server: https://a.example.com:6443
+- name: cluster-b
+  cluster:
+    server: https://b.example.com:6443
+users:
+- name: user-a
+  user:
+    username: admin-a
+- name: user-b
+  user:
+    username: admin-b
+contexts:
+- name: ctx-a
+  context:
+    cluster: cluster-a
+    user: user-a
+    namespace: ns-a
+- name: ctx-b
+  context:
+    cluster: cluster-b
+    user: user-b
+    namespace: ns-b
+"#;
+
+    #[tokio::test]
+    async fn test_context_selects_cluster_and_user() {
+        let kubeconfig = Kubeconfig::from_yaml(KUBECONFIG).unwrap();
+        let config = context_config(kubeconfig.clone(), "ctx-b").await.unwrap();
+        assert_eq!(config.cluster_url.host(), Some("b.example.com"));
+        assert_eq!(config.auth_info.username.as_deref(), Some("admin-b"));
+
+        let config = context_config(kubeconfig.clone(), "ctx-a").await.unwrap();
+        assert_eq!(config.cluster_url.host(), Some("a.example.com"));
+        assert_eq!(config.auth_info.username.as_deref(), Some("admin-a"));
+
+        assert!(context_config(kubeconfig, "ctx-c").await.is_err());
+
+        //resources are created in the namespace of cluster rather than the one of context
+        let cluster = KubeCluster::new("test_ns").with_context(Some("ctx-b"));
+        assert_eq!(cluster.namespace, "test_ns");
+        assert_eq!(cluster.context.as_deref(), Some("ctx-b"));
+    }
+}
@@ -0,0 +1,388 @@
+use crate::cluster::{Cluster, PodCondition, PvcCondition};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::{
+    ConfigMap, Event, Namespace, PersistentVolumeClaim, PersistentVolumeClaimStatus, Pod,
+    PodStatus, Secret, Service,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference};
+use kube::{
+    api::{DeleteParams, ListParams},
+    error::ErrorResponse,
+    ResourceExt,
+};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+//in-memory cluster used to exercise allocation without kubernetes, finalizers are not simulated
+#[derive(Default)]
+pub struct FakeCluster {
+    pod_status: Option<PodStatus>,
+    pod_status_after_ready: Option<PodStatus>,
+    pvc_status: Option<PersistentVolumeClaimStatus>,
+    namespaces: Mutex<BTreeMap<String, Namespace>>,
+    configmaps: Mutex<BTreeMap<String, ConfigMap>>,
+    pods: Mutex<BTreeMap<String, Pod>>,
+    pvcs: Mutex<BTreeMap<String, PersistentVolumeClaim>>,
+    services: Mutex<BTreeMap<String, Service>>,
+    secrets: Mutex<BTreeMap<String, Secret>>,
+    pod_events: Vec<Event>,
+    container_logs: Option<String>,
+    pod_name_conflicts: Mutex<u32>,
+    pvc_create_conflicts: Mutex<u32>,
+    failing_operations: Vec<&'static str>,
+}
+
+impl FakeCluster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //status assigned to every pod once created, pods without status never become running
+    pub fn with_pod_status(mut self, status: PodStatus) -> Self {
+        self.pod_status = Some(status);
+        self
+    }
+
+    //status the pods switch to once a wait on them has matched, e.g. evicted after running
+    pub fn with_pod_status_after_ready(mut self, status: PodStatus) -> Self {
+        self.pod_status_after_ready = Some(status);
+        self
+    }
+
+    //status assigned to every pvc once created, pvcs without status are never bound
+    pub fn with_pvc_status(mut self, status: PersistentVolumeClaimStatus) -> Self {
+        self.pvc_status = Some(status);
+        self
+    }
+
+    //the next pods created are rejected as their names are taken by other pods, which are stored
+    pub fn with_pod_name_conflicts(self, count: u32) -> Self {
+        *self.pod_name_conflicts.lock().unwrap() = count;
+        self
+    }
+
+    //the next pvcs created are rejected as if they were created concurrently by someone else,
+    //the concurrently created pvcs are stored
+    pub fn with_pvc_create_conflicts(self, count: u32) -> Self {
+        *self.pvc_create_conflicts.lock().unwrap() = count;
+        self
+    }
+
+    //the operation named after the method of cluster always fails, e.g. "create_pvc"
+    pub fn with_failing_operation(mut self, operation: &'static str) -> Self {
+        self.failing_operations.push(operation);
+        self
+    }
+
+    fn check_operation(&self, operation: &str) -> Result<()> {
+        if self.failing_operations.contains(&operation) {
+            return Err(anyhow!("{} failed", operation));
+        }
+        Ok(())
+    }
+
+    pub fn with_namespace(self, namespace: Namespace) -> Self {
+        self.namespaces
+            .lock()
+            .unwrap()
+            .insert(namespace.name_any(), namespace);
+        self
+    }
+
+    pub fn with_pod(self, pod: Pod) -> Self {
+        self.pods.lock().unwrap().insert(pod.name_any(), pod);
+        self
+    }
+
+    pub fn with_pvc(self, pvc: PersistentVolumeClaim) -> Self {
+        self.pvcs.lock().unwrap().insert(pvc.name_any(), pvc);
+        self
+    }
+
+    //event reported for every pod, as created pods get random names
+    pub fn with_pod_event(mut self, event: Event) -> Self {
+        self.pod_events.push(event);
+        self
+    }
+
+    //logs printed by every container, containers have no logs otherwise
+    pub fn with_container_logs(mut self, logs: &str) -> Self {
+        self.container_logs = Some(logs.to_string());
+        self
+    }
+
+    pub fn pods(&self) -> Vec<Pod> {
+        self.pods.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn pvcs(&self) -> Vec<PersistentVolumeClaim> {
+        self.pvcs.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn services(&self) -> Vec<Service> {
+        self.services.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn secrets(&self) -> Vec<Secret> {
+        self.secrets.lock().unwrap().values().cloned().collect()
+    }
+}
+
+fn created(metadata: &ObjectMeta) -> ObjectMeta {
+    ObjectMeta {
+        uid: Some(Uuid::new_v4().to_string()),
+        ..metadata.clone()
+    }
+}
+
+//same error as the api server, so that callers can handle conflicts
+fn already_exists(name: &str) -> anyhow::Error {
+    kube::Error::Api(ErrorResponse {
+        status: "Failure".to_string(),
+        message: format!("{} already exists", name),
+        reason: "AlreadyExists".to_string(),
+        code: 409,
+    })
+    .into()
+}
+
+fn insert_new<K: ResourceExt>(store: &Mutex<BTreeMap<String, K>>, resource: K) -> Result<()> {
+    let mut store = store.lock().unwrap();
+    let name = resource.name_any();
+    if store.contains_key(&name) {
+        return Err(already_exists(&name));
+    }
+    store.insert(name, resource);
+    Ok(())
+}
+
+fn remove<K>(store: &Mutex<BTreeMap<String, K>>, name: &str) -> Result<()> {
+    store
+        .lock()
+        .unwrap()
+        .remove(name)
+        .map(|_| ())
+        .ok_or_else(|| anyhow!("{} not found", name))
+}
+
+//supports the equality selectors generated by this crate, e.g. "app=x,has_volume=true"
+fn matches_selector(selector: Option<&String>, lookup: impl Fn(&str) -> Option<String>) -> bool {
+    selector
+        .into_iter()
+        .flat_map(|s| s.split(','))
+        .filter(|requirement| !requirement.is_empty())
+        .all(|requirement| match requirement.split_once('=') {
+            Some((key, value)) => lookup(key).as_deref() == Some(value.trim_start_matches('=')),
+            None => lookup(requirement).is_some(),
+        })
+}
+
+fn matches_list_params<K: ResourceExt>(resource: &K, list_params: &ListParams) -> bool {
+    matches_selector(list_params.label_selector.as_ref(), |key| {
+        resource.labels().get(key).cloned()
+    })
+}
+
+fn matches_pod_fields(pod: &Pod, list_params: &ListParams) -> bool {
+    matches_selector(list_params.field_selector.as_ref(), |key| match key {
+        "metadata.name" => Some(pod.name_any()),
+        "status.podIP" => pod.status.as_ref().and_then(|s| s.pod_ip.clone()),
+        "status.phase" => pod.status.as_ref().and_then(|s| s.phase.clone()),
+        _ => None,
+    })
+}
+
+#[async_trait]
+impl Cluster for FakeCluster {
+    async fn get_namespace(&self, name: &str) -> Result<Namespace> {
+        self.namespaces
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("namespace {} not found", name))
+    }
+
+    async fn get_or_create_configmap(&self, configmap: &ConfigMap) -> Result<ConfigMap> {
+        let mut configmaps = self.configmaps.lock().unwrap();
+        let created = configmaps
+            .entry(configmap.name_any())
+            .or_insert_with(|| ConfigMap {
+                metadata: created(&configmap.metadata),
+                ..configmap.clone()
+            });
+        Ok(created.clone())
+    }
+
+    async fn create_pod(&self, pod: &Pod) -> Result<Pod> {
+        let pod = Pod {
+            metadata: created(&pod.metadata),
+            status: self.pod_status.clone(),
+            ..pod.clone()
+        };
+        {
+            let mut conflicts = self.pod_name_conflicts.lock().unwrap();
+            if *conflicts > 0 {
+                *conflicts -= 1;
+                let name = pod.name_any();
+                insert_new(&self.pods, pod)?;
+                return Err(already_exists(&name));
+            }
+        }
+        insert_new(&self.pods, pod.clone())?;
+        Ok(pod)
+    }
+
+    async fn get_pod(&self, name: &str) -> Result<Option<Pod>> {
+        Ok(self.pods.lock().unwrap().get(name).cloned())
+    }
+
+    async fn list_pods(&self, list_params: &ListParams) -> Result<Vec<Pod>> {
+        Ok(self
+            .pods()
+            .into_iter()
+            .filter(|p| matches_list_params(p, list_params) && matches_pod_fields(p, list_params))
+            .collect())
+    }
+
+    async fn set_pod_finalizers(&self, name: &str, finalizers: Vec<String>) -> Result<()> {
+        self.check_operation("set_pod_finalizers")?;
+        let mut pods = self.pods.lock().unwrap();
+        let pod = pods
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("{} not found", name))?;
+        pod.metadata.finalizers = Some(finalizers);
+        Ok(())
+    }
+
+    async fn wait_pod(&self, name: &str, condition: PodCondition) -> Result<Option<Pod>> {
+        //nothing changes pods behind the caller's back, unmatched conditions wait forever
+        let pod = self.get_pod(name).await?;
+        if condition(pod.as_ref()) {
+            if let Some(status) = &self.pod_status_after_ready {
+                if let Some(current) = self.pods.lock().unwrap().get_mut(name) {
+                    current.status = Some(status.clone());
+                }
+            }
+            return Ok(pod);
+        }
+        std::future::pending().await
+    }
+
+    async fn delete_pod(&self, name: &str, _delete_params: &DeleteParams) -> Result<()> {
+        remove(&self.pods, name)
+    }
+
+    async fn list_pod_events(&self, name: &str) -> Result<Vec<Event>> {
+        Ok(self
+            .pod_events
+            .iter()
+            .map(|e| {
+                let mut event = e.clone();
+                event.involved_object.kind = Some("Pod".to_string());
+                event.involved_object.name = Some(name.to_string());
+                event
+            })
+            .collect())
+    }
+
+    async fn pod_logs(&self, name: &str, container: &str, tail_lines: i64) -> Result<String> {
+        let logs = self
+            .container_logs
+            .as_ref()
+            .ok_or_else(|| anyhow!("container {} of pod {} has no logs", container, name))?;
+        let lines: Vec<&str> = logs.lines().collect();
+        let skipped = lines.len().saturating_sub(tail_lines as usize);
+        Ok(lines[skipped..]
+            .iter()
+            .map(|l| format!("{}\n", l))
+            .collect())
+    }
+
+    async fn get_pvc(&self, name: &str) -> Result<Option<PersistentVolumeClaim>> {
+        Ok(self.pvcs.lock().unwrap().get(name).cloned())
+    }
+
+    async fn create_pvc(&self, pvc: &PersistentVolumeClaim) -> Result<()> {
+        self.check_operation("create_pvc")?;
+        let pvc = PersistentVolumeClaim {
+            metadata: created(&pvc.metadata),
+            status: self.pvc_status.clone(),
+            ..pvc.clone()
+        };
+        {
+            let mut conflicts = self.pvc_create_conflicts.lock().unwrap();
+            if *conflicts > 0 {
+                *conflicts -= 1;
+                let name = pvc.name_any();
+                insert_new(&self.pvcs, pvc)?;
+                return Err(already_exists(&name));
+            }
+        }
+        insert_new(&self.pvcs, pvc)
+    }
+
+    async fn wait_pvc(
+        &self,
+        name: &str,
+        condition: PvcCondition,
+    ) -> Result<Option<PersistentVolumeClaim>> {
+        let pvc = self.pvcs.lock().unwrap().get(name).cloned();
+        if condition(pvc.as_ref()) {
+            return Ok(pvc);
+        }
+        std::future::pending().await
+    }
+
+    async fn delete_pvc(&self, name: &str, _delete_params: &DeleteParams) -> Result<()> {
+        remove(&self.pvcs, name)
+    }
+
+    async fn create_service(&self, service: &Service) -> Result<()> {
+        let service = Service {
+            metadata: created(&service.metadata),
+            ..service.clone()
+        };
+        insert_new(&self.services, service)
+    }
+
+    async fn list_services(&self, list_params: &ListParams) -> Result<Vec<Service>> {
+        Ok(self
+            .services()
+            .into_iter()
+            .filter(|s| matches_list_params(s, list_params))
+            .collect())
+    }
+
+    async fn delete_service(&self, name: &str, _delete_params: &DeleteParams) -> Result<()> {
+        remove(&self.services, name)
+    }
+
+    async fn create_secret(&self, secret: &Secret) -> Result<()> {
+        let secret = Secret {
+            metadata: created(&secret.metadata),
+            ..secret.clone()
+        };
+        insert_new(&self.secrets, secret)
+    }
+
+    async fn set_secret_owner_references(
+        &self,
+        name: &str,
+        owner_references: Vec<OwnerReference>,
+    ) -> Result<()> {
+        self.check_operation("set_secret_owner_references")?;
+        let mut secrets = self.secrets.lock().unwrap();
+        let secret = secrets
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("{} not found", name))?;
+        secret.metadata.owner_references = Some(owner_references);
+        Ok(())
+    }
+
+    async fn delete_secret(&self, name: &str, _delete_params: &DeleteParams) -> Result<()> {
+        remove(&self.secrets, name)
+    }
+}
@@ -0,0 +1,8756 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, ValueEnum};
+use k8s_openapi::api::core::v1::{
+    Affinity, Capabilities, ConfigMap, Container, ContainerState, EmptyDirVolumeSource, GRPCAction,
+    HTTPGetAction, Namespace, NodeSelector, NodeSelectorRequirement, NodeSelectorTerm,
+    PersistentVolumeClaim, Pod, PodOS, PodSecurityContext, PodStatus, PreferredSchedulingTerm,
+    Probe, Secret, SecurityContext, Service, ServicePort, ServiceSpec, TCPSocketAction, Toleration,
+    Volume, VolumeMount, WindowsSecurityContextOptions,
+};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::{
+    api::{DeleteParams, ListParams},
+    runtime::wait::{conditions, conditions::is_pod_running, Condition},
+    ResourceExt,
+};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use handlebars::{no_escape, Handlebars};
+
+pub mod cluster;
+#[cfg(any(test, feature = "testing"))]
+pub mod fake;
+
+use cluster::{Cluster, KubeCluster};
+
+static RAW_VOLUME_MOUNT: &str = r#"volumeMounts:
+{{content}}"#;
+
+static RAW_SECRET_MOUNT: &str = r#"      - mountPath: {{mount_path}}
+        name: {{name}}
+{{#if sub_path}}
+        subPath: {{sub_path}}
+{{/if}}
+"#;
+
+static RAW_VOLUME_MOUNT_PVC: &str = r#"      - mountPath: {{mount_path}}
+        name: {{volume_name}}
+"#;
+
+static RAW_VOLUME_MOUNT_PVC_READONLY: &str = r#"      - mountPath: {{mount_path}}
+        name: {{volume_name}}
+        readOnly: true
+"#;
+
+static RAW_ENV: &str = r#"env:
+{{content}}"#;
+
+static RAW_ENV_VAR: &str = r#"      - name: {{name}}
+        value: {{value}}
+"#;
+
+static RAW_SEQUENCE: &str = r#"{{key}}:
+{{content}}"#;
+
+static RAW_SEQUENCE_ITEM: &str = r#"      - {{value}}
+"#;
+
+static RAW_ENV_FROM: &str = r#"envFrom:
+{{content}}"#;
+
+static RAW_ENV_FROM_SECRET: &str = r#"      - secretRef:
+          name: {{name}}
+"#;
+
+static RAW_IMAGE_PULL_SECRETS: &str = r#"imagePullSecrets:
+    - name: {{name}}"#;
+
+static RAW_SIDECAR: &str = r#"    - image: {{image}}
+      imagePullPolicy: {{image_pull_policy}}
+      name: sidecar
+      {{volume_mount}}"#;
+
+static RAW_POD: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: {{name}}
+  namespace: {{namespace}}
+  labels:
+    app: resalloc-kubernetes
+    has_volume: {{has_volume}}
+spec:
+  {{volume}}
+  {{image_pull_secrets}}
+  containers:
+    - image: {{image}}
+      imagePullPolicy: {{image_pull_policy}}
+      name: {{name}}
+      {{command}}
+      {{args}}
+      securityContext:
+        privileged: {{privileged}}
+      resources:
+        limits:
+          cpu: {{cpu_limit}}
+          memory: {{memory_limit}}
+{{#if ephemeral_storage}}
+          ephemeral-storage: {{ephemeral_storage}}
+{{/if}}
+        requests:
+          cpu: {{cpu_request}}
+          memory: {{memory_request}}
+{{#if ephemeral_storage}}
+          ephemeral-storage: {{ephemeral_storage}}
+{{/if}}
+      {{env}}
+      {{env_from}}
+      {{volume_mount}}
+{{sidecar}}"#;
+
+static RAW_VOLUME_HEADER: &str = "volumes:";
+
+static RAW_VOLUME: &str = r#"
+  - name: {{volume_name}}
+    persistentVolumeClaim:
+      claimName: {{claim_name}}"#;
+
+//missing size limit and medium are rendered as null, which keeps the emptyDir with defaults
+static RAW_EMPTYDIR_VOLUME: &str = r#"
+  - name: {{volume_name}}
+    emptyDir:
+      sizeLimit: {{size_limit}}
+      medium: {{medium}}"#;
+
+static RAW_SECRET_VOLUME: &str = r#"
+  - name: {{volume_name}}
+    secret:
+      secretName: {{secret_name}}"#;
+
+static RAW_CONFIGMAP_VOLUME: &str = r#"
+  - name: {{volume_name}}
+    configMap:
+      name: {{configmap_name}}"#;
+
+static ALLOCATION_ID_LABEL: &str = "resalloc.allocation-id";
+
+static IDEMPOTENCY_KEY_LABEL: &str = "resalloc.idempotency-key";
+
+static TRACE_ID_ANNOTATION: &str = "resalloc.trace-id";
+
+static PROTECT_FINALIZER: &str = "resalloc-kubernetes/protect";
+
+static DEFAULT_COST_LABEL_KEY: &str = "cost-center";
+
+static RAW_PVC: &str = r#"apiVersion: v1
+kind: PersistentVolumeClaim
+metadata:
+  name: {{name}}
+  namespace: {{namespace}}
+  labels:
+    app: resalloc-kubernetes
+spec:
+  accessModes:
+  - {{access_mode}}
+  resources:
+    requests:
+      storage: {{size}}
+{{#if limit}}
+    limits:
+      storage: {{limit}}
+{{/if}}
+{{#if volume_name}}
+  volumeName: {{volume_name}}
+{{/if}}
+{{#if class}}
+  storageClassName: {{class}}
+{{else}}
+  storageClassName: ""
+{{/if}}"#;
+
+#[derive(Args, Clone, Default)]
+#[command(args_override_self = true)]
+pub struct AllocationRequest {
+    #[arg(long)]
+    #[arg(
+        help = "specify a yaml file mapping option names to values, for example 'image-tag: openeuler/openeuler:22.03', options on the command line override the file"
+    )]
+    pub values: Option<PathBuf>,
+    #[arg(long, default_value_t = 90)]
+    #[arg(help = "timeout for waiting pod to be ready")]
+    pub timeout: u64,
+    #[arg(long, env = "RESALLOC_IMAGE_TAG")]
+    #[arg(
+        help = "specify the image tag used for generating, for example: docker.io/organization/image:tag"
+    )]
+    pub image_tag: String,
+    #[arg(long, env = "RESALLOC_CPU_RESOURCE")]
+    #[arg(help = "specify the request and limit cpu resource, '1', '2000m' and etc.")]
+    pub cpu_resource: String,
+    #[arg(long, env = "RESALLOC_MEMORY_RESOURCE")]
+    #[arg(help = "specify the request and limit memory resource, '1024Mi', '2Gi' and etc.")]
+    pub memory_resource: String,
+    #[arg(long)]
+    #[arg(help = "specify the request cpu resource, overrides the cpu resource for requests")]
+    pub cpu_request: Option<String>,
+    #[arg(long)]
+    #[arg(help = "specify the limit cpu resource, overrides the cpu resource for limits")]
+    pub cpu_limit: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the request memory resource, overrides the memory resource for requests"
+    )]
+    pub memory_request: Option<String>,
+    #[arg(long)]
+    #[arg(help = "specify the limit memory resource, overrides the memory resource for limits")]
+    pub memory_limit: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the request and limit ephemeral storage resource, '10Gi' and etc., the pod is evicted once exceeded"
+    )]
+    pub ephemeral_storage: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the node selector for pod resource in the format of 'NAME=VALUE', can be specified with multiple times"
+    )]
+    pub node_selector: Vec<String>,
+    #[arg(long, value_parser = parse_toleration)]
+    #[arg(
+        help = "specify the toleration of pod in the format of 'KEY=VALUE:EFFECT', or 'KEY:EFFECT' to tolerate any value, the effect can be omitted to tolerate all effects, can be specified with multiple times"
+    )]
+    pub toleration: Vec<Toleration>,
+    #[arg(long, value_parser = parse_node_affinity)]
+    #[arg(
+        help = "specify the required node affinity in the format of 'KEY:OP:VALUE1,VALUE2', OP is one of 'In', 'NotIn', 'Exists' and 'DoesNotExist', the values are omitted for the latter two, can be specified with multiple times"
+    )]
+    pub node_affinity: Vec<NodeSelectorRequirement>,
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(i64).range(0..))]
+    #[arg(
+        help = "keep pod bound to the node for the seconds after it becomes not ready, instead of the default eviction by kubernetes"
+    )]
+    pub tolerate_not_ready: Option<i64>,
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(i64).range(0..))]
+    #[arg(
+        help = "keep pod bound to the node for the seconds after it becomes unreachable, instead of the default eviction by kubernetes"
+    )]
+    pub tolerate_unreachable: Option<i64>,
+    #[arg(long)]
+    #[arg(help = "run pod in privileged mode")]
+    pub privileged: bool,
+    #[arg(long)]
+    #[arg(
+        help = "require the container to run as non-root user, images running as root fail to start"
+    )]
+    pub run_as_non_root: bool,
+    #[arg(long)]
+    #[arg(help = "specify the uid to run the container process with")]
+    pub run_as_user: Option<i64>,
+    #[arg(long)]
+    #[arg(help = "specify the gid to run the container process with")]
+    pub run_as_group: Option<i64>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the supplemental group of pod, which owns the mounted volumes that support ownership management"
+    )]
+    pub fs_group: Option<i64>,
+    #[arg(long)]
+    #[arg(
+        help = "mount the root filesystem of container as read-only, a writable emptyDir is mounted to /tmp unless it's already mounted"
+    )]
+    pub read_only_root_fs: bool,
+    #[arg(long, visible_alias = "emptydir", value_parser = parse_emptydir_mount)]
+    #[arg(
+        help = "mount an emptyDir scratch volume in <mountPath>[:<sizeLimit>[:<medium>]] form, can be specified with multiple times"
+    )]
+    pub emptydir_mount: Vec<EmptyDirMount>,
+    #[arg(long)]
+    #[arg(
+        help = "wait the additional volume to be bound before creating pod within the timeout seconds, don't use it with storage class of WaitForFirstConsumer binding mode"
+    )]
+    pub volume_timeout: Option<u64>,
+    #[arg(long)]
+    #[arg(help = "disallow the container process to gain more privileges than its parent")]
+    pub no_privilege_escalation: bool,
+    #[arg(long, value_parser = parse_capability)]
+    #[arg(
+        help = "add the linux capability to container, e.g. SYS_ADMIN, which avoids running in privileged mode, can be specified with multiple times"
+    )]
+    pub cap_add: Vec<String>,
+    #[arg(long, value_parser = parse_capability)]
+    #[arg(
+        help = "drop the linux capability from container, 'ALL' drops every capability, can be specified with multiple times"
+    )]
+    pub cap_drop: Vec<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the additional labels for pod, pvc and service resources in the format of 'NAME=VALUE', can be specified with multiple times"
+    )]
+    pub additional_labels: Vec<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the labels only for pod resource in the format of 'NAME=VALUE', can be specified with multiple times"
+    )]
+    pub pod_label: Vec<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the labels only for service resource in the format of 'NAME=VALUE', can be specified with multiple times"
+    )]
+    pub service_label: Vec<String>,
+    #[arg(long, value_parser = parse_annotation)]
+    #[arg(
+        help = "specify the annotation of pod resource in the format of 'NAME=VALUE', the value may contain '=', can be specified with multiple times"
+    )]
+    pub annotation: Vec<(String, String)>,
+    #[arg(long)]
+    #[arg(
+        help = "stamp annotations of the requested image, cpu and memory requests and limits onto pod, so that the pod documents what it was created with"
+    )]
+    pub pod_info_annotations: bool,
+    #[arg(long, visible_alias = "pvc-label")]
+    #[arg(
+        help = "specify the labels only for pvc resource in the format of 'NAME=VALUE', can be specified with multiple times"
+    )]
+    pub additional_pvc_labels: Vec<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the additional persistent volume size, use in group(additional_volume_size, additional_volume_class, additional_volume_mount_path)."
+    )]
+    pub additional_volume_size: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the storage limit of additional persistent volume, which should not be less than the volume size"
+    )]
+    pub volume_limit: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the additional persistent volume class, use in group(additional_volume_size, additional_volume_class, additional_volume_mount_path)."
+    )]
+    pub additional_volume_class: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify mount point for persistent volume, use in group(additional_volume_size, additional_volume_class, additional_volume_mount_path)."
+    )]
+    pub additional_volume_mount_path: Option<String>,
+    #[arg(long, value_enum, default_value_t = AccessMode::ReadWriteOnce)]
+    #[arg(
+        help = "specify the access mode of additional persistent volume, 'ReadWriteMany' shares the volume between pods on different nodes"
+    )]
+    pub additional_volume_access_mode: AccessMode,
+    #[arg(long)]
+    #[arg(
+        help = "specify the pre-provisioned persistent volume bound by the additional persistent volume claim, which replaces the additional volume class"
+    )]
+    pub volume_name_static: Option<String>,
+    #[arg(long, requires = "volume_name_static")]
+    #[arg(
+        help = "allow the additional volume class with static volume name, the class must match the one of the persistent volume"
+    )]
+    pub allow_static_volume_class: bool,
+    #[arg(long)]
+    #[arg(
+        help = "mount the pre-created persistent volume claim at the additional volume mount path instead of creating one, the claim is never deleted"
+    )]
+    pub existing_volume_claim: Option<String>,
+    #[arg(long, required = false)]
+    #[arg(help = "just dry run and print the create resource in json")]
+    pub dry_run: bool,
+    #[arg(long, requires = "dry_run")]
+    #[arg(
+        help = "validate the generated manifests strictly on dry run, such as enum values, names and quantities, all errors are reported at once"
+    )]
+    pub strict: bool,
+    #[arg(long)]
+    #[arg(help = "print the created resource in yaml to stderr before creating them")]
+    pub show_yaml: bool,
+    #[arg(long)]
+    #[arg(help = "print the elapsed time from pod creation to readiness to stderr")]
+    pub timings: bool,
+    #[arg(long, value_parser=parse_volume_mount)]
+    #[arg(
+        help = "specify secret in <mountPath>:<name>:<subPath> form, or <mountPath>:<name> to mount the whole secret"
+    )]
+    pub secret: Option<VolumeMount>,
+    #[arg(long, value_parser=parse_configmap_mount)]
+    #[arg(
+        help = "specify configmap in <mountPath>:<name>:<subPath> form, or <mountPath>:<name> to mount the whole configmap"
+    )]
+    pub configmap: Option<VolumeMount>,
+    #[arg(long, value_parser=parse_secret_source)]
+    #[arg(
+        help = "create secret from local files in <name>:<key>=<file>,... form before the pod, it's named <pod>-<name> and mounted to /run/secrets/<name> unless --secret refers to it, and deleted together with the pod"
+    )]
+    pub create_secret: Option<SecretSource>,
+    #[arg(long)]
+    #[arg(
+        help = "create kubernetes.io/dockerconfigjson secret from the docker config json file and use it to pull the image, it's deleted together with the pod"
+    )]
+    pub image_pull_secret_from_file: Option<PathBuf>,
+    #[arg(long, value_parser = parse_env)]
+    #[arg(
+        help = "specify the environment variable of container in the format of 'NAME=VALUE', can be specified with multiple times"
+    )]
+    pub env: Vec<(String, String)>,
+    #[arg(long, allow_hyphen_values = true)]
+    #[arg(
+        help = "override the entrypoint of image, can be specified with multiple times for each element of the command"
+    )]
+    pub command: Vec<String>,
+    #[arg(long = "arg", allow_hyphen_values = true)]
+    #[arg(
+        help = "specify the argument of container, can be specified with multiple times for each argument"
+    )]
+    pub args: Vec<String>,
+    #[arg(long, allow_hyphen_values = true)]
+    #[arg(
+        help = "add an init container running the command with the image of pod before the container, can be specified with multiple times for each element of the command"
+    )]
+    pub init_command: Vec<String>,
+    #[arg(long = "init-args", allow_hyphen_values = true)]
+    #[arg(
+        help = "specify the argument of init container, which is added when the init command or argument is given, can be specified with multiple times for each argument"
+    )]
+    pub init_args: Vec<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the request and limit cpu resource of init container, defaults to the resources of container"
+    )]
+    pub init_cpu: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the request and limit memory resource of init container, defaults to the resources of container"
+    )]
+    pub init_memory: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "load all keys of the secret as environment variables of container, can be specified with multiple times"
+    )]
+    pub env_from_secret: Vec<String>,
+    #[arg(long)]
+    #[arg(help = "specify the image of sidecar container, which shares the additional volume")]
+    pub sidecar_image: Option<String>,
+    #[arg(long, requires = "sidecar_image")]
+    #[arg(help = "mount the additional volume read-only into the sidecar container")]
+    pub sidecar_volume_readonly: bool,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    #[arg(
+        help = "specify the output format, 'json' prints the pod ip and all created resources on success, and an error object to stderr on failure"
+    )]
+    pub output: OutputFormat,
+    #[arg(long, value_parser = parse_ip_output, default_value = "stdout")]
+    #[arg(
+        help = "specify where the allocation result is written, 'stdout' or 'file:PATH', nothing else is ever written to stdout"
+    )]
+    pub ip_output: IpOutput,
+    #[arg(long)]
+    #[arg(help = "specify a file the allocated pod ip is additionally written to")]
+    pub ip_file: Option<PathBuf>,
+    #[arg(long, value_enum)]
+    #[arg(
+        help = "specify the restart policy of pod, the field is omitted when not specified, failed container will be restarted in place with 'OnFailure'"
+    )]
+    pub restart_policy: Option<RestartPolicy>,
+    #[arg(long, value_enum)]
+    #[arg(
+        help = "specify the preemption policy of pod, 'Never' keeps the pod from preempting lower priority pods, the field is omitted when not specified"
+    )]
+    pub preemption_policy: Option<PreemptionPolicy>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the service account of pod, the default service account of namespace is used when not specified"
+    )]
+    pub service_account: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "run pod in the network namespace of node, the dns policy is set to ClusterFirstWithHostNet to keep resolving cluster services"
+    )]
+    pub host_network: bool,
+    #[arg(long, value_enum)]
+    #[arg(
+        help = "specify the image pull policy of containers, resolved by the rules of config file or 'IfNotPresent' when not specified"
+    )]
+    pub image_pull_policy: Option<ImagePullPolicy>,
+    #[arg(long, visible_alias = "wait-condition", value_enum, default_value_t = WaitFor::Running)]
+    #[arg(
+        help = "specify the state of pod to wait for, 'ready' waits until all the readiness probes pass, 'succeeded' is used for batch pods which run to completion and have no ip address reported"
+    )]
+    pub wait_for: WaitFor,
+    #[arg(long, conflicts_with = "wait_for")]
+    #[arg(
+        help = "return the pod name right after the resources are created without waiting for the pod, failed pods are not cleaned up"
+    )]
+    pub no_wait: bool,
+    #[arg(
+        long,
+        value_name = "BOOL",
+        require_equals = true,
+        conflicts_with = "no_wait"
+    )]
+    #[arg(help = "whether to wait for the pod, '--wait=false' is the same as '--no-wait'")]
+    pub wait: Option<bool>,
+    #[arg(long)]
+    #[arg(
+        help = "copy the labels of the target namespace whose key starts with the prefix onto pod resource"
+    )]
+    pub inherit_namespace_labels: Option<String>,
+    #[arg(long, value_parser = parse_prometheus_scrape)]
+    #[arg(
+        help = "annotate pod to be scraped by prometheus in <port>[:<path>] form, path defaults to /metrics"
+    )]
+    pub prometheus_scrape: Option<PrometheusScrape>,
+    #[arg(long, group = "readiness", value_parser = parse_tcp_probe)]
+    #[arg(help = "specify the tcp readiness probe of pod in <port> form")]
+    pub readiness_tcp: Option<ProbeHandler>,
+    #[arg(long, group = "readiness", value_parser = parse_http_probe)]
+    #[arg(
+        help = "specify the http readiness probe of pod in <port>[:<path>] form, path defaults to /"
+    )]
+    pub readiness_http: Option<ProbeHandler>,
+    #[arg(long, group = "readiness", value_parser = parse_grpc_probe)]
+    #[arg(help = "specify the grpc readiness probe of pod in <port>[:<service>] form")]
+    pub readiness_grpc: Option<ProbeHandler>,
+    #[arg(long, group = "liveness", value_parser = parse_tcp_probe)]
+    #[arg(help = "specify the tcp liveness probe of pod in <port> form")]
+    pub liveness_tcp: Option<ProbeHandler>,
+    #[arg(long, group = "liveness", value_parser = parse_http_probe)]
+    #[arg(
+        help = "specify the http liveness probe of pod in <port>[:<path>] form, path defaults to /"
+    )]
+    pub liveness_http: Option<ProbeHandler>,
+    #[arg(long, group = "liveness", value_parser = parse_grpc_probe)]
+    #[arg(help = "specify the grpc liveness probe of pod in <port>[:<service>] form")]
+    pub liveness_grpc: Option<ProbeHandler>,
+    #[arg(long, group = "startup", value_parser = parse_tcp_probe)]
+    #[arg(help = "specify the tcp startup probe of pod in <port> form")]
+    pub startup_tcp: Option<ProbeHandler>,
+    #[arg(long, group = "startup", value_parser = parse_http_probe)]
+    #[arg(
+        help = "specify the http startup probe of pod in <port>[:<path>] form, path defaults to /"
+    )]
+    pub startup_http: Option<ProbeHandler>,
+    #[arg(long, group = "startup", value_parser = parse_grpc_probe)]
+    #[arg(help = "specify the grpc startup probe of pod in <port>[:<service>] form")]
+    pub startup_grpc: Option<ProbeHandler>,
+    #[arg(long)]
+    #[arg(
+        help = "tolerate the common spot/preemptible node taints and prefer spot nodes, keys can be overridden in config"
+    )]
+    pub spot: bool,
+    #[arg(long, value_enum)]
+    #[arg(
+        help = "run pod in a sandboxed runtime, privileged mode is ignored, runtime class names can be overridden in config"
+    )]
+    pub sandbox: Option<Sandbox>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the yaml file containing nodeSelector, tolerations and affinity of pod, scheduling options on the command line take precedence"
+    )]
+    pub scheduling_file: Option<PathBuf>,
+    #[arg(skip)]
+    pub config: Config,
+    #[arg(long, value_enum)]
+    #[arg(help = "specify the cpu architecture of node, merged into the node selector")]
+    pub arch: Option<Arch>,
+    #[arg(long, value_enum)]
+    #[arg(help = "specify the operating system of node, merged into the node selector")]
+    pub os: Option<Os>,
+    #[arg(long)]
+    #[arg(
+        help = "run pod on windows node, linux only options such as privileged mode are rejected"
+    )]
+    pub windows: bool,
+    #[arg(long)]
+    #[arg(help = "specify the number of gpu requested and limited for pod, 0 means no gpu")]
+    pub gpu: Option<u32>,
+    #[arg(long, value_enum, default_value_t = GpuVendor::Nvidia)]
+    #[arg(
+        help = "specify the vendor of gpu, or its resource name such as 'nvidia.com/gpu', used in group(gpu, gpu_vendor, mig_profile)"
+    )]
+    pub gpu_vendor: GpuVendor,
+    #[arg(long)]
+    #[arg(
+        help = "specify the MIG profile of nvidia gpu such as '1g.5gb', used in group(gpu, gpu_vendor, mig_profile)"
+    )]
+    pub mig_profile: Option<String>,
+    #[arg(long)]
+    #[arg(help = "specify the availability zone of node, merged into the node selector")]
+    pub zone: Option<String>,
+    #[arg(long, env = "RESALLOC_COST_CENTER")]
+    #[arg(help = "specify the cost center label of pod and pvc resource")]
+    pub cost_center: Option<String>,
+    #[arg(long)]
+    #[arg(help = "specify the label key of cost center, defaults to 'cost-center'")]
+    pub cost_label_key: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify a marker configmap owning pod and pvc resource, it's created when missing and deleting it reaps all the owned resources"
+    )]
+    pub owner_configmap: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify the key stored as pod label, the running pod with the same key is reported instead of allocating a new one"
+    )]
+    pub idempotency_key: Option<String>,
+    #[arg(long, env = "RESALLOC_TRACE_ID")]
+    #[arg(
+        help = "specify the trace id stamped as annotation of pod and pvc and included in logs, for correlating with the resalloc pipeline"
+    )]
+    pub trace_id: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "add a finalizer to pod which protects it from deletion until the allocation is finished"
+    )]
+    pub protect: bool,
+    #[arg(long)]
+    #[arg(help = "specify the grace period in seconds of deleting resources on failed allocation")]
+    pub grace_period: Option<u32>,
+    #[arg(long)]
+    #[arg(help = "delete resources immediately on failed allocation, overrides grace period")]
+    pub force: bool,
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(..=u32::MAX as u64))]
+    #[arg(
+        help = "specify the seconds given to pod to shut down gracefully, also used when deleting it, kubernetes defaults to 30 seconds"
+    )]
+    pub termination_grace_period: Option<u64>,
+    #[arg(long, value_enum, default_value_t = VolumeDeletePolicy::Delete)]
+    #[arg(help = "specify whether the pvc is deleted or retained on failed allocation")]
+    pub volume_delete_policy: VolumeDeletePolicy,
+    #[arg(long)]
+    #[arg(
+        help = "expose the port of pod with a service named after the pod, can be specified multiple times"
+    )]
+    pub expose: Vec<u16>,
+    #[arg(long)]
+    #[arg(help = "create the service of pod as a headless service")]
+    pub headless: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    spot: SpotConfig,
+    sandbox: SandboxConfig,
+    image_pull_policy: Vec<ImagePullPolicyRule>,
+}
+
+impl Config {
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        match path {
+            None => Ok(Config::default()),
+            Some(p) => {
+                let content = std::fs::read_to_string(p).map_err(|e| {
+                    anyhow!("failed to read config file {}, due to {:?}", p.display(), e)
+                })?;
+                serde_yaml::from_str(&content).map_err(|e| {
+                    categorized_error(
+                        ErrorCategory::Validation,
+                        format!("invalid config file {}, due to {}", p.display(), e),
+                    )
+                })
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct SpotConfig {
+    tolerations: Vec<Toleration>,
+    node_labels: BTreeMap<String, String>,
+}
+
+impl Default for SpotConfig {
+    fn default() -> Self {
+        //taints and labels used by GKE, AKS and EKS for spot/preemptible nodes
+        let spot_labels = [
+            ("cloud.google.com/gke-spot", "true"),
+            ("cloud.google.com/gke-preemptible", "true"),
+            ("kubernetes.azure.com/scalesetpriority", "spot"),
+            ("eks.amazonaws.com/capacityType", "SPOT"),
+        ];
+        SpotConfig {
+            tolerations: spot_labels
+                .iter()
+                .map(|(key, value)| Toleration {
+                    key: Some(key.to_string()),
+                    operator: Some("Equal".to_string()),
+                    value: Some(value.to_string()),
+                    effect: Some("NoSchedule".to_string()),
+                    toleration_seconds: None,
+                })
+                .collect(),
+            node_labels: spot_labels
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+struct Scheduling {
+    node_selector: BTreeMap<String, String>,
+    tolerations: Vec<Toleration>,
+    affinity: Option<Affinity>,
+}
+
+impl Scheduling {
+    fn load(path: &Path) -> Result<Scheduling> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            anyhow!(
+                "failed to read scheduling file {}, due to {:?}",
+                path.display(),
+                e
+            )
+        })?;
+        serde_yaml::from_str(&content).map_err(|e| {
+            categorized_error(
+                ErrorCategory::Validation,
+                format!("invalid scheduling file {}, due to {}", path.display(), e),
+            )
+        })
+    }
+}
+
+//the first rule matching all of its specified fields decides the pull policy
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ImagePullPolicyRule {
+    namespace: Option<String>,
+    image_prefix: Option<String>,
+    image_suffix: Option<String>,
+    policy: ImagePullPolicy,
+}
+
+impl ImagePullPolicyRule {
+    fn matches(&self, namespace: &str, image: &str) -> bool {
+        self.namespace.iter().all(|n| n == namespace)
+            && self
+                .image_prefix
+                .iter()
+                .all(|p| image.starts_with(p.as_str()))
+            && self
+                .image_suffix
+                .iter()
+                .all(|s| image.ends_with(s.as_str()))
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct SandboxConfig {
+    kata: SandboxPreset,
+    gvisor: SandboxPreset,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct SandboxPreset {
+    //defaults to the name of sandbox
+    runtime_class: Option<String>,
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Sandbox {
+    Kata,
+    Gvisor,
+}
+
+impl Sandbox {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Sandbox::Kata => "kata",
+            Sandbox::Gvisor => "gvisor",
+        }
+    }
+
+    fn runtime_class(&self, config: &Config) -> String {
+        self.preset(config)
+            .runtime_class
+            .clone()
+            .unwrap_or_else(|| self.as_str().to_string())
+    }
+
+    fn preset<'a>(&self, config: &'a Config) -> &'a SandboxPreset {
+        match self {
+            Sandbox::Kata => &config.sandbox.kata,
+            Sandbox::Gvisor => &config.sandbox.gvisor,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum WaitFor {
+    #[default]
+    Running,
+    Ready,
+    Succeeded,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListOutput {
+    #[default]
+    Table,
+    Wide,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum StatusOutput {
+    #[default]
+    Text,
+    Wide,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum IpOutput {
+    #[default]
+    Stdout,
+    File(PathBuf),
+}
+
+pub fn parse_ip_output(value: &str) -> Result<IpOutput, String> {
+    if value == "stdout" {
+        return Ok(IpOutput::Stdout);
+    }
+    match value.strip_prefix("file:") {
+        Some(path) if !path.is_empty() => Ok(IpOutput::File(PathBuf::from(path))),
+        _ => Err(format!(
+            "invalid ip output '{}', expected 'stdout' or 'file:PATH'",
+            value
+        )),
+    }
+}
+
+//outcome of allocation, ip is empty on dry run, without waiting and for pods waited to succeed
+#[derive(Debug, PartialEq, Eq)]
+pub struct Allocation {
+    pub name: String,
+    pub allocation_id: String,
+    pub ip: Option<String>,
+    pub exit_code: Option<i32>,
+    pub resources: Vec<CreatedResource>,
+    //time from pod creation until it's ready or succeeded, only measured for created pods
+    pub elapsed: Option<std::time::Duration>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeletedPod {
+    pub name: String,
+    pub pvc: Option<String>,
+    //pvc still referenced by other pods is kept
+    pub pvc_still_referenced: bool,
+}
+
+//pod allocated by this crate as shown by list and status
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct PodSummary {
+    pub name: String,
+    pub ip: Option<String>,
+    pub node: Option<String>,
+    pub phase: Option<String>,
+    pub age: Option<String>,
+    pub cpu: Option<String>,
+    pub memory: Option<String>,
+    pub volume: Option<String>,
+}
+
+impl PodSummary {
+    pub fn from_pod(
+        pod: &Pod,
+        now: k8s_openapi::chrono::DateTime<k8s_openapi::chrono::Utc>,
+    ) -> Self {
+        let status = pod.status.as_ref();
+        let requests = pod
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.containers.first())
+            .and_then(|c| c.resources.as_ref())
+            .and_then(|r| r.requests.as_ref());
+        let request = |resource: &str| requests.and_then(|r| r.get(resource)).map(|q| q.0.clone());
+        PodSummary {
+            name: pod.name_any(),
+            ip: status.and_then(|s| s.pod_ip.clone()),
+            node: pod.spec.as_ref().and_then(|s| s.node_name.clone()),
+            phase: status.and_then(|s| s.phase.clone()),
+            age: pod
+                .metadata
+                .creation_timestamp
+                .as_ref()
+                .map(|t| format_age((now - t.0).num_seconds())),
+            cpu: request("cpu"),
+            memory: request("memory"),
+            volume: get_pvc_name(pod),
+        }
+    }
+}
+
+//same units as kubectl, e.g. 45s, 5m, 3h, 2d
+fn format_age(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h", s / 60 / 60),
+        s => format!("{}d", s / 24 / 60 / 60),
+    }
+}
+
+//aligned table of pods, the wide one also shows age, requests and volume
+pub fn write_pod_table<W: Write>(out: &mut W, pods: &[PodSummary], wide: bool) -> Result<()> {
+    let mut headers = vec!["NAME", "IP", "NODE", "STATUS"];
+    if wide {
+        headers.extend(["AGE", "CPU", "MEMORY", "VOLUME"]);
+    }
+    let rows: Vec<Vec<String>> = pods
+        .iter()
+        .map(|p| {
+            let mut row = vec![
+                Some(&p.name),
+                p.ip.as_ref(),
+                p.node.as_ref(),
+                p.phase.as_ref(),
+            ];
+            if wide {
+                row.extend([
+                    p.age.as_ref(),
+                    p.cpu.as_ref(),
+                    p.memory.as_ref(),
+                    p.volume.as_ref(),
+                ]);
+            }
+            row.into_iter()
+                .map(|v| v.cloned().unwrap_or_else(|| "<none>".to_string()))
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            rows.iter()
+                .map(|r| r[i].len())
+                .chain([h.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let header_row: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    for row in [header_row].iter().chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(v, width)| format!("{:width$}", v, width = width))
+            .collect();
+        writeln!(out, "{}", line.join("   ").trim_end())?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct CreatedResource {
+    pub kind: String,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum VolumeDeletePolicy {
+    #[default]
+    Delete,
+    Retain,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum AccessMode {
+    #[default]
+    #[value(name = "ReadWriteOnce")]
+    ReadWriteOnce,
+    #[value(name = "ReadOnlyMany")]
+    ReadOnlyMany,
+    #[value(name = "ReadWriteMany")]
+    ReadWriteMany,
+    #[value(name = "ReadWriteOncePod")]
+    ReadWriteOncePod,
+}
+
+impl AccessMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AccessMode::ReadWriteOnce => "ReadWriteOnce",
+            AccessMode::ReadOnlyMany => "ReadOnlyMany",
+            AccessMode::ReadWriteMany => "ReadWriteMany",
+            AccessMode::ReadWriteOncePod => "ReadWriteOncePod",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RestartPolicy {
+    #[value(name = "Always")]
+    Always,
+    #[value(name = "OnFailure")]
+    OnFailure,
+    #[value(name = "Never")]
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PreemptionPolicy {
+    #[value(name = "Never")]
+    Never,
+    #[value(name = "PreemptLowerPriority")]
+    PreemptLowerPriority,
+}
+
+impl PreemptionPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PreemptionPolicy::Never => "Never",
+            PreemptionPolicy::PreemptLowerPriority => "PreemptLowerPriority",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+pub enum ImagePullPolicy {
+    #[value(name = "Always")]
+    Always,
+    #[value(name = "IfNotPresent")]
+    IfNotPresent,
+    #[value(name = "Never")]
+    Never,
+}
+
+impl ImagePullPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImagePullPolicy::Always => "Always",
+            ImagePullPolicy::IfNotPresent => "IfNotPresent",
+            ImagePullPolicy::Never => "Never",
+        }
+    }
+}
+
+impl RestartPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RestartPolicy::Always => "Always",
+            RestartPolicy::OnFailure => "OnFailure",
+            RestartPolicy::Never => "Never",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Arch {
+    Amd64,
+    Arm64,
+}
+
+impl Arch {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Arch::Amd64 => "amd64",
+            Arch::Arm64 => "arm64",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Os {
+    Linux,
+    Windows,
+}
+
+impl Os {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Os::Linux => "linux",
+            Os::Windows => "windows",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GpuVendor {
+    #[default]
+    #[value(alias = "nvidia.com/gpu")]
+    Nvidia,
+    #[value(alias = "amd.com/gpu")]
+    Amd,
+}
+
+impl GpuVendor {
+    fn resource_name(&self) -> &'static str {
+        match self {
+            GpuVendor::Nvidia => "nvidia.com/gpu",
+            GpuVendor::Amd => "amd.com/gpu",
+        }
+    }
+}
+
+//total size limit of annotations of a single object
+const MAX_ANNOTATION_SIZE: usize = 256 * 1024;
+
+//upper bound of MIG instances on a single gpu, e.g. seven 1g.5gb instances of an A100, the count
+//of whole gpus is bounded by the node capacity which is left to the scheduler
+const MAX_MIG_COUNT: u32 = 7;
+
+const TMP_VOLUME_NAME: &str = "tmp";
+
+//capabilities of linux(7), named without the CAP_ prefix as kubernetes expects
+const LINUX_CAPABILITIES: [&str; 42] = [
+    "ALL",
+    "AUDIT_CONTROL",
+    "AUDIT_READ",
+    "AUDIT_WRITE",
+    "BLOCK_SUSPEND",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "KILL",
+    "LEASE",
+    "LINUX_IMMUTABLE",
+    "MAC_ADMIN",
+    "MAC_OVERRIDE",
+    "MKNOD",
+    "NET_ADMIN",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_RAW",
+    "PERFMON",
+    "SETFCAP",
+    "SETGID",
+    "SETPCAP",
+    "SETUID",
+    "SYSLOG",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_CHROOT",
+    "SYS_MODULE",
+    "SYS_NICE",
+    "SYS_PACCT",
+    "SYS_PTRACE",
+    "SYS_RAWIO",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "WAKE_ALARM",
+];
+
+//attempts of generating pod name when it collides with an existing pod
+const MAX_NAME_ATTEMPTS: u32 = 3;
+
+//lines of container logs included in the error of failed allocation
+const DIAGNOSTIC_LOG_LINES: i64 = 20;
+
+//waiting reasons of containers which are not going to recover within the timeout
+const FAIL_FAST_REASONS: [&str; 2] = ["ImagePullBackOff", "ErrImagePull"];
+
+//crashed containers are in CrashLoopBackOff between the restarts, they are only given up once
+//restarted this many times, unless the pod is never restarted
+const CRASH_LOOP_RESTART_LIMIT: i32 = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmptyDirMount {
+    pub mount_path: String,
+    pub size_limit: Option<String>,
+    pub medium: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecretSource {
+    pub name: String,
+    pub files: Vec<(String, PathBuf)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrometheusScrape {
+    pub port: u16,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeHandler {
+    Tcp(u16),
+    Http { port: u16, path: String },
+    Grpc { port: u16, service: Option<String> },
+}
+
+impl ProbeHandler {
+    fn to_probe(&self) -> Probe {
+        match self {
+            ProbeHandler::Tcp(port) => Probe {
+                tcp_socket: Some(TCPSocketAction {
+                    port: IntOrString::Int(i32::from(*port)),
+                    host: None,
+                }),
+                ..Default::default()
+            },
+            ProbeHandler::Http { port, path } => Probe {
+                http_get: Some(HTTPGetAction {
+                    port: IntOrString::Int(i32::from(*port)),
+                    path: Some(path.clone()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ProbeHandler::Grpc { port, service } => Probe {
+                grpc: Some(GRPCAction {
+                    port: i32::from(*port),
+                    service: service.clone(),
+                }),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+fn parse_probe_port(port: &str) -> Result<u16, String> {
+    port.parse::<u16>()
+        .map_err(|_| format!("invalid probe port '{}'", port))
+}
+
+fn parse_tcp_probe(value: &str) -> Result<ProbeHandler, String> {
+    Ok(ProbeHandler::Tcp(parse_probe_port(value)?))
+}
+
+fn parse_http_probe(value: &str) -> Result<ProbeHandler, String> {
+    let (port, path) = match value.split_once(':') {
+        Some((port, path)) => (port, path),
+        None => (value, "/"),
+    };
+    if !path.starts_with('/') {
+        return Err(format!(
+            "invalid probe path '{}', it should start with '/'",
+            path
+        ));
+    }
+    Ok(ProbeHandler::Http {
+        port: parse_probe_port(port)?,
+        path: path.to_string(),
+    })
+}
+
+fn parse_grpc_probe(value: &str) -> Result<ProbeHandler, String> {
+    let (port, service) = match value.split_once(':') {
+        Some((port, service)) => (port, Some(service.to_string())),
+        None => (value, None),
+    };
+    Ok(ProbeHandler::Grpc {
+        port: parse_probe_port(port)?,
+        service,
+    })
+}
+
+fn parse_prometheus_scrape(value: &str) -> Result<PrometheusScrape, String> {
+    let (port, path) = match value.split_once(':') {
+        Some((port, path)) => (port, path),
+        None => (value, "/metrics"),
+    };
+    let port = port
+        .parse::<u16>()
+        .map_err(|_| format!("invalid prometheus scrape port '{}'", port))?;
+    if !path.starts_with('/') {
+        return Err(format!(
+            "invalid prometheus scrape path '{}', it should start with '/'",
+            path
+        ));
+    }
+    Ok(PrometheusScrape {
+        port,
+        path: path.to_string(),
+    })
+}
+
+fn parse_required_label(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!(
+            "invalid label '{}', it should be in the format of 'NAME=VALUE'",
+            value
+        )),
+    }
+}
+
+fn parse_duration(value: &str) -> Result<std::time::Duration, String> {
+    let (number, unit) =
+        value.split_at(value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len());
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+    match number.parse::<u64>() {
+        Ok(number) if multiplier > 0 => Ok(std::time::Duration::from_secs(number * multiplier)),
+        _ => Err(format!(
+            "invalid duration '{}', it should be a number followed by one of 's', 'm', 'h' and 'd'",
+            value
+        )),
+    }
+}
+
+fn parse_emptydir_mount(value: &str) -> Result<EmptyDirMount, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() > 3 || parts[0].is_empty() {
+        return Err(format!(
+            "invalid emptyDir '{}', it should be in the format of '<mountPath>[:<sizeLimit>[:<medium>]]'",
+            value
+        ));
+    }
+    let optional = |index: usize| {
+        parts
+            .get(index)
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_string())
+    };
+    let medium = optional(2);
+    if let Some(ref m) = medium {
+        if m != "Memory" && !m.starts_with("HugePages") {
+            return Err(format!(
+                "invalid emptyDir medium '{}', it should be 'Memory' or 'HugePages[-<size>]'",
+                m
+            ));
+        }
+    }
+    Ok(EmptyDirMount {
+        mount_path: parts[0].to_string(),
+        size_limit: optional(1),
+        medium,
+    })
+}
+
+fn parse_node_affinity(value: &str) -> Result<NodeSelectorRequirement, String> {
+    let parts: Vec<&str> = value.splitn(3, ':').collect();
+    if parts.len() < 2 || parts[0].is_empty() {
+        return Err(format!(
+            "invalid node affinity '{}', it should be in the format of 'KEY:OP[:VALUE1,VALUE2]'",
+            value
+        ));
+    }
+    let values: Vec<String> = parts
+        .get(2)
+        .into_iter()
+        .flat_map(|v| v.split(','))
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .collect();
+    match (parts[1], values.is_empty()) {
+        ("In" | "NotIn", false) | ("Exists" | "DoesNotExist", true) => {}
+        ("In" | "NotIn", true) => {
+            return Err(format!(
+                "node affinity operator '{}' requires at least one value",
+                parts[1]
+            ))
+        }
+        ("Exists" | "DoesNotExist", false) => {
+            return Err(format!(
+                "node affinity operator '{}' doesn't accept values",
+                parts[1]
+            ))
+        }
+        (op, _) => {
+            return Err(format!(
+                "invalid node affinity operator '{}', it should be one of 'In', 'NotIn', 'Exists' and 'DoesNotExist'",
+                op
+            ))
+        }
+    }
+    Ok(NodeSelectorRequirement {
+        key: parts[0].to_string(),
+        operator: parts[1].to_string(),
+        values: (!values.is_empty()).then_some(values),
+    })
+}
+
+fn parse_capability(value: &str) -> Result<String, String> {
+    let name = value.trim_start_matches("CAP_");
+    match LINUX_CAPABILITIES.contains(&name) {
+        true => Ok(name.to_string()),
+        false => Err(format!(
+            "unknown capability '{}', it should be one of {}",
+            value,
+            LINUX_CAPABILITIES.join(", ")
+        )),
+    }
+}
+
+fn parse_toleration(value: &str) -> Result<Toleration, String> {
+    let (pair, effect) = match value.rsplit_once(':') {
+        Some((pair, effect)) => (pair, Some(effect)),
+        None => (value, None),
+    };
+    if let Some(effect) = effect {
+        if !["NoSchedule", "PreferNoSchedule", "NoExecute"].contains(&effect) {
+            return Err(format!(
+                "invalid toleration effect '{}', it should be one of 'NoSchedule', 'PreferNoSchedule' and 'NoExecute'",
+                effect
+            ));
+        }
+    }
+    //key only toleration matches any value of the taint
+    let (key, operator, toleration_value) = match pair.split_once('=') {
+        Some((key, v)) => (key, "Equal", Some(v.to_string())),
+        None => (pair, "Exists", None),
+    };
+    if key.is_empty() {
+        return Err(format!(
+            "invalid toleration '{}', it should be in the format of 'KEY[=VALUE][:EFFECT]'",
+            value
+        ));
+    }
+    Ok(Toleration {
+        key: Some(key.to_string()),
+        operator: Some(operator.to_string()),
+        value: toleration_value,
+        effect: effect.map(|e| e.to_string()),
+        toleration_seconds: None,
+    })
+}
+
+fn parse_annotation(value: &str) -> Result<(String, String), String> {
+    //only split on the first '=', json values are kept intact
+    match value.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!(
+            "invalid annotation '{}', it should be in the format of 'NAME=VALUE'",
+            value
+        )),
+    }
+}
+
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    //only split on the first '=', the value is kept intact
+    match value.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!(
+            "invalid environment variable '{}', it should be in the format of 'NAME=VALUE'",
+            value
+        )),
+    }
+}
+
+fn parse_secret_source(value: &str) -> Result<SecretSource, String> {
+    let invalid = || {
+        format!(
+            "invalid secret source '{}', it should be in the format of '<name>:<key>=<file>,...'",
+            value
+        )
+    };
+    let (name, files) = value.split_once(':').ok_or_else(invalid)?;
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    let files = files
+        .split(',')
+        .map(|file| match file.split_once('=') {
+            Some((key, path)) if !key.is_empty() && !path.is_empty() => {
+                Ok((key.to_string(), PathBuf::from(path)))
+            }
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(SecretSource {
+        name: name.to_string(),
+        files,
+    })
+}
+
+fn parse_volume_mount(value: &str) -> Result<VolumeMount, String> {
+    parse_object_mount("secret", value)
+}
+
+fn parse_configmap_mount(value: &str) -> Result<VolumeMount, String> {
+    parse_object_mount("configmap", value)
+}
+
+fn parse_object_mount(kind: &str, value: &str) -> Result<VolumeMount, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+
+    //the sub path is optional, without it the whole object is mounted as a directory
+    if !(2..=3).contains(&parts.len()) || parts.iter().any(|p| p.is_empty()) {
+        return Err(format!(
+            "invalid {} '{}', it should be in the format of '<mountPath>:<name>[:<subPath>]'",
+            kind, value
+        ));
+    }
+
+    Ok(VolumeMount {
+        mount_path: parts[0].to_string(),
+        name: parts[1].to_string(),
+        sub_path: parts.get(2).map(|p| p.to_string()),
+        mount_propagation: Default::default(),
+        sub_path_expr: Default::default(),
+        read_only: Default::default(),
+    })
+}
+
+#[derive(Args, Default)]
+pub struct DeallocationRequest {
+    #[arg(long, required_unless_present_any = ["allocation_id", "uuid"])]
+    #[arg(help = "specify ip address of pod to delete.")]
+    #[arg(env = "RESALLOC_NAME")]
+    pub name: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify allocation id of pod to delete, which is reported on creation, takes precedence over name"
+    )]
+    pub allocation_id: Option<String>,
+    #[arg(long)]
+    #[arg(
+        help = "specify uuid or full name of pod to delete, which works even if pod has no ip address, takes precedence over allocation id and name"
+    )]
+    pub uuid: Option<String>,
+    #[arg(long, requires = "name", conflicts_with_all = ["allocation_id", "uuid"])]
+    #[arg(
+        help = "treat name as the name of pod instead of its ip address, which works even if pod has no ip address"
+    )]
+    pub by_name: bool,
+    #[arg(long)]
+    #[arg(help = "wait until the pod is actually removed")]
+    pub wait: bool,
+    #[arg(long, default_value_t = 60)]
+    #[arg(help = "timeout in seconds for waiting pod to be removed, used with wait")]
+    pub timeout: u64,
+    #[arg(long)]
+    #[arg(help = "print the pods and pvcs which would be deleted without deleting them")]
+    pub dry_run: bool,
+    #[arg(long, default_value_t = 3)]
+    #[arg(
+        help = "specify how many times to list pods again when none is found, as the ip address may not be indexed yet"
+    )]
+    pub retry: u32,
+    #[arg(long, default_value_t = 500)]
+    #[arg(
+        help = "specify the initial interval in milliseconds between retries, doubled on every retry"
+    )]
+    pub retry_interval: u64,
+    #[arg(long, value_parser = parse_required_label)]
+    #[arg(
+        help = "only delete pods having all the labels in the format of 'NAME=VALUE', can be specified with multiple times"
+    )]
+    pub require_labels: Vec<(String, String)>,
+}
+
+#[derive(Args, Default)]
+pub struct StatusRequest {
+    #[arg(long)]
+    #[arg(help = "specify ip address of pod to query.")]
+    #[arg(env = "RESALLOC_NAME")]
+    pub name: String,
+    #[arg(long, value_enum, default_value_t = StatusOutput::Text)]
+    #[arg(
+        help = "specify the output format, 'wide' prints the pod as a row of the wide list, 'json' prints the status fields as they are in kubernetes"
+    )]
+    pub output: StatusOutput,
+}
+
+#[derive(Args, Default)]
+pub struct DeleteAllRequest {
+    #[arg(long)]
+    #[arg(help = "print the pods and pvcs which would be deleted without deleting them")]
+    pub dry_run: bool,
+    #[arg(long, value_parser = parse_duration)]
+    #[arg(
+        help = "only delete pods created before the duration such as '90s', '30m', '2h' or '1d'"
+    )]
+    pub older_than: Option<std::time::Duration>,
+}
+
+#[derive(Args, Default)]
+pub struct ListRequest {
+    #[arg(long, value_enum, default_value_t = ListOutput::Table)]
+    #[arg(help = "specify the output format, 'wide' also shows age, requests and volume of pods")]
+    pub output: ListOutput,
+}
+
+#[derive(Args, Default)]
+pub struct EnsureRequest {
+    #[arg(long, required = true, value_parser = parse_required_label)]
+    #[arg(
+        help = "specify the label of pods in the pool in the format of 'NAME=VALUE', which is added to the created pods, can be specified with multiple times"
+    )]
+    pub selector: Vec<(String, String)>,
+    #[arg(long)]
+    #[arg(help = "specify the number of pods in the pool to converge to")]
+    pub replicas: u32,
+    #[command(flatten)]
+    pub allocation: AllocationRequest,
+}
+
+//pods created and deleted to converge the pool
+#[derive(Debug, Default)]
+pub struct Reconciliation {
+    pub created: Vec<Allocation>,
+    pub deleted: Vec<DeletedPod>,
+}
+
+fn pod_name_from_uuid(value: &str) -> String {
+    match value.starts_with("resalloc-") {
+        true => value.to_string(),
+        false => format!("resalloc-{}", value),
+    }
+}
+
+impl DeallocationRequest {
+    pub fn target(&self) -> String {
+        if let Some(pod_name) = self.pod_name() {
+            return format!("pod {}", pod_name);
+        }
+        match self.allocation_id {
+            Some(ref id) => format!("allocation {}", id),
+            None => format!("{} address", self.name.as_deref().unwrap_or_default()),
+        }
+    }
+
+    //pod deleted by name rather than found by ip address or allocation id
+    fn pod_name(&self) -> Option<String> {
+        match self.uuid {
+            Some(ref uuid) => Some(pod_name_from_uuid(uuid)),
+            None if self.by_name => self.name.clone(),
+            None => None,
+        }
+    }
+
+    fn list_params(&self) -> ListParams {
+        match self.allocation_id {
+            Some(ref id) => {
+                ListParams::default().labels(&format!("{}={}", ALLOCATION_ID_LABEL, id))
+            }
+            None => ListParams::default().fields(&format!(
+                "status.podIP={}",
+                self.name.as_deref().unwrap_or_default()
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    Generic,
+    Validation,
+    Auth,
+    NotFound,
+    Timeout,
+    //container of a batch pod exited with the code
+    ContainerExit(i32),
+}
+
+//exit codes of the command up to it are reserved for the categories, container exit codes are
+//offset beyond it so that they are never mistaken for a failure of the command itself
+const CONTAINER_EXIT_CODE_OFFSET: i32 = 100;
+
+impl ErrorCategory {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorCategory::Generic => 1,
+            ErrorCategory::Validation => 2,
+            ErrorCategory::Auth => 3,
+            ErrorCategory::NotFound => 4,
+            ErrorCategory::Timeout => 5,
+            //e.g. 1 => 101 and 137 => 237, codes beyond 255 would be truncated by the os
+            ErrorCategory::ContainerExit(code) => code
+                .saturating_add(CONTAINER_EXIT_CODE_OFFSET)
+                .clamp(CONTAINER_EXIT_CODE_OFFSET, 255),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Generic => "generic",
+            ErrorCategory::Validation => "validation",
+            ErrorCategory::Auth => "auth",
+            ErrorCategory::NotFound => "not_found",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::ContainerExit(_) => "container_exit",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CategorizedError {
+    category: ErrorCategory,
+    message: String,
+}
+
+impl std::fmt::Display for CategorizedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CategorizedError {}
+
+//the generated pod name is taken by an existing pod, allocation is retried with a new name
+#[derive(Debug)]
+struct NameCollision(String);
+
+impl std::fmt::Display for NameCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "pod {} already exists", self.0)
+    }
+}
+
+//error of allocation carrying the generated pod name, message and category are the ones of the error
+struct AllocationError {
+    pod: String,
+    trace_id: Option<String>,
+    error: anyhow::Error,
+}
+
+impl std::fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::fmt::Debug for AllocationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.error)
+    }
+}
+
+impl std::error::Error for AllocationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.chain().nth(1)
+    }
+}
+
+//the pod allocated when the error occurred, it may have never been created
+pub fn error_pod(err: &anyhow::Error) -> Option<&str> {
+    err.downcast_ref::<AllocationError>()
+        .map(|e| e.pod.as_str())
+}
+
+//trace id of the failed allocation, reported for correlating with the resalloc pipeline
+pub fn error_trace_id(err: &anyhow::Error) -> Option<&str> {
+    err.downcast_ref::<AllocationError>()
+        .and_then(|e| e.trace_id.as_deref())
+}
+
+//machine readable form of error, e.g. {"error": "...", "category": "timeout", "pod": "resalloc-..."}
+pub fn error_json(err: &anyhow::Error) -> serde_json::Value {
+    let mut value = serde_json::json!({
+        "error": format!("{:#}", err),
+        "category": error_category(err).as_str(),
+    });
+    if let Some(pod) = error_pod(err) {
+        value["pod"] = serde_json::Value::String(pod.to_string());
+    }
+    if let Some(trace_id) = error_trace_id(err) {
+        value["trace_id"] = serde_json::Value::String(trace_id.to_string());
+    }
+    value
+}
+
+fn is_already_exists(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(cause.downcast_ref::<kube::Error>(), Some(kube::Error::Api(resp)) if resp.code == 409)
+    })
+}
+
+pub fn categorized_error(category: ErrorCategory, message: String) -> anyhow::Error {
+    anyhow::Error::new(CategorizedError { category, message })
+}
+
+pub fn error_category(err: &anyhow::Error) -> ErrorCategory {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<AllocationError>() {
+            return error_category(&e.error);
+        }
+        if let Some(e) = cause.downcast_ref::<CategorizedError>() {
+            return e.category;
+        }
+        if let Some(e) = cause.downcast_ref::<kube::Error>() {
+            match e {
+                kube::Error::Api(resp) if resp.code == 401 || resp.code == 403 => {
+                    return ErrorCategory::Auth
+                }
+                kube::Error::Api(resp) if resp.code == 404 => return ErrorCategory::NotFound,
+                kube::Error::Auth(_) => return ErrorCategory::Auth,
+                _ => {}
+            }
+        }
+        if cause
+            .downcast_ref::<tokio::time::error::Elapsed>()
+            .is_some()
+        {
+            return ErrorCategory::Timeout;
+        }
+    }
+    ErrorCategory::Generic
+}
+
+//templates are rendered in strict mode, so that a missing variable fails instead of rendering empty yaml
+fn template_registry() -> Handlebars<'static> {
+    let mut handler = Handlebars::new();
+    handler.set_strict_mode(true);
+    handler
+}
+
+fn render_template(
+    handler: &Handlebars,
+    template: &str,
+    attribute: &BTreeMap<&str, String>,
+) -> Result<String> {
+    handler
+        .render(template, attribute)
+        .with_context(|| format!("failed to render template {}", template))
+}
+
+//values of the flags are rendered into the template as they are, e.g. an image tag of "a: b"
+//breaks the yaml, which is reported as a validation error rather than a panic
+fn parse_rendered<T: serde::de::DeserializeOwned>(yaml: &str, template: &str) -> Result<T> {
+    serde_yaml::from_str(yaml)
+        .map_err(|e| {
+            categorized_error(
+                ErrorCategory::Validation,
+                format!("invalid manifest, due to {}", e),
+            )
+        })
+        .with_context(|| format!("failed to parse rendered template {}", template))
+}
+
+pub async fn generate_pvc_resource(
+    add_command: &AllocationRequest,
+    namespace: &str,
+    pvc_name: &str,
+) -> Result<PersistentVolumeClaim> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("pvc_template", RAW_PVC)
+        .unwrap();
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    let volume_size = add_command.additional_volume_size.clone().unwrap();
+    attribute.insert("name", pvc_name.to_string());
+    attribute.insert("namespace", namespace.to_string());
+    attribute.insert("size", volume_size);
+    attribute.insert(
+        "access_mode",
+        add_command
+            .additional_volume_access_mode
+            .as_str()
+            .to_string(),
+    );
+    //static volume is bound without class unless allowed, the empty class skips the default one
+    if let Some(ref volume_class) = add_command.additional_volume_class {
+        attribute.insert("class", volume_class.to_string());
+    }
+    if let Some(ref volume_name) = add_command.volume_name_static {
+        attribute.insert("volume_name", volume_name.to_string());
+    }
+    if let Some(ref limit) = add_command.volume_limit {
+        attribute.insert("limit", limit.to_string());
+    }
+    let yaml = render_template(&handler, "pvc_template", &attribute)?;
+    let mut pvc: PersistentVolumeClaim = parse_rendered(&yaml, "pvc_template")?;
+
+    //add labels
+    add_labels(&mut pvc.metadata, &add_command.additional_labels);
+    add_labels(&mut pvc.metadata, &add_command.additional_pvc_labels);
+    add_cost_center_label(add_command, &mut pvc.metadata);
+    if let Some(ref trace_id) = add_command.trace_id {
+        stamp_trace_id(&mut pvc.metadata, trace_id);
+    }
+    validate_metadata_keys(&pvc.metadata)?;
+    Ok(pvc)
+}
+
+fn qualified_name_error(key: &str) -> Option<String> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+    if let Some(prefix) = prefix {
+        if prefix.is_empty() || prefix.len() > 253 {
+            return Some("prefix must be 1 to 253 characters".to_string());
+        }
+        //prefix must be a DNS-1123 subdomain
+        for label in prefix.split('.') {
+            if label.is_empty()
+                || !label.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+                || !label.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+                || !label
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            {
+                return Some(format!(
+                    "prefix '{}' must be a lowercase DNS subdomain",
+                    prefix
+                ));
+            }
+        }
+    }
+    if name.is_empty() || name.len() > 63 {
+        return Some("name must be 1 to 63 characters".to_string());
+    }
+    if !name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        || !name.ends_with(|c: char| c.is_ascii_alphanumeric())
+    {
+        return Some("name must start and end with an alphanumeric character".to_string());
+    }
+    if !name.chars().all(is_name_char) {
+        return Some("name must consist of alphanumeric characters, '-', '_' or '.'".to_string());
+    }
+    None
+}
+
+fn validate_qualified_name(kind: &str, key: &str) -> Result<()> {
+    match qualified_name_error(key) {
+        Some(reason) => Err(categorized_error(
+            ErrorCategory::Validation,
+            format!("invalid {} key '{}', {}", kind, key, reason),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn validate_metadata_keys(metadata: &ObjectMeta) -> Result<()> {
+    for key in metadata.labels.iter().flatten().map(|(k, _)| k) {
+        validate_qualified_name("label", key)?;
+    }
+    for key in metadata.annotations.iter().flatten().map(|(k, _)| k) {
+        validate_qualified_name("annotation", key)?;
+    }
+    validate_annotation_size(metadata)
+}
+
+fn validate_annotation_size(metadata: &ObjectMeta) -> Result<()> {
+    //kubernetes counts both keys and values towards the limit
+    let total: usize = metadata
+        .annotations
+        .iter()
+        .flatten()
+        .map(|(k, v)| k.len() + v.len())
+        .sum();
+    if total > MAX_ANNOTATION_SIZE {
+        let largest = metadata
+            .annotations
+            .iter()
+            .flatten()
+            .max_by_key(|(k, v)| k.len() + v.len())
+            .map(|(k, _)| k.as_str())
+            .unwrap_or_default();
+        return Err(categorized_error(
+            ErrorCategory::Validation,
+            format!(
+                "total size of annotations {} bytes exceeds the limit of {} bytes, the largest one is '{}'",
+                total, MAX_ANNOTATION_SIZE, largest
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn add_cost_center_label(add_command: &AllocationRequest, metadata: &mut ObjectMeta) {
+    if let Some(ref cost_center) = add_command.cost_center {
+        let key = add_command
+            .cost_label_key
+            .as_deref()
+            .unwrap_or(DEFAULT_COST_LABEL_KEY);
+        metadata
+            .labels
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.to_string(), cost_center.clone());
+    }
+}
+
+fn generate_volume_str(claim_name: &str, volume_name: &str) -> Result<String> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("vol_template", RAW_VOLUME)
+        .unwrap();
+
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("claim_name", claim_name.to_string());
+    attribute.insert("volume_name", volume_name.to_string());
+
+    render_template(&handler, "vol_template", &attribute)
+}
+
+fn generate_volume_emptydir_str(volume_name: &str, emptydir: &EmptyDirMount) -> Result<String> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("vol_emptydir_template", RAW_EMPTYDIR_VOLUME)
+        .unwrap();
+    handler.register_escape_fn(no_escape);
+
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("volume_name", volume_name.to_string());
+    attribute.insert(
+        "size_limit",
+        emptydir.size_limit.as_deref().unwrap_or("null").to_string(),
+    );
+    attribute.insert(
+        "medium",
+        emptydir.medium.as_deref().unwrap_or("null").to_string(),
+    );
+
+    render_template(&handler, "vol_emptydir_template", &attribute)
+}
+
+fn generate_volume_secret_str(volume: &str, secret: &str) -> Result<String> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("vol_secret_template", RAW_SECRET_VOLUME)
+        .unwrap();
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("volume_name", volume.to_string());
+    attribute.insert("secret_name", secret.to_string());
+
+    render_template(&handler, "vol_secret_template", &attribute)
+}
+
+fn generate_volume_configmap_str(volume: &str, configmap: &str) -> Result<String> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("vol_configmap_template", RAW_CONFIGMAP_VOLUME)
+        .unwrap();
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("volume_name", volume.to_string());
+    attribute.insert("configmap_name", configmap.to_string());
+
+    render_template(&handler, "vol_configmap_template", &attribute)
+}
+
+//configmap volumes are named with prefix, so a secret with the same name can be mounted together
+fn configmap_volume_name(configmap: &str) -> String {
+    format!("configmap-{}", configmap)
+}
+
+//also used for configmap mounts, which share the same fields
+fn generate_volume_mount_secret_str(
+    mount_path: &str,
+    sub_path: Option<&str>,
+    name: &str,
+) -> Result<String> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("vol_secret_mount_template", RAW_SECRET_MOUNT)
+        .unwrap();
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("mount_path", mount_path.to_string());
+    if let Some(sub_path) = sub_path {
+        attribute.insert("sub_path", sub_path.to_string());
+    }
+    attribute.insert("name", name.to_string());
+
+    render_template(&handler, "vol_secret_mount_template", &attribute)
+}
+
+fn generate_volume_mount_pvc_str(mount_path: &str, name: &str, read_only: bool) -> Result<String> {
+    let template = match read_only {
+        true => RAW_VOLUME_MOUNT_PVC_READONLY,
+        false => RAW_VOLUME_MOUNT_PVC,
+    };
+    let mut handler = template_registry();
+    handler
+        .register_template_string("vol_mount_template", template)
+        .unwrap();
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("mount_path", mount_path.to_string());
+    attribute.insert("volume_name", name.to_string());
+
+    render_template(&handler, "vol_mount_template", &attribute)
+}
+
+fn generate_image_pull_secrets_str(secret_name: &str) -> Result<String> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("image_pull_secrets_template", RAW_IMAGE_PULL_SECRETS)
+        .unwrap();
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("name", secret_name.to_string());
+
+    render_template(&handler, "image_pull_secrets_template", &attribute)
+}
+
+fn generate_sidecar_str(
+    image: &str,
+    image_pull_policy: ImagePullPolicy,
+    volume_mount: &str,
+) -> Result<String> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("sidecar_template", RAW_SIDECAR)
+        .unwrap();
+    handler.register_escape_fn(no_escape);
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("image", image.to_string());
+    attribute.insert("image_pull_policy", image_pull_policy.as_str().to_string());
+    attribute.insert("volume_mount", volume_mount.to_string());
+
+    render_template(&handler, "sidecar_template", &attribute)
+}
+
+fn generate_volume_mount_str(mounts: &[&str]) -> Result<String> {
+    if mounts.iter().all(|m| m.is_empty()) {
+        return Ok("".to_string());
+    }
+
+    let mut handler = template_registry();
+    handler
+        .register_template_string("vol_mount_template", RAW_VOLUME_MOUNT)
+        .unwrap();
+    handler.register_escape_fn(no_escape);
+    let content = mounts.concat();
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("content", content);
+
+    render_template(&handler, "vol_mount_template", &attribute)
+}
+
+fn generate_env_str(env: &[(String, String)]) -> Result<String> {
+    if env.is_empty() {
+        return Ok("".to_string());
+    }
+
+    let mut handler = template_registry();
+    handler
+        .register_template_string("env_template", RAW_ENV)
+        .unwrap();
+    handler
+        .register_template_string("env_var_template", RAW_ENV_VAR)
+        .unwrap();
+    handler.register_escape_fn(no_escape);
+    let mut content = String::new();
+    for (name, value) in env.iter() {
+        //quoted as json string, so that values like 'a: b' or 'true' are kept as is
+        let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+        attribute.insert("name", serde_json::to_string(name)?);
+        attribute.insert("value", serde_json::to_string(value)?);
+        content += &render_template(&handler, "env_var_template", &attribute)?;
+    }
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("content", content);
+
+    render_template(&handler, "env_template", &attribute)
+}
+
+//rendered as yaml sequence of json strings, omitted when empty so that the image default applies
+fn generate_sequence_str(key: &str, values: &[String]) -> Result<String> {
+    if values.is_empty() {
+        return Ok("".to_string());
+    }
+
+    let mut handler = template_registry();
+    handler
+        .register_template_string("sequence_template", RAW_SEQUENCE)
+        .unwrap();
+    handler
+        .register_template_string("sequence_item_template", RAW_SEQUENCE_ITEM)
+        .unwrap();
+    handler.register_escape_fn(no_escape);
+    let mut content = String::new();
+    for value in values.iter() {
+        let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+        attribute.insert("value", serde_json::to_string(value)?);
+        content += &render_template(&handler, "sequence_item_template", &attribute)?;
+    }
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("key", key.to_string());
+    attribute.insert("content", content);
+
+    render_template(&handler, "sequence_template", &attribute)
+}
+
+fn generate_env_from_str(secrets: &[String]) -> Result<String> {
+    if secrets.is_empty() {
+        return Ok("".to_string());
+    }
+
+    let mut handler = template_registry();
+    handler
+        .register_template_string("env_from_template", RAW_ENV_FROM)
+        .unwrap();
+    handler
+        .register_template_string("env_from_secret_template", RAW_ENV_FROM_SECRET)
+        .unwrap();
+    handler.register_escape_fn(no_escape);
+    let mut content = String::new();
+    for secret in secrets.iter() {
+        let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+        attribute.insert("name", secret.to_string());
+        content += &render_template(&handler, "env_from_secret_template", &attribute)?;
+    }
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    attribute.insert("content", content);
+
+    render_template(&handler, "env_from_template", &attribute)
+}
+
+//requests and limits of cpu, falls back to the cpu resource which makes them equal
+fn cpu_resources(add_command: &AllocationRequest) -> (&str, &str) {
+    (
+        add_command
+            .cpu_request
+            .as_deref()
+            .unwrap_or(&add_command.cpu_resource),
+        add_command
+            .cpu_limit
+            .as_deref()
+            .unwrap_or(&add_command.cpu_resource),
+    )
+}
+
+fn memory_resources(add_command: &AllocationRequest) -> (&str, &str) {
+    (
+        add_command
+            .memory_request
+            .as_deref()
+            .unwrap_or(&add_command.memory_resource),
+        add_command
+            .memory_limit
+            .as_deref()
+            .unwrap_or(&add_command.memory_resource),
+    )
+}
+fn image_pull_policy(
+    add_command: &AllocationRequest,
+    namespace: &str,
+    image: &str,
+) -> ImagePullPolicy {
+    if let Some(policy) = add_command.image_pull_policy {
+        return policy;
+    }
+    add_command
+        .config
+        .image_pull_policy
+        .iter()
+        .find(|rule| rule.matches(namespace, image))
+        .map_or(ImagePullPolicy::IfNotPresent, |rule| rule.policy)
+}
+
+//validated before, but it's reported rather than panicking when used as library
+fn volume_mount_path(add_command: &AllocationRequest) -> Result<&str> {
+    add_command
+        .additional_volume_mount_path
+        .as_deref()
+        .context("additional volume mount path is required to mount the volume")
+}
+
+async fn create_simple_pod_yaml(
+    add_command: &AllocationRequest,
+    namespace: &str,
+    name: &str,
+    pvc_name: &str,
+    has_volume: bool,
+) -> Result<String> {
+    let mut handler = template_registry();
+    handler
+        .register_template_string("pod_template", RAW_POD)
+        .unwrap();
+    handler.register_escape_fn(no_escape);
+
+    let mut vol: Vec<String> = Vec::new();
+    let mut vol_mount_pvc: String = Default::default();
+    let mut vol_mount_secret: String = Default::default();
+
+    if let Some(ref secret) = secret_mount(add_command) {
+        vol_mount_secret = generate_volume_mount_secret_str(
+            &secret.mount_path.to_string(),
+            secret.sub_path.as_deref(),
+            &secret.name,
+        )?;
+        //the created secret is private to the pod
+        let secret_name = match add_command.create_secret {
+            Some(ref source) => created_secret_name(name, &source.name),
+            None => secret.name.clone(),
+        };
+        vol.push(generate_volume_secret_str(&secret.name, &secret_name)?);
+    }
+    let mut vol_mount_configmap: String = Default::default();
+    if let Some(ref configmap) = add_command.configmap {
+        let volume_name = configmap_volume_name(&configmap.name);
+        vol_mount_configmap = generate_volume_mount_secret_str(
+            &configmap.mount_path,
+            configmap.sub_path.as_deref(),
+            &volume_name,
+        )?;
+        vol.push(generate_volume_configmap_str(
+            &volume_name,
+            &configmap.name,
+        )?);
+    }
+    //the existing claim is mounted the same way, but it's not labeled so that it's never deleted
+    let claim_name = match has_volume {
+        true => Some(pvc_name),
+        false => add_command.existing_volume_claim.as_deref(),
+    };
+    if let Some(claim_name) = claim_name {
+        vol.push(generate_volume_str(claim_name, claim_name)?);
+        vol_mount_pvc =
+            generate_volume_mount_pvc_str(volume_mount_path(add_command)?, claim_name, false)?;
+    }
+    let mut vol_mount_emptydir: String = Default::default();
+    for (index, emptydir) in add_command.emptydir_mount.iter().enumerate() {
+        let volume_name = format!("emptydir-{}", index);
+        vol.push(generate_volume_emptydir_str(&volume_name, emptydir)?);
+        vol_mount_emptydir +=
+            &generate_volume_mount_pvc_str(&emptydir.mount_path, &volume_name, false)?;
+    }
+
+    let vol_mount = generate_volume_mount_str(&[
+        &vol_mount_secret,
+        &vol_mount_configmap,
+        &vol_mount_pvc,
+        &vol_mount_emptydir,
+    ])?;
+
+    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
+    //optional sections are rendered empty unless specified
+    for key in ["volume", "image_pull_secrets", "sidecar"] {
+        attribute.insert(key, String::new());
+    }
+    attribute.insert("name", name.to_string());
+    attribute.insert("namespace", namespace.to_string());
+    attribute.insert("image", add_command.image_tag.clone());
+    attribute.insert(
+        "image_pull_policy",
+        image_pull_policy(add_command, namespace, &add_command.image_tag)
+            .as_str()
+            .to_string(),
+    );
+    let (cpu_request, cpu_limit) = cpu_resources(add_command);
+    let (memory_request, memory_limit) = memory_resources(add_command);
+    attribute.insert("cpu_request", cpu_request.to_string());
+    attribute.insert("cpu_limit", cpu_limit.to_string());
+    attribute.insert("memory_request", memory_request.to_string());
+    attribute.insert("memory_limit", memory_limit.to_string());
+    if let Some(ref ephemeral_storage) = add_command.ephemeral_storage {
+        attribute.insert("ephemeral_storage", ephemeral_storage.to_string());
+    }
+    attribute.insert("privileged", add_command.privileged.to_string());
+    if !vol.is_empty() {
+        let mut vols: String = RAW_VOLUME_HEADER.to_string();
+        for v in vol.iter() {
+            vols = format!("{}{}", vols, v)
+        }
+        attribute.insert("volume", vols);
+    }
+    attribute.insert("volume_mount", vol_mount);
+    attribute.insert("env", generate_env_str(&add_command.env)?);
+    attribute.insert(
+        "command",
+        generate_sequence_str("command", &add_command.command)?,
+    );
+    attribute.insert("args", generate_sequence_str("args", &add_command.args)?);
+    attribute.insert(
+        "env_from",
+        generate_env_from_str(&add_command.env_from_secret)?,
+    );
+    attribute.insert("has_volume", has_volume.to_string());
+    if add_command.image_pull_secret_from_file.is_some() {
+        attribute.insert(
+            "image_pull_secrets",
+            generate_image_pull_secrets_str(&pull_secret_name(name))?,
+        );
+    }
+    if let Some(ref sidecar_image) = add_command.sidecar_image {
+        let mut sidecar_vol_mount_pvc: String = Default::default();
+        if let Some(claim_name) = claim_name {
+            sidecar_vol_mount_pvc = generate_volume_mount_pvc_str(
+                volume_mount_path(add_command)?,
+                claim_name,
+                add_command.sidecar_volume_readonly,
+            )?;
+        }
+        attribute.insert(
+            "sidecar",
+            generate_sidecar_str(
+                sidecar_image,
+                image_pull_policy(add_command, namespace, sidecar_image),
+                &generate_volume_mount_str(&[&sidecar_vol_mount_pvc])?,
+            )?,
+        );
+    }
+    let s = render_template(&handler, "pod_template", &attribute)?;
+    debug!("render pod yaml: {}", s);
+    Ok(s)
+}
+
+//numeric value of quantity like '10Gi' or '1.5T', only used for comparing sizes
+fn quantity_value(quantity: &str) -> Option<f64> {
+    let suffixes: [(&str, f64); 13] = [
+        ("Ki", 1024f64),
+        ("Mi", 1024f64.powi(2)),
+        ("Gi", 1024f64.powi(3)),
+        ("Ti", 1024f64.powi(4)),
+        ("Pi", 1024f64.powi(5)),
+        ("Ei", 1024f64.powi(6)),
+        ("m", 1e-3),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+    let (number, multiplier) = suffixes
+        .iter()
+        .find_map(|(suffix, multiplier)| {
+            quantity
+                .strip_suffix(suffix)
+                .map(|number| (number, *multiplier))
+        })
+        .unwrap_or((quantity, 1f64));
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .map(|n| n * multiplier)
+}
+
+fn check_enum(errors: &mut Vec<String>, field: &str, value: Option<&String>, allowed: &[&str]) {
+    if let Some(value) = value {
+        if !allowed.contains(&value.as_str()) {
+            errors.push(format!(
+                "{} '{}' is invalid, it should be one of {}",
+                field,
+                value,
+                allowed.join(", ")
+            ));
+        }
+    }
+}
+
+//lowercase alphanumeric characters or '-', starts and ends with alphanumeric character
+fn is_dns_label(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 63
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !value.starts_with('-')
+        && !value.ends_with('-')
+}
+
+//checks beyond deserialization, which accepts any string for enum fields of k8s-openapi types
+pub fn validate_manifests(pod: &Pod, pvc: Option<&PersistentVolumeClaim>) -> Result<()> {
+    let mut errors: Vec<String> = Vec::new();
+
+    match pod.spec {
+        None => errors.push("pod spec is missing".to_string()),
+        Some(ref spec) => {
+            check_enum(
+                &mut errors,
+                "restartPolicy",
+                spec.restart_policy.as_ref(),
+                &["Always", "OnFailure", "Never"],
+            );
+            check_enum(
+                &mut errors,
+                "dnsPolicy",
+                spec.dns_policy.as_ref(),
+                &["ClusterFirst", "ClusterFirstWithHostNet", "Default", "None"],
+            );
+            check_enum(
+                &mut errors,
+                "preemptionPolicy",
+                spec.preemption_policy.as_ref(),
+                &["Never", "PreemptLowerPriority"],
+            );
+            for toleration in spec.tolerations.iter().flatten() {
+                check_enum(
+                    &mut errors,
+                    "toleration operator",
+                    toleration.operator.as_ref(),
+                    &["Equal", "Exists"],
+                );
+                check_enum(
+                    &mut errors,
+                    "toleration effect",
+                    toleration.effect.as_ref(),
+                    &["NoSchedule", "PreferNoSchedule", "NoExecute"],
+                );
+            }
+            if spec.containers.is_empty() {
+                errors.push("pod has no container".to_string());
+            }
+            let volumes: Vec<&str> = spec
+                .volumes
+                .iter()
+                .flatten()
+                .map(|v| v.name.as_str())
+                .collect();
+            let containers = spec
+                .init_containers
+                .iter()
+                .flatten()
+                .chain(spec.containers.iter());
+            for container in containers {
+                if !is_dns_label(&container.name) {
+                    errors.push(format!(
+                        "container name '{}' is not a valid dns label",
+                        container.name
+                    ));
+                }
+                if container.image.as_deref().unwrap_or_default().is_empty() {
+                    errors.push(format!("image of container {} is empty", container.name));
+                }
+                check_enum(
+                    &mut errors,
+                    "imagePullPolicy",
+                    container.image_pull_policy.as_ref(),
+                    &["Always", "IfNotPresent", "Never"],
+                );
+                let quantities = container
+                    .resources
+                    .iter()
+                    .flat_map(|r| r.limits.iter().flatten().chain(r.requests.iter().flatten()));
+                for (resource, quantity) in quantities {
+                    if quantity_value(&quantity.0).is_none() {
+                        errors.push(format!(
+                            "{} quantity '{}' of container {} is invalid",
+                            resource, quantity.0, container.name
+                        ));
+                    }
+                }
+                for mount in container.volume_mounts.iter().flatten() {
+                    if !volumes.contains(&mount.name.as_str()) {
+                        errors.push(format!(
+                            "volume {} mounted by container {} is not defined",
+                            mount.name, container.name
+                        ));
+                    }
+                    if !mount.mount_path.starts_with('/') {
+                        errors.push(format!(
+                            "mount path '{}' of container {} is not absolute",
+                            mount.mount_path, container.name
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(pvc) = pvc {
+        //the type of pvc resources differs between kubernetes versions, read it as json
+        let spec = serde_json::to_value(&pvc.spec)?;
+        for mode in spec["accessModes"].as_array().into_iter().flatten() {
+            let mode = mode.as_str().unwrap_or_default().to_string();
+            check_enum(
+                &mut errors,
+                "accessMode",
+                Some(&mode),
+                &[
+                    "ReadWriteOnce",
+                    "ReadOnlyMany",
+                    "ReadWriteMany",
+                    "ReadWriteOncePod",
+                ],
+            );
+        }
+        match spec["resources"]["requests"]["storage"].as_str() {
+            Some(storage) if quantity_value(storage).is_some() => {}
+            Some(storage) => errors.push(format!("storage quantity '{}' is invalid", storage)),
+            None => errors.push("storage request of pvc is missing".to_string()),
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(categorized_error(
+        ErrorCategory::Validation,
+        errors.join("; "),
+    ))
+}
+
+//reject conflicting options before anything is generated, all conflicts are reported at once
+pub fn validate_request(add_command: &AllocationRequest) -> Result<()> {
+    let mut conflicts: Vec<String> = Vec::new();
+
+    let volume_group = [
+        add_command.additional_volume_size.is_some(),
+        add_command.additional_volume_class.is_some() || add_command.volume_name_static.is_some(),
+        add_command.additional_volume_mount_path.is_some(),
+    ];
+    match add_command.existing_volume_claim {
+        Some(ref claim) => {
+            if volume_group[0] || volume_group[1] {
+                conflicts.push(format!(
+                    "existing volume claim {} can not be combined with additional volume size, class or static volume name",
+                    claim
+                ));
+            }
+            if !volume_group[2] {
+                conflicts.push(
+                    "existing volume claim requires additional volume mount path".to_string(),
+                );
+            }
+        }
+        None => {
+            if volume_group.contains(&true) && volume_group.contains(&false) {
+                conflicts.push("additional_volume_size, additional_volume_class and additional_volume_mount_path must be specified together".to_string());
+            }
+        }
+    }
+    if let Some(ref volume_name) = add_command.volume_name_static {
+        if add_command.additional_volume_class.is_some() && !add_command.allow_static_volume_class {
+            conflicts.push(format!(
+                "static volume {} can not be combined with additional volume class unless allow static volume class is specified",
+                volume_name
+            ));
+        }
+    }
+    if let Some(ref limit) = add_command.volume_limit {
+        match add_command.additional_volume_size {
+            None => conflicts.push("volume limit requires additional volume size".to_string()),
+            Some(ref size) => match (quantity_value(size), quantity_value(limit)) {
+                (Some(size_value), Some(limit_value)) if limit_value < size_value => conflicts
+                    .push(format!(
+                        "volume limit {} is less than volume size {}",
+                        limit, size
+                    )),
+                (Some(_), Some(_)) => {}
+                _ => conflicts.push(format!(
+                    "volume size {} or limit {} is not a valid quantity",
+                    size, limit
+                )),
+            },
+        }
+    }
+    if add_command.windows {
+        if add_command.privileged {
+            conflicts.push("privileged mode is not supported by windows pod".to_string());
+        }
+        if add_command.os == Some(Os::Linux) {
+            conflicts.push("windows pod can not be scheduled with linux os".to_string());
+        }
+        if !add_command.cap_add.is_empty() || !add_command.cap_drop.is_empty() {
+            conflicts.push("linux capabilities are not supported by windows pod".to_string());
+        }
+        if let Some(sandbox) = add_command.sandbox {
+            conflicts.push(format!(
+                "sandbox {} is not supported by windows pod",
+                sandbox.as_str()
+            ));
+        }
+    }
+    for (kind, quantity) in [
+        ("init cpu", &add_command.init_cpu),
+        ("init memory", &add_command.init_memory),
+        ("ephemeral storage", &add_command.ephemeral_storage),
+    ] {
+        if let Some(quantity) = quantity {
+            if quantity_value(quantity).is_none() {
+                conflicts.push(format!("{} {} is not a valid quantity", kind, quantity));
+            }
+        }
+    }
+    for (kind, (request, limit)) in [
+        ("cpu", cpu_resources(add_command)),
+        ("memory", memory_resources(add_command)),
+    ] {
+        match (quantity_value(request), quantity_value(limit)) {
+            (Some(request_value), Some(limit_value)) if request_value > limit_value => conflicts
+                .push(format!(
+                    "{} request {} is greater than {} limit {}",
+                    kind, request, kind, limit
+                )),
+            (Some(_), Some(_)) => {}
+            _ => conflicts.push(format!(
+                "{} request {} or limit {} is not a valid quantity",
+                kind, request, limit
+            )),
+        }
+    }
+    let gpu = add_command.gpu.unwrap_or_default();
+    if add_command.mig_profile.is_some() {
+        if gpu == 0 {
+            conflicts.push("mig profile requires gpu to be specified".to_string());
+        }
+        if add_command.gpu_vendor != GpuVendor::Nvidia {
+            conflicts.push("mig profile is only supported by nvidia gpu".to_string());
+        }
+    }
+    if add_command.mig_profile.is_some() && gpu > MAX_MIG_COUNT {
+        conflicts.push(format!(
+            "mig instance count {} exceeds the maximum {} of a gpu",
+            gpu, MAX_MIG_COUNT
+        ));
+    }
+    if let (Some(secret), Some(source)) = (&add_command.secret, &add_command.create_secret) {
+        if secret.name != source.name {
+            conflicts.push(format!(
+                "mounted secret {} differs from created secret {}",
+                secret.name, source.name
+            ));
+        }
+    }
+    let mut emptydir_paths: Vec<&str> = Vec::new();
+    for emptydir in add_command.emptydir_mount.iter() {
+        if emptydir_paths.contains(&emptydir.mount_path.as_str()) {
+            conflicts.push(format!(
+                "emptyDir mount path {} is specified more than once",
+                emptydir.mount_path
+            ));
+        }
+        emptydir_paths.push(&emptydir.mount_path);
+    }
+    //privileged container always escalates privileges
+    if add_command.no_privilege_escalation && add_command.privileged {
+        conflicts
+            .push("disallowing privilege escalation conflicts with privileged mode".to_string());
+    }
+    if add_command.run_as_non_root && add_command.run_as_user == Some(0) {
+        conflicts.push("running as non-root user conflicts with running as user 0".to_string());
+    }
+    //an always restarted pod never completes
+    if add_command.wait_for == WaitFor::Succeeded
+        && add_command.restart_policy == Some(RestartPolicy::Always)
+    {
+        conflicts
+            .push("waiting for succeeded pod conflicts with restart policy Always".to_string());
+    }
+
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+    Err(categorized_error(
+        ErrorCategory::Validation,
+        conflicts.join("; "),
+    ))
+}
+
+fn init_container(add_command: &AllocationRequest, pod: &Pod) -> Option<Container> {
+    if add_command.init_command.is_empty() && add_command.init_args.is_empty() {
+        return None;
+    }
+    let container = pod.spec.as_ref()?.containers.first()?;
+    let mut resources = container.resources.clone().unwrap_or_default();
+    for (key, value) in [
+        ("cpu", &add_command.init_cpu),
+        ("memory", &add_command.init_memory),
+    ] {
+        if let Some(value) = value {
+            for quantity in [&mut resources.limits, &mut resources.requests] {
+                quantity
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(key.to_string(), Quantity(value.clone()));
+            }
+        }
+    }
+    let non_empty = |values: &Vec<String>| Some(values.clone()).filter(|v| !v.is_empty());
+    Some(Container {
+        name: "init".to_string(),
+        image: container.image.clone(),
+        image_pull_policy: container.image_pull_policy.clone(),
+        command: non_empty(&add_command.init_command),
+        args: non_empty(&add_command.init_args),
+        env: container.env.clone(),
+        env_from: container.env_from.clone(),
+        volume_mounts: container.volume_mounts.clone(),
+        resources: Some(resources),
+        security_context: container.security_context.clone(),
+        ..Default::default()
+    })
+}
+
+pub async fn generate_pod_resource(
+    add_command: &AllocationRequest,
+    namespace: &str,
+    name: &str,
+    pvc_name: &str,
+    create_volume: bool,
+) -> Result<Pod> {
+    validate_request(add_command)?;
+    let yaml =
+        create_simple_pod_yaml(add_command, namespace, name, pvc_name, create_volume).await?;
+    let mut pod: Pod = parse_rendered(&yaml, "pod_template")?;
+    let scheduling = match add_command.scheduling_file {
+        Some(ref path) => Scheduling::load(path)?,
+        None => Scheduling::default(),
+    };
+
+    //add tolerations and affinity of scheduling file, the flags below extend them
+    if let Some(ref mut spec) = pod.spec {
+        if !scheduling.tolerations.is_empty() {
+            spec.tolerations = Some(scheduling.tolerations.clone());
+        }
+        spec.affinity = scheduling.affinity.clone();
+    }
+
+    //add tolerations
+    if !add_command.toleration.is_empty() {
+        if let Some(ref mut spec) = pod.spec {
+            spec.tolerations
+                .get_or_insert_with(Vec::new)
+                .extend(add_command.toleration.iter().cloned());
+        }
+    }
+    //add required node affinity, terms are ORed so every term of scheduling file gets the expressions
+    if !add_command.node_affinity.is_empty() {
+        if let Some(ref mut spec) = pod.spec {
+            let required = spec
+                .affinity
+                .get_or_insert_with(Default::default)
+                .node_affinity
+                .get_or_insert_with(Default::default)
+                .required_during_scheduling_ignored_during_execution
+                .get_or_insert_with(|| NodeSelector {
+                    node_selector_terms: Vec::new(),
+                });
+            if required.node_selector_terms.is_empty() {
+                required.node_selector_terms.push(Default::default());
+            }
+            for term in required.node_selector_terms.iter_mut() {
+                term.match_expressions
+                    .get_or_insert_with(Vec::new)
+                    .extend(add_command.node_affinity.iter().cloned());
+            }
+        }
+    }
+    let node_condition_taints = [
+        (
+            "node.kubernetes.io/not-ready",
+            add_command.tolerate_not_ready,
+        ),
+        (
+            "node.kubernetes.io/unreachable",
+            add_command.tolerate_unreachable,
+        ),
+    ];
+    for (key, seconds) in node_condition_taints {
+        if let (Some(seconds), Some(ref mut spec)) = (seconds, pod.spec.as_mut()) {
+            spec.tolerations
+                .get_or_insert_with(Vec::new)
+                .push(Toleration {
+                    key: Some(key.to_string()),
+                    operator: Some("Exists".to_string()),
+                    value: None,
+                    effect: Some("NoExecute".to_string()),
+                    toleration_seconds: Some(seconds),
+                });
+        }
+    }
+
+    //add labels
+    add_labels(&mut pod.metadata, &add_command.additional_labels);
+    add_labels(&mut pod.metadata, &add_command.pod_label);
+
+    //add annotations
+    if !add_command.annotation.is_empty() {
+        pod.metadata
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(add_command.annotation.iter().cloned());
+    }
+
+    if add_command.pod_info_annotations {
+        pod.metadata
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(pod_info_annotations(add_command));
+    }
+
+    //add node selector
+    if !add_command.node_selector.is_empty() {
+        if let Some(ref mut spec) = pod.spec {
+            let node_selector = add_command.node_selector.clone();
+            match spec.node_selector {
+                Some(_) => {
+                    return Err(anyhow!(
+                        "generated pod resource node selector should be empty"
+                    ));
+                }
+                None => {
+                    let mut container: BTreeMap<String, String> = BTreeMap::new();
+                    for s in node_selector.into_iter() {
+                        let pair: Vec<&str> = s.split('=').collect();
+                        if pair.len() == 2 {
+                            if let Some(existing) = container.get(pair[0]) {
+                                if existing != pair[1] {
+                                    return Err(categorized_error(
+                                        ErrorCategory::Validation,
+                                        format!(
+                                            "conflicting node selector '{}', both '{}' and '{}' are specified",
+                                            pair[0], existing, pair[1]
+                                        ),
+                                    ));
+                                }
+                            }
+                            container.insert(pair[0].to_string(), pair[1].to_string());
+                        }
+                    }
+                    spec.node_selector = Some(container)
+                }
+            }
+        }
+    }
+
+    //add restart policy, a restarted container keeps the pod in running phase
+    if let Some(restart_policy) = add_command.restart_policy {
+        if let Some(ref mut spec) = pod.spec {
+            spec.restart_policy = Some(restart_policy.as_str().to_string());
+        }
+    }
+
+    //add service account
+    if let Some(ref service_account) = add_command.service_account {
+        if let Some(ref mut spec) = pod.spec {
+            spec.service_account_name = Some(service_account.clone());
+        }
+    }
+
+    //add host network, the default dns policy would fall back to the dns of node
+    if add_command.host_network {
+        if let Some(ref mut spec) = pod.spec {
+            spec.host_network = Some(true);
+            spec.dns_policy = Some("ClusterFirstWithHostNet".to_string());
+        }
+    }
+
+    //add termination grace period
+    if let Some(seconds) = add_command.termination_grace_period {
+        if let Some(ref mut spec) = pod.spec {
+            spec.termination_grace_period_seconds = Some(seconds as i64);
+        }
+    }
+
+    //add preemption policy
+    if let Some(preemption_policy) = add_command.preemption_policy {
+        if let Some(ref mut spec) = pod.spec {
+            spec.preemption_policy = Some(preemption_policy.as_str().to_string());
+        }
+    }
+
+    //add prometheus scrape annotations
+    if let Some(ref scrape) = add_command.prometheus_scrape {
+        let annotations = pod.metadata.annotations.get_or_insert_with(BTreeMap::new);
+        annotations.insert("prometheus.io/scrape".to_string(), "true".to_string());
+        annotations.insert("prometheus.io/port".to_string(), scrape.port.to_string());
+        annotations.insert("prometheus.io/path".to_string(), scrape.path.clone());
+    }
+
+    //add probes of main container, clap ensures at most one handler per probe
+    if let Some(ref mut spec) = pod.spec {
+        let container = &mut spec.containers[0];
+        let readiness = [
+            &add_command.readiness_tcp,
+            &add_command.readiness_http,
+            &add_command.readiness_grpc,
+        ];
+        let liveness = [
+            &add_command.liveness_tcp,
+            &add_command.liveness_http,
+            &add_command.liveness_grpc,
+        ];
+        let startup = [
+            &add_command.startup_tcp,
+            &add_command.startup_http,
+            &add_command.startup_grpc,
+        ];
+        if let Some(handler) = readiness.into_iter().flatten().next() {
+            container.readiness_probe = Some(handler.to_probe());
+        }
+        if let Some(handler) = liveness.into_iter().flatten().next() {
+            container.liveness_probe = Some(handler.to_probe());
+        }
+        if let Some(handler) = startup.into_iter().flatten().next() {
+            container.startup_probe = Some(handler.to_probe());
+        }
+    }
+
+    //add spot tolerations and prefer spot nodes
+    if add_command.spot {
+        if let Some(ref mut spec) = pod.spec {
+            let spot = &add_command.config.spot;
+            spec.tolerations
+                .get_or_insert_with(Vec::new)
+                .extend(spot.tolerations.iter().cloned());
+            let preferred = spec
+                .affinity
+                .get_or_insert_with(Default::default)
+                .node_affinity
+                .get_or_insert_with(Default::default)
+                .preferred_during_scheduling_ignored_during_execution
+                .get_or_insert_with(Vec::new);
+            for (key, value) in spot.node_labels.iter() {
+                preferred.push(PreferredSchedulingTerm {
+                    weight: 100,
+                    preference: NodeSelectorTerm {
+                        match_expressions: Some(vec![NodeSelectorRequirement {
+                            key: key.clone(),
+                            operator: "In".to_string(),
+                            values: Some(vec![value.clone()]),
+                        }]),
+                        match_fields: None,
+                    },
+                });
+            }
+        }
+    }
+
+    //apply sandbox runtime preset
+    if let Some(sandbox) = add_command.sandbox {
+        for warning in sandbox_warnings(add_command) {
+            warn!("{}", warning);
+        }
+        let preset = sandbox.preset(&add_command.config);
+        if !preset.annotations.is_empty() {
+            pod.metadata
+                .annotations
+                .get_or_insert_with(BTreeMap::new)
+                .extend(preset.annotations.clone());
+        }
+        if let Some(ref mut spec) = pod.spec {
+            spec.runtime_class_name = Some(sandbox.runtime_class(&add_command.config));
+            for container in spec.containers.iter_mut() {
+                if let Some(ref mut security_context) = container.security_context {
+                    security_context.privileged = None;
+                }
+            }
+        }
+    }
+
+    //apply windows preset
+    if add_command.windows {
+        if let Some(ref mut spec) = pod.spec {
+            spec.os = Some(PodOS {
+                name: Os::Windows.as_str().to_string(),
+            });
+            for container in spec.containers.iter_mut() {
+                container.security_context = Some(SecurityContext {
+                    windows_options: Some(WindowsSecurityContextOptions {
+                        run_as_user_name: Some("ContainerUser".to_string()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    //apply user of container process
+    if add_command.run_as_non_root
+        || add_command.run_as_user.is_some()
+        || add_command.run_as_group.is_some()
+    {
+        for warning in security_warnings(add_command) {
+            warn!("{}", warning);
+        }
+        if let Some(ref mut spec) = pod.spec {
+            let security_context = spec.containers[0]
+                .security_context
+                .get_or_insert_with(Default::default);
+            if add_command.run_as_non_root {
+                security_context.run_as_non_root = Some(true);
+            }
+            if let Some(uid) = add_command.run_as_user {
+                security_context.run_as_user = Some(uid);
+            }
+            if let Some(gid) = add_command.run_as_group {
+                security_context.run_as_group = Some(gid);
+            }
+        }
+    }
+    if let Some(fs_group) = add_command.fs_group {
+        if let Some(ref mut spec) = pod.spec {
+            spec.security_context
+                .get_or_insert_with(PodSecurityContext::default)
+                .fs_group = Some(fs_group);
+        }
+    }
+
+    if !add_command.cap_add.is_empty() || !add_command.cap_drop.is_empty() {
+        if let Some(ref mut spec) = pod.spec {
+            spec.containers[0]
+                .security_context
+                .get_or_insert_with(Default::default)
+                .capabilities = Some(Capabilities {
+                add: (!add_command.cap_add.is_empty()).then(|| add_command.cap_add.clone()),
+                drop: (!add_command.cap_drop.is_empty()).then(|| add_command.cap_drop.clone()),
+            });
+        }
+    }
+
+    if add_command.no_privilege_escalation {
+        if let Some(ref mut spec) = pod.spec {
+            spec.containers[0]
+                .security_context
+                .get_or_insert_with(Default::default)
+                .allow_privilege_escalation = Some(false);
+        }
+    }
+
+    //apply read-only root filesystem, most images still need a writable /tmp
+    if add_command.read_only_root_fs {
+        if let Some(ref mut spec) = pod.spec {
+            let container = &mut spec.containers[0];
+            container
+                .security_context
+                .get_or_insert_with(Default::default)
+                .read_only_root_filesystem = Some(true);
+            let mounts = container.volume_mounts.get_or_insert_with(Vec::new);
+            if !mounts
+                .iter()
+                .any(|m| m.mount_path.trim_end_matches('/') == "/tmp")
+            {
+                mounts.push(VolumeMount {
+                    mount_path: "/tmp".to_string(),
+                    name: TMP_VOLUME_NAME.to_string(),
+                    ..Default::default()
+                });
+                spec.volumes.get_or_insert_with(Vec::new).push(Volume {
+                    name: TMP_VOLUME_NAME.to_string(),
+                    empty_dir: Some(EmptyDirVolumeSource::default()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    //add arch, os and zone node selector
+    let mut well_known_selector = Vec::new();
+    if let Some(arch) = add_command.arch {
+        well_known_selector.push(("kubernetes.io/arch", arch.as_str()));
+    }
+    let os = match add_command.windows {
+        true => Some(Os::Windows),
+        false => add_command.os,
+    };
+    if let Some(os) = os {
+        well_known_selector.push(("kubernetes.io/os", os.as_str()));
+    }
+    if let Some(ref zone) = add_command.zone {
+        well_known_selector.push(("topology.kubernetes.io/zone", zone.as_str()));
+    }
+    if !well_known_selector.is_empty() {
+        if let Some(ref mut spec) = pod.spec {
+            let node_selector = spec.node_selector.get_or_insert_with(BTreeMap::new);
+            for (key, value) in well_known_selector.into_iter() {
+                node_selector
+                    .entry(key.to_string())
+                    .or_insert_with(|| value.to_string());
+            }
+        }
+    }
+
+    //add init container, it shares image, environment and mounts of container to prepare volumes
+    if let Some(init_container) = init_container(add_command, &pod) {
+        if let Some(ref mut spec) = pod.spec {
+            spec.init_containers = Some(vec![init_container]);
+        }
+    }
+
+    //add gpu resource
+    let gpu = add_command.gpu.unwrap_or_default();
+    if gpu > 0 {
+        if let Some(ref mut spec) = pod.spec {
+            let resources = spec.containers[0]
+                .resources
+                .get_or_insert_with(Default::default);
+            //gpu can not be overcommitted, so the request always equals the limit
+            for quantity in [&mut resources.limits, &mut resources.requests] {
+                quantity.get_or_insert_with(BTreeMap::new).insert(
+                    add_command.gpu_vendor.resource_name().to_string(),
+                    Quantity(gpu.to_string()),
+                );
+            }
+            if let Some(ref profile) = add_command.mig_profile {
+                spec.node_selector.get_or_insert_with(BTreeMap::new).insert(
+                    "nvidia.com/mig.config".to_string(),
+                    format!("all-{}", profile),
+                );
+            }
+        }
+    }
+
+    //mark the pod so that its service is deleted along with it
+    if creates_service(add_command) {
+        pod.metadata
+            .labels
+            .get_or_insert_with(BTreeMap::new)
+            .insert("has_service".to_string(), "true".to_string());
+    }
+
+    //add idempotency key label, which finds the pod allocated by previous attempt
+    if let Some(ref key) = add_command.idempotency_key {
+        pod.metadata
+            .labels
+            .get_or_insert_with(BTreeMap::new)
+            .insert(IDEMPOTENCY_KEY_LABEL.to_string(), key.clone());
+    }
+
+    //add node selector of scheduling file unless it's specified by flags
+    if !scheduling.node_selector.is_empty() {
+        if let Some(ref mut spec) = pod.spec {
+            let node_selector = spec.node_selector.get_or_insert_with(BTreeMap::new);
+            for (key, value) in scheduling.node_selector.into_iter() {
+                node_selector.entry(key).or_insert(value);
+            }
+        }
+    }
+
+    //add cost center label
+    add_cost_center_label(add_command, &mut pod.metadata);
+    if let Some(ref trace_id) = add_command.trace_id {
+        stamp_trace_id(&mut pod.metadata, trace_id);
+    }
+
+    //validate keys of labels, annotations and node selector
+    validate_metadata_keys(&pod.metadata)?;
+    if let Some(ref spec) = pod.spec {
+        for key in spec.node_selector.iter().flatten().map(|(k, _)| k) {
+            validate_qualified_name("node selector", key)?;
+        }
+    }
+
+    Ok(pod)
+}
+
+fn sandbox_warnings(add_command: &AllocationRequest) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Some(sandbox) = add_command.sandbox {
+        if add_command.privileged {
+            warnings.push(format!(
+                "privileged mode is not supported by {} sandbox and is ignored",
+                sandbox.as_str()
+            ));
+        }
+    }
+    warnings
+}
+
+fn security_warnings(add_command: &AllocationRequest) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if add_command.run_as_non_root && add_command.privileged {
+        warnings.push(
+            "privileged container still has full access to the host even when running as non-root user"
+                .to_string(),
+        );
+    }
+    warnings
+}
+
+fn existing_allocation(pods: &[Pod]) -> Option<(&Pod, String)> {
+    pods.iter().find_map(|p| {
+        let status = p.status.as_ref()?;
+        if status.phase.as_deref() != Some("Running") || p.metadata.deletion_timestamp.is_some() {
+            return None;
+        }
+        status.pod_ip.clone().map(|ip| (p, ip))
+    })
+}
+
+fn secret_mount(add_command: &AllocationRequest) -> Option<VolumeMount> {
+    match (&add_command.secret, &add_command.create_secret) {
+        (Some(secret), _) => Some(secret.clone()),
+        (None, Some(source)) => Some(VolumeMount {
+            mount_path: format!("/run/secrets/{}", source.name),
+            name: source.name.clone(),
+            ..Default::default()
+        }),
+        (None, None) => None,
+    }
+}
+
+//concurrent allocations create secrets from the same source, so they are named after the pod
+fn created_secret_name(pod_name: &str, name: &str) -> String {
+    format!("{}-{}", pod_name, name)
+}
+
+fn generate_secret_resource(
+    add_command: &AllocationRequest,
+    namespace: &str,
+    pod_name: &str,
+) -> Result<Option<Secret>> {
+    let source = match add_command.create_secret {
+        Some(ref source) => source,
+        None => return Ok(None),
+    };
+    let mut data = BTreeMap::new();
+    for (key, path) in source.files.iter() {
+        let content = std::fs::read(path).map_err(|e| {
+            anyhow!(
+                "failed to read secret file {}, due to {:?}",
+                path.display(),
+                e
+            )
+        })?;
+        data.insert(key.clone(), k8s_openapi::ByteString(content));
+    }
+    Ok(Some(Secret {
+        metadata: ObjectMeta {
+            name: Some(created_secret_name(pod_name, &source.name)),
+            namespace: Some(namespace.to_string()),
+            labels: Some(BTreeMap::from([(
+                "app".to_string(),
+                "resalloc-kubernetes".to_string(),
+            )])),
+            ..Default::default()
+        },
+        data: Some(data),
+        type_: Some("Opaque".to_string()),
+        ..Default::default()
+    }))
+}
+
+fn pull_secret_name(pod_name: &str) -> String {
+    format!("{}-pull-secret", pod_name)
+}
+
+fn generate_pull_secret_resource(
+    add_command: &AllocationRequest,
+    namespace: &str,
+    name: &str,
+) -> Result<Option<Secret>> {
+    let path = match add_command.image_pull_secret_from_file {
+        Some(ref path) => path,
+        None => return Ok(None),
+    };
+    let content = std::fs::read(path).map_err(|e| {
+        anyhow!(
+            "failed to read docker config file {}, due to {:?}",
+            path.display(),
+            e
+        )
+    })?;
+    //reject malformed file early, otherwise the pod would fail to pull the image
+    if let Err(e) = serde_json::from_slice::<serde_json::Value>(&content) {
+        return Err(categorized_error(
+            ErrorCategory::Validation,
+            format!(
+                "docker config file {} is not valid json, due to {}",
+                path.display(),
+                e
+            ),
+        ));
+    }
+    Ok(Some(Secret {
+        metadata: ObjectMeta {
+            name: Some(pull_secret_name(name)),
+            namespace: Some(namespace.to_string()),
+            labels: Some(BTreeMap::from([(
+                "app".to_string(),
+                "resalloc-kubernetes".to_string(),
+            )])),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(
+            ".dockerconfigjson".to_string(),
+            k8s_openapi::ByteString(content),
+        )])),
+        type_: Some("kubernetes.io/dockerconfigjson".to_string()),
+        ..Default::default()
+    }))
+}
+
+async fn delete_secrets<C: Cluster>(cluster: &C, secrets: &[Secret]) -> Result<()> {
+    for s in secrets.iter() {
+        cluster
+            .delete_secret(&s.name_any(), &DeleteParams::default())
+            .await?;
+    }
+    Ok(())
+}
+
+fn pod_owner_reference(pod: &Pod) -> Result<OwnerReference> {
+    let uid = pod
+        .uid()
+        .ok_or_else(|| anyhow!("pod {} has no uid", pod.name_any()))?;
+    Ok(OwnerReference {
+        api_version: "v1".to_string(),
+        kind: "Pod".to_string(),
+        name: pod.name_any(),
+        uid,
+        ..Default::default()
+    })
+}
+
+//the caller is responsible for the lifecycle of pods which are not waited
+pub fn skips_wait(add_command: &AllocationRequest) -> bool {
+    add_command.no_wait || add_command.wait == Some(false)
+}
+
+fn creates_service(add_command: &AllocationRequest) -> bool {
+    add_command.headless || !add_command.expose.is_empty()
+}
+
+//labels in the format of 'NAME=VALUE', malformed ones are skipped
+fn add_labels(metadata: &mut ObjectMeta, labels: &[String]) {
+    for label in labels.iter() {
+        let pair: Vec<&str> = label.split('=').collect();
+        if pair.len() == 2 {
+            metadata
+                .labels
+                .get_or_insert_with(BTreeMap::new)
+                .insert(pair[0].to_string(), pair[1].to_string());
+        }
+    }
+}
+
+fn generate_service_resource(
+    add_command: &AllocationRequest,
+    namespace: &str,
+    name: &str,
+    allocation_id: &str,
+) -> Option<Service> {
+    if !creates_service(add_command) {
+        return None;
+    }
+    //the service selects the pod by its allocation id, which also links them on deletion
+    let selector = BTreeMap::from([(ALLOCATION_ID_LABEL.to_string(), allocation_id.to_string())]);
+    let mut labels = selector.clone();
+    labels.insert("app".to_string(), "resalloc-kubernetes".to_string());
+    let mut metadata = ObjectMeta {
+        name: Some(name.to_string()),
+        namespace: Some(namespace.to_string()),
+        labels: Some(labels),
+        ..Default::default()
+    };
+    add_labels(&mut metadata, &add_command.additional_labels);
+    add_labels(&mut metadata, &add_command.service_label);
+    let ports = add_command
+        .expose
+        .iter()
+        .map(|port| ServicePort {
+            name: Some(format!("port-{}", port)),
+            port: i32::from(*port),
+            target_port: Some(IntOrString::Int(i32::from(*port))),
+            protocol: Some("TCP".to_string()),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+    Some(Service {
+        metadata,
+        spec: Some(ServiceSpec {
+            selector: Some(selector),
+            ports: (!ports.is_empty()).then_some(ports),
+            cluster_ip: add_command.headless.then(|| "None".to_string()),
+            ..Default::default()
+        }),
+        status: None,
+    })
+}
+
+fn service_list_params(allocation_id: &str) -> ListParams {
+    ListParams::default().labels(&format!("{}={}", ALLOCATION_ID_LABEL, allocation_id))
+}
+
+fn linked_service_list_params(pod: &Pod) -> Option<ListParams> {
+    let labels = pod.metadata.labels.as_ref()?;
+    if labels.get("has_service").map(String::as_str) != Some("true") {
+        return None;
+    }
+    labels
+        .get(ALLOCATION_ID_LABEL)
+        .map(|id| service_list_params(id))
+}
+
+fn allocation_id(name: &str) -> String {
+    //short id taken from the uuid part of the generated name
+    name.trim_start_matches("resalloc-")
+        .chars()
+        .take(8)
+        .collect()
+}
+
+fn stamp_allocation_id(metadata: &mut ObjectMeta, allocation_id: &str) {
+    metadata
+        .labels
+        .get_or_insert_with(BTreeMap::new)
+        .insert(ALLOCATION_ID_LABEL.to_string(), allocation_id.to_string());
+}
+
+//allocation context kept on pod, keys are under resalloc namespace as other annotations of this crate
+//the resources are recorded as the container gets them, i.e. with the request and limit overrides
+fn pod_info_annotations(add_command: &AllocationRequest) -> BTreeMap<String, String> {
+    let (cpu_request, cpu_limit) = cpu_resources(add_command);
+    let (memory_request, memory_limit) = memory_resources(add_command);
+    BTreeMap::from([
+        ("resalloc.image".to_string(), add_command.image_tag.clone()),
+        ("resalloc.cpu.request".to_string(), cpu_request.to_string()),
+        ("resalloc.cpu.limit".to_string(), cpu_limit.to_string()),
+        (
+            "resalloc.memory.request".to_string(),
+            memory_request.to_string(),
+        ),
+        (
+            "resalloc.memory.limit".to_string(),
+            memory_limit.to_string(),
+        ),
+    ])
+}
+
+//e.g. ", trace id 4bf92f35...", appended to the log lines of allocation
+fn trace_note(add_command: &AllocationRequest) -> String {
+    add_command
+        .trace_id
+        .as_ref()
+        .map(|trace_id| format!(", trace id {}", trace_id))
+        .unwrap_or_default()
+}
+
+fn stamp_trace_id(metadata: &mut ObjectMeta, trace_id: &str) {
+    metadata
+        .annotations
+        .get_or_insert_with(BTreeMap::new)
+        .insert(TRACE_ID_ANNOTATION.to_string(), trace_id.to_string());
+}
+
+fn inherit_namespace_labels(pod: &mut Pod, namespace: &Namespace, prefix: &str) {
+    if let Some(ref ns_labels) = namespace.metadata.labels {
+        let labels = pod.metadata.labels.get_or_insert_with(BTreeMap::new);
+        for (key, value) in ns_labels.iter() {
+            //labels generated for the pod take precedence over the inherited ones
+            if key.starts_with(prefix) && !labels.contains_key(key) {
+                labels.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+async fn ensure_owner_configmap<C: Cluster>(cluster: &C, name: &str) -> Result<ConfigMap> {
+    let mut labels = BTreeMap::new();
+    labels.insert("app".to_string(), "resalloc-kubernetes".to_string());
+    let configmap = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            labels: Some(labels),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    cluster.get_or_create_configmap(&configmap).await
+}
+
+fn set_owner_reference(metadata: &mut ObjectMeta, configmap: &ConfigMap) -> Result<()> {
+    let uid = configmap
+        .uid()
+        .ok_or_else(|| anyhow!("owner configmap {} has no uid", configmap.name_any()))?;
+    metadata
+        .owner_references
+        .get_or_insert_with(Vec::new)
+        .push(OwnerReference {
+            api_version: "v1".to_string(),
+            kind: "ConfigMap".to_string(),
+            name: configmap.name_any(),
+            uid,
+            ..Default::default()
+        });
+    Ok(())
+}
+
+fn add_protect_finalizer(metadata: &mut ObjectMeta) {
+    let finalizers = metadata.finalizers.get_or_insert_with(Vec::new);
+    if !finalizers.iter().any(|f| f == PROTECT_FINALIZER) {
+        finalizers.push(PROTECT_FINALIZER.to_string());
+    }
+}
+
+fn without_protect_finalizer(metadata: &ObjectMeta) -> Vec<String> {
+    metadata
+        .finalizers
+        .iter()
+        .flatten()
+        .filter(|f| *f != PROTECT_FINALIZER)
+        .cloned()
+        .collect()
+}
+
+async fn release_protect_finalizer<C: Cluster>(cluster: &C, name: &str) -> Result<()> {
+    //a pod which is already gone holds no finalizer
+    let pod = match cluster.get_pod(name).await? {
+        Some(pod) => pod,
+        None => return Ok(()),
+    };
+    let finalizers = without_protect_finalizer(&pod.metadata);
+    if pod.metadata.finalizers.unwrap_or_default().len() == finalizers.len() {
+        return Ok(());
+    }
+    cluster.set_pod_finalizers(name, finalizers).await?;
+    debug!("finalizer of pod {} has been removed", name);
+    Ok(())
+}
+
+//phase and reason of a pod which is no longer running, e.g. "Failed (Evicted: low on memory)"
+fn pod_lost_state(status: &PodStatus) -> Option<String> {
+    let phase = status.phase.as_deref().unwrap_or("Unknown");
+    if phase == "Running" {
+        return None;
+    }
+    let mut state = phase.to_string();
+    if let Some(reason) = &status.reason {
+        state.push_str(&format!(" ({}", reason));
+        if let Some(message) = &status.message {
+            state.push_str(&format!(": {}", message));
+        }
+        state.push(')');
+    }
+    Some(state)
+}
+
+//compact form of conditions, e.g. 'PodScheduled=False reason=Unschedulable message="..."'
+fn format_pod_conditions(pod: &Pod) -> Option<String> {
+    let conditions = pod.status.as_ref()?.conditions.as_ref()?;
+    if conditions.is_empty() {
+        return None;
+    }
+    let formatted: Vec<String> = conditions
+        .iter()
+        .map(|c| {
+            let mut s = format!("{}={}", c.type_, c.status);
+            if let Some(ref reason) = c.reason {
+                s += &format!(" reason={}", reason);
+            }
+            if let Some(ref message) = c.message {
+                s += &format!(" message={:?}", message);
+            }
+            s
+        })
+        .collect();
+    Some(formatted.join(", "))
+}
+
+//conditions, events and container logs of the pod explain most failures, e.g. unschedulable or
+//image pull errors, appended to the error, so they must be fetched before the pod is cleaned up
+async fn pod_diagnostics_note<C: Cluster>(cluster: &C, name: &str) -> String {
+    let pod = match cluster.get_pod(name).await {
+        Ok(Some(pod)) => pod,
+        _ => return String::new(),
+    };
+    let mut note = format_pod_conditions(&pod)
+        .map(|c| format!(", pod conditions: {}", c))
+        .unwrap_or_default();
+    match cluster.list_pod_events(name).await {
+        Ok(events) if !events.is_empty() => {
+            let formatted: Vec<String> = events
+                .iter()
+                .map(|e| {
+                    let mut s = format!(
+                        "{} {}: {}",
+                        e.type_.as_deref().unwrap_or("Unknown"),
+                        e.reason.as_deref().unwrap_or("Unknown"),
+                        e.message.as_deref().unwrap_or_default()
+                    );
+                    if let Some(count) = e.count.filter(|c| *c > 1) {
+                        s += &format!(" (x{})", count);
+                    }
+                    s
+                })
+                .collect();
+            note += &format!(", pod events: {}", formatted.join("; "));
+        }
+        Ok(_) => {}
+        Err(e) => warn!("failed to list events of pod {}, due to {:?}", name, e),
+    }
+    //containers never started have no logs, which is expected
+    let spec = pod.spec.unwrap_or_default();
+    for container in spec
+        .init_containers
+        .iter()
+        .flatten()
+        .chain(&spec.containers)
+    {
+        match cluster
+            .pod_logs(name, &container.name, DIAGNOSTIC_LOG_LINES)
+            .await
+        {
+            Ok(logs) if !logs.trim().is_empty() => {
+                note += &format!(
+                    "\nlast logs of container {}:\n{}",
+                    container.name,
+                    logs.trim_end()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => debug!(
+                "failed to get logs of container {} in pod {}, due to {:?}",
+                container.name, name, e
+            ),
+        }
+    }
+    note
+}
+
+//only the pvc created by the allocation is deleted, the reused one belongs to others
+async fn cleanup<C: Cluster>(
+    add_command: &AllocationRequest,
+    cluster: &C,
+    name: &str,
+    owns_pvc: bool,
+) -> Result<()> {
+    warn!("cleaning up pod {}{}", name, trace_note(add_command));
+    //finalizer must be released, otherwise the pod can never be deleted, the resources are
+    //deleted anyway if it fails
+    if add_command.protect {
+        if let Err(e) = release_protect_finalizer(cluster, name).await {
+            warn!(
+                "failed to release finalizer of pod {}, due to {:?}",
+                name, e
+            );
+        }
+    }
+    //pods unready, delete them
+    let delete_params = cleanup_delete_params(add_command);
+    cluster.delete_pod(name, &delete_params).await?;
+    if owns_pvc && add_command.volume_delete_policy == VolumeDeletePolicy::Delete {
+        cluster.delete_pvc(name, &delete_params).await?;
+    }
+    if creates_service(add_command) {
+        delete_services(
+            cluster,
+            &service_list_params(&allocation_id(name)),
+            &delete_params,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+//finalizer of an allocated pod is released once it's handed over, the pod is cleaned up if that
+//fails so that it's never left behind with the finalizer
+async fn release_allocated_pod<C: Cluster>(
+    add_command: &AllocationRequest,
+    cluster: &C,
+    name: &str,
+    owns_pvc: bool,
+) -> Result<()> {
+    if !add_command.protect {
+        return Ok(());
+    }
+    if let Err(e) = release_protect_finalizer(cluster, name).await {
+        cleanup(add_command, cluster, name, owns_pvc).await?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+//reason of a pod which is never going to become ready, e.g. the image tag is mistyped
+fn pod_failure_reason(pod: &Pod) -> Option<String> {
+    let status = pod.status.as_ref()?;
+    let never_restarts =
+        pod.spec.as_ref().and_then(|s| s.restart_policy.as_deref()) == Some("Never");
+    let waiting = status
+        .init_container_statuses
+        .iter()
+        .flatten()
+        .chain(status.container_statuses.iter().flatten())
+        .find_map(|c| {
+            let waiting = c.state.as_ref()?.waiting.as_ref()?;
+            let reason = waiting.reason.as_deref()?;
+            let crash_looping = reason == "CrashLoopBackOff"
+                && (never_restarts || c.restart_count >= CRASH_LOOP_RESTART_LIMIT);
+            (FAIL_FAST_REASONS.contains(&reason) || crash_looping)
+                .then(|| (c.name.clone(), waiting))
+        });
+    if let Some((container, waiting)) = waiting {
+        let mut reason = format!(
+            "container {} is waiting ({}",
+            container,
+            waiting.reason.as_deref().unwrap_or_default()
+        );
+        if let Some(message) = &waiting.message {
+            reason += &format!(": {}", message);
+        }
+        return Some(reason + ")");
+    }
+    status.conditions.iter().flatten().find_map(|c| {
+        (c.type_ == "PodScheduled"
+            && c.status == "False"
+            && c.reason.as_deref() == Some("Unschedulable"))
+        .then(|| {
+            format!(
+                "pod is unschedulable: {}",
+                c.message.as_deref().unwrap_or("Unknown")
+            )
+        })
+    })
+}
+
+//the Ready condition of pod is true once all the containers are running and pass the readiness probes
+fn is_pod_ready() -> impl Condition<Pod> {
+    |obj: Option<&Pod>| {
+        obj.and_then(|p| p.status.as_ref())
+            .and_then(|s| s.conditions.as_ref())
+            .is_some_and(|conditions| {
+                conditions
+                    .iter()
+                    .any(|c| c.type_ == "Ready" && c.status == "True")
+            })
+    }
+}
+
+fn is_pod_failing() -> impl Condition<Pod> {
+    |obj: Option<&Pod>| obj.and_then(pod_failure_reason).is_some()
+}
+
+fn is_pod_completed() -> impl Condition<Pod> {
+    |obj: Option<&Pod>| obj.and_then(batch_pod_outcome).is_some()
+}
+
+fn batch_pod_outcome(pod: &Pod) -> Option<Result<i32>> {
+    let status = pod.status.as_ref()?;
+    let terminated = status.container_statuses.iter().flatten().find_map(|c| {
+        c.state
+            .as_ref()
+            .and_then(|s| s.terminated.as_ref())
+            .map(|t| (c.name.clone(), t))
+    });
+    match status.phase.as_deref()? {
+        "Succeeded" => Some(Ok(terminated.map_or(0, |(_, t)| t.exit_code))),
+        "Failed" => Some(Err(match terminated {
+            Some((container, t)) => categorized_error(
+                ErrorCategory::ContainerExit(t.exit_code),
+                format!(
+                    "pod {} failed, container {} exited with code {}: {}",
+                    pod.name_any(),
+                    container,
+                    t.exit_code,
+                    t.reason.as_deref().unwrap_or("Unknown")
+                ),
+            ),
+            None => anyhow!(
+                "pod {} failed, {}",
+                pod.name_any(),
+                status
+                    .reason
+                    .clone()
+                    .or_else(|| status.message.clone())
+                    .unwrap_or_else(|| "Unknown".to_string())
+            ),
+        })),
+        _ => None,
+    }
+}
+
+fn cleanup_delete_params(add_command: &AllocationRequest) -> DeleteParams {
+    let mut delete_params = DeleteParams::background();
+    delete_params.grace_period_seconds = match add_command.force {
+        true => Some(0),
+        false => add_command.grace_period.or(add_command
+            .termination_grace_period
+            .map(|seconds| seconds as u32)),
+    };
+    delete_params
+}
+
+//the grace period of pod is passed explicitly, so that it's respected as specified on creation
+fn pod_delete_params(pod: &Pod) -> DeleteParams {
+    DeleteParams {
+        grace_period_seconds: pod
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.termination_grace_period_seconds)
+            .and_then(|seconds| u32::try_from(seconds).ok()),
+        ..Default::default()
+    }
+}
+
+fn show_manifests<W: Write>(
+    add_command: &AllocationRequest,
+    out: &mut W,
+    pvc: Option<&PersistentVolumeClaim>,
+    pod: &Pod,
+    service: Option<&Service>,
+) -> Result<()> {
+    if !add_command.show_yaml {
+        return Ok(());
+    }
+    if let Some(p) = pvc {
+        write!(out, "---\n{}", serde_yaml::to_string(p)?)?;
+    }
+    write!(out, "---\n{}", serde_yaml::to_string(pod)?)?;
+    if let Some(s) = service {
+        write!(out, "---\n{}", serde_yaml::to_string(s)?)?;
+    }
+    Ok(())
+}
+
+fn report_timing<W: Write>(
+    add_command: &AllocationRequest,
+    out: &mut W,
+    name: &str,
+    state: &str,
+    elapsed: std::time::Duration,
+) -> Result<()> {
+    if !add_command.timings {
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "pod {} {} in {:.1}s",
+        name,
+        state,
+        elapsed.as_secs_f64()
+    )?;
+    Ok(())
+}
+
+fn created_resources(
+    pod: &Pod,
+    pvc: Option<&PersistentVolumeClaim>,
+    service: Option<&Service>,
+    secrets: &[Secret],
+) -> Vec<CreatedResource> {
+    let mut resources = Vec::new();
+    for s in secrets.iter() {
+        resources.push(CreatedResource {
+            kind: "Secret".to_string(),
+            name: s.name_any(),
+        });
+    }
+    if let Some(p) = pvc {
+        resources.push(CreatedResource {
+            kind: "PersistentVolumeClaim".to_string(),
+            name: p.name_any(),
+        });
+    }
+    resources.push(CreatedResource {
+        kind: "Pod".to_string(),
+        name: pod.name_any(),
+    });
+    if let Some(s) = service {
+        resources.push(CreatedResource {
+            kind: "Service".to_string(),
+            name: s.name_any(),
+        });
+    }
+    resources
+}
+
+//the pvc may be created by a concurrent allocation between the lookup and creation, it's usable as well,
+//returns whether the pvc is created by this attempt, the reused ones belong to others and must never be deleted
+async fn create_or_reuse_pvc<C: Cluster>(cluster: &C, pvc: &PersistentVolumeClaim) -> Result<bool> {
+    if cluster.get_pvc(&pvc.name_any()).await?.is_some() {
+        info!("pvc {} already exists, reusing it", pvc.name_any());
+        return Ok(false);
+    }
+    match cluster.create_pvc(pvc).await {
+        Ok(()) => Ok(true),
+        Err(e) if is_already_exists(&e) => {
+            info!(
+                "pvc {} has been created concurrently, reusing it",
+                pvc.name_any()
+            );
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn allocate(add_command: &AllocationRequest, namespace: &str) -> Result<Allocation> {
+    allocate_with(&KubeCluster::new(namespace), add_command, namespace).await
+}
+
+pub async fn allocate_with<C: Cluster>(
+    cluster: &C,
+    add_command: &AllocationRequest,
+    namespace: &str,
+) -> Result<Allocation> {
+    let mut attempt = 1;
+    loop {
+        let name = format!("resalloc-{}", Uuid::new_v4());
+        match allocate_named(cluster, add_command, namespace, name.clone()).await {
+            Err(e)
+                if attempt < MAX_NAME_ATTEMPTS && e.downcast_ref::<NameCollision>().is_some() =>
+            {
+                warn!("{}, retrying with a new name", e);
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(AllocationError {
+                    pod: name,
+                    trace_id: add_command.trace_id.clone(),
+                    error: e,
+                }
+                .into())
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn allocate_named<C: Cluster>(
+    cluster: &C,
+    add_command: &AllocationRequest,
+    namespace: &str,
+    name: String,
+) -> Result<Allocation> {
+    //check persistent volume argument
+    let mut additional_volume = false;
+    let mut pvc = None;
+
+    validate_request(add_command)?;
+    if add_command.additional_volume_size.is_some() {
+        additional_volume = true;
+
+        pvc = Some(generate_pvc_resource(add_command, namespace, &name).await?);
+    }
+    let mut pod =
+        generate_pod_resource(add_command, namespace, &name, &name, additional_volume).await?;
+    if add_command.protect {
+        add_protect_finalizer(&mut pod.metadata);
+    }
+    let allocation_id = allocation_id(&name);
+    stamp_allocation_id(&mut pod.metadata, &allocation_id);
+    if let Some(ref mut p) = pvc {
+        stamp_allocation_id(&mut p.metadata, &allocation_id);
+    }
+    let service = generate_service_resource(add_command, namespace, &name, &allocation_id);
+    let mut secrets: Vec<Secret> = generate_secret_resource(add_command, namespace, &name)?
+        .into_iter()
+        .chain(generate_pull_secret_resource(
+            add_command,
+            namespace,
+            &name,
+        )?)
+        .collect();
+    for s in secrets.iter_mut() {
+        stamp_allocation_id(&mut s.metadata, &allocation_id);
+    }
+    info!(
+        "allocating pod {} with allocation id {}{}",
+        &name,
+        &allocation_id,
+        trace_note(add_command)
+    );
+
+    if add_command.dry_run {
+        if add_command.strict {
+            validate_manifests(&pod, pvc.as_ref())?;
+        }
+        if pvc.is_some() {
+            info!("---");
+            info!("{}", serde_yaml::to_string(&pvc).unwrap());
+        }
+        info!("---");
+        info!("{}", serde_yaml::to_string(&pod).unwrap());
+        if service.is_some() {
+            info!("---");
+            info!("{}", serde_yaml::to_string(&service).unwrap());
+        }
+        //secret data is never printed
+        for s in secrets.iter() {
+            info!("secret {} would be created", s.name_any());
+        }
+        return Ok(Allocation {
+            resources: created_resources(&pod, pvc.as_ref(), service.as_ref(), &secrets),
+            name,
+            allocation_id,
+            ip: None,
+            exit_code: None,
+            elapsed: None,
+        });
+    }
+
+    //report the pod allocated by previous attempt with the same key
+    if let Some(ref key) = add_command.idempotency_key {
+        let list_params =
+            ListParams::default().labels(&format!("{}={}", IDEMPOTENCY_KEY_LABEL, key));
+        let existing = cluster.list_pods(&list_params).await?;
+        if let Some((existing, pod_ip)) = existing_allocation(&existing) {
+            info!(
+                "pod {} with idempotency key {} already exists",
+                existing.name_any(),
+                key
+            );
+            return Ok(Allocation {
+                name: existing.name_any(),
+                allocation_id: existing
+                    .labels()
+                    .get(ALLOCATION_ID_LABEL)
+                    .cloned()
+                    .unwrap_or_default(),
+                ip: Some(pod_ip),
+                exit_code: None,
+                resources: created_resources(existing, None, None, &[]),
+                elapsed: None,
+            });
+        }
+    }
+
+    if let Some(ref prefix) = add_command.inherit_namespace_labels {
+        let ns = cluster.get_namespace(namespace).await?;
+        inherit_namespace_labels(&mut pod, &ns, prefix);
+    }
+
+    if let Some(ref owner) = add_command.owner_configmap {
+        let configmap = ensure_owner_configmap(cluster, owner).await?;
+        set_owner_reference(&mut pod.metadata, &configmap)?;
+        if let Some(ref mut p) = pvc {
+            set_owner_reference(&mut p.metadata, &configmap)?;
+        }
+    }
+
+    show_manifests(
+        add_command,
+        &mut std::io::stderr(),
+        pvc.as_ref(),
+        &pod,
+        service.as_ref(),
+    )?;
+    let resources = created_resources(&pod, pvc.as_ref(), service.as_ref(), &secrets);
+
+    // generate secret resources, they must exist before the pod uses them
+    for (index, s) in secrets.iter().enumerate() {
+        if let Err(e) = cluster.create_secret(s).await {
+            delete_secrets(cluster, &secrets[..index]).await?;
+            return Err(e);
+        }
+    }
+    // generate pvc resource
+    let mut owns_pvc = false;
+    if let Some(p) = pvc {
+        owns_pvc = match create_or_reuse_pvc(cluster, &p).await {
+            Ok(owns_pvc) => owns_pvc,
+            Err(e) => {
+                delete_secrets(cluster, &secrets).await?;
+                return Err(e);
+            }
+        };
+        if let Some(volume_timeout) = add_command.volume_timeout {
+            if let Err(e) = wait_pvc_bound(cluster, &p, volume_timeout).await {
+                if owns_pvc {
+                    cluster
+                        .delete_pvc(&p.name_any(), &cleanup_delete_params(add_command))
+                        .await?;
+                }
+                delete_secrets(cluster, &secrets).await?;
+                return Err(e);
+            }
+        }
+    }
+    // generate pod resource
+    let started = std::time::Instant::now();
+    let created = match cluster.create_pod(&pod).await {
+        Ok(created) => created,
+        Err(e) => {
+            delete_secrets(cluster, &secrets).await?;
+            if is_already_exists(&e) {
+                //the pvc created by this attempt is unused by the existing pod, while the reused one
+                //is probably mounted by the existing pod
+                if owns_pvc {
+                    cluster
+                        .delete_pvc(&name, &cleanup_delete_params(add_command))
+                        .await?;
+                }
+                return Err(e.context(NameCollision(name)));
+            }
+            return Err(e);
+        }
+    };
+    //the secrets are garbage collected together with the pod, or deleted explicitly before owned
+    for s in secrets.iter() {
+        let owned = match pod_owner_reference(&created) {
+            Ok(owner) => {
+                cluster
+                    .set_secret_owner_references(&s.name_any(), vec![owner])
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = owned {
+            delete_secrets(cluster, &secrets).await?;
+            cleanup(add_command, cluster, &name, owns_pvc).await?;
+            return Err(e);
+        }
+    }
+    // generate service resource
+    if let Some(ref s) = service {
+        if let Err(e) = cluster.create_service(s).await {
+            cleanup(add_command, cluster, &name, owns_pvc).await?;
+            return Err(e);
+        }
+    }
+    if skips_wait(add_command) {
+        release_allocated_pod(add_command, cluster, &name, owns_pvc).await?;
+        info!("pod {} has been created without waiting", &name);
+        return Ok(Allocation {
+            name,
+            allocation_id,
+            ip: None,
+            exit_code: None,
+            resources,
+            elapsed: None,
+        });
+    }
+    if add_command.wait_for == WaitFor::Succeeded {
+        let completed = cluster.wait_pod(
+            &name,
+            Box::new(|p: Option<&Pod>| {
+                is_pod_completed().matches_object(p) || is_pod_failing().matches_object(p)
+            }),
+        );
+        let result = match tokio::time::timeout(
+            std::time::Duration::from_secs(add_command.timeout),
+            completed,
+        )
+        .await
+        {
+            Err(e) => {
+                warn!(
+                    "pod {} is not completed within {} seconds{}",
+                    &name,
+                    add_command.timeout,
+                    trace_note(add_command)
+                );
+                Err(categorized_error(
+                    ErrorCategory::Timeout,
+                    format!(
+                        "failed to waiting pod to complete, due to {:?}{}",
+                        e,
+                        pod_diagnostics_note(cluster, &name).await
+                    ),
+                ))
+            }
+            Ok(Err(e)) => Err(anyhow!("failed to waiting pod to complete, due to {:?}", e)),
+            Ok(Ok(completed)) => match completed.as_ref().and_then(pod_failure_reason) {
+                Some(reason) => Err(anyhow!(
+                    "pod {} is failed before completion, {}{}",
+                    &name,
+                    reason,
+                    pod_diagnostics_note(cluster, &name).await
+                )),
+                None => completed
+                    .as_ref()
+                    .and_then(batch_pod_outcome)
+                    .unwrap_or_else(|| Err(anyhow!("pod {} disappeared before completion", &name))),
+            },
+        };
+        match result {
+            Ok(_) => {
+                release_allocated_pod(add_command, cluster, &name, owns_pvc).await?;
+                info!("pod {} has succeeded", &name);
+            }
+            Err(_) => cleanup(add_command, cluster, &name, owns_pvc).await?,
+        }
+        let elapsed = started.elapsed();
+        let exit_code = result?;
+        report_timing(
+            add_command,
+            &mut std::io::stderr(),
+            &name,
+            "succeeded",
+            elapsed,
+        )?;
+        return Ok(Allocation {
+            name,
+            allocation_id,
+            ip: None,
+            exit_code: Some(exit_code),
+            resources,
+            elapsed: Some(elapsed),
+        });
+    }
+    //wait pod to be running or ready, the pods never going to be ready are given up early
+    let wait_ready = add_command.wait_for == WaitFor::Ready;
+    let running = cluster.wait_pod(
+        &name,
+        Box::new(move |p: Option<&Pod>| {
+            let started = match wait_ready {
+                true => is_pod_ready().matches_object(p),
+                false => is_pod_running().matches_object(p),
+            };
+            started || is_pod_failing().matches_object(p)
+        }),
+    );
+    match tokio::time::timeout(std::time::Duration::from_secs(add_command.timeout), running).await {
+        Ok(res) => match res {
+            Err(e) => {
+                let note = pod_diagnostics_note(cluster, &name).await;
+                cleanup(add_command, cluster, &name, owns_pvc).await?;
+                Err(anyhow!(
+                    "failed to creating new pod resource in kubernetes, due to {:?}{}",
+                    e,
+                    note
+                ))
+            }
+            Ok(ready) => {
+                if let Some(reason) = ready.as_ref().and_then(pod_failure_reason) {
+                    let note = pod_diagnostics_note(cluster, &name).await;
+                    cleanup(add_command, cluster, &name, owns_pvc).await?;
+                    return Err(anyhow!(
+                        "pod {} is failed to become ready, {}{}",
+                        &name,
+                        reason,
+                        note
+                    ));
+                }
+                //check pod ip address
+                match cluster.get_pod(&name).await {
+                    Err(e) => {
+                        cleanup(add_command, cluster, &name, owns_pvc).await?;
+                        Err(anyhow!(
+                            "failed to getting new pod resource in kubernetes, due to {:?}",
+                            e
+                        ))
+                    }
+                    Ok(current) => {
+                        if let Some(status) = current.and_then(|p| p.status) {
+                            //the pod can be evicted between becoming ready and being reported
+                            if let Some(state) = pod_lost_state(&status) {
+                                cleanup(add_command, cluster, &name, owns_pvc).await?;
+                                return Err(anyhow!(
+                                    "pod {} is {} after getting ip address {}",
+                                    &name,
+                                    state,
+                                    status.pod_ip.as_deref().unwrap_or("<none>")
+                                ));
+                            }
+                            if let Some(pod_ip) = status.pod_ip {
+                                let elapsed = started.elapsed();
+                                release_allocated_pod(add_command, cluster, &name, owns_pvc)
+                                    .await?;
+                                report_timing(
+                                    add_command,
+                                    &mut std::io::stderr(),
+                                    &name,
+                                    "ready",
+                                    elapsed,
+                                )?;
+                                return Ok(Allocation {
+                                    name,
+                                    allocation_id,
+                                    ip: Some(pod_ip),
+                                    exit_code: None,
+                                    resources,
+                                    elapsed: Some(elapsed),
+                                });
+                            }
+                        }
+                        cleanup(add_command, cluster, &name, owns_pvc).await?;
+                        Err(anyhow!("container ip address empty"))
+                    }
+                }
+            }
+        },
+        Err(e) => {
+            warn!(
+                "pod {} is not ready within {} seconds{}",
+                &name,
+                add_command.timeout,
+                trace_note(add_command)
+            );
+            let note = pod_diagnostics_note(cluster, &name).await;
+            cleanup(add_command, cluster, &name, owns_pvc).await?;
+            Err(categorized_error(
+                ErrorCategory::Timeout,
+                format!(
+                    "failed to creating new pod resource in kubernetes, due to {:?}{}",
+                    e, note
+                ),
+            ))
+        }
+    }
+}
+
+//creates or deletes pods of the pool selected by labels until there are exactly the replicas
+pub async fn ensure_with<C: Cluster>(
+    cluster: &C,
+    ensure_command: &EnsureRequest,
+    namespace: &str,
+) -> Result<Reconciliation> {
+    let selector: Vec<String> = ensure_command
+        .selector
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let list_params =
+        ListParams::default().labels(&format!("app=resalloc-kubernetes,{}", selector.join(",")));
+    //terminating pods are already leaving the pool
+    let mut pods: Vec<Pod> = cluster
+        .list_pods(&list_params)
+        .await?
+        .into_iter()
+        .filter(|p| p.metadata.deletion_timestamp.is_none())
+        .collect();
+    let replicas = ensure_command.replicas as usize;
+    info!(
+        "pool {} has {} pods, {} desired",
+        selector.join(","),
+        pods.len(),
+        replicas
+    );
+
+    let mut reconciliation = Reconciliation::default();
+    if pods.len() < replicas {
+        let mut allocation = ensure_command.allocation.clone();
+        allocation.pod_label.extend(selector.iter().cloned());
+        for _ in pods.len()..replicas {
+            reconciliation
+                .created
+                .push(allocate_with(cluster, &allocation, namespace).await?);
+        }
+    }
+    if pods.len() > replicas {
+        //pods not running yet are deleted first, then the youngest ones
+        pods.sort_by_key(|p| {
+            (
+                p.status.as_ref().and_then(|s| s.phase.as_deref()) == Some("Running"),
+                std::cmp::Reverse(p.metadata.creation_timestamp.clone()),
+            )
+        });
+        for p in pods.iter().take(pods.len() - replicas) {
+            let delete_command = DeallocationRequest {
+                uuid: Some(p.name_any()),
+                require_labels: ensure_command.selector.clone(),
+                ..Default::default()
+            };
+            reconciliation
+                .deleted
+                .extend(deallocate_with(cluster, &delete_command).await?);
+        }
+    }
+    Ok(reconciliation)
+}
+
+//pod allocated by this crate with the ip address
+pub async fn status_with<C: Cluster>(cluster: &C, status_command: &StatusRequest) -> Result<Pod> {
+    let list_params = ListParams::default()
+        .labels("app=resalloc-kubernetes")
+        .fields(&format!("status.podIP={}", status_command.name));
+    cluster
+        .list_pods(&list_params)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            categorized_error(
+                ErrorCategory::NotFound,
+                format!(
+                    "failed to get any pods with {} address",
+                    status_command.name
+                ),
+            )
+        })
+}
+
+//e.g. "running", "waiting (ImagePullBackOff)" or "terminated (Error, exit code 1)"
+fn format_container_state(state: Option<&ContainerState>) -> String {
+    match state {
+        Some(ContainerState {
+            waiting: Some(w), ..
+        }) => match w.reason {
+            Some(ref reason) => format!("waiting ({})", reason),
+            None => "waiting".to_string(),
+        },
+        Some(ContainerState {
+            terminated: Some(t),
+            ..
+        }) => format!(
+            "terminated ({}, exit code {})",
+            t.reason.as_deref().unwrap_or("Unknown"),
+            t.exit_code
+        ),
+        Some(ContainerState {
+            running: Some(_), ..
+        }) => "running".to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+pub fn write_pod_status<W: Write>(
+    out: &mut W,
+    status_command: &StatusRequest,
+    pod: &Pod,
+) -> Result<()> {
+    let status = pod.status.clone().unwrap_or_default();
+    if status_command.output == StatusOutput::Wide {
+        let summary = PodSummary::from_pod(pod, k8s_openapi::chrono::Utc::now());
+        return write_pod_table(out, &[summary], true);
+    }
+    if status_command.output == StatusOutput::Json {
+        let value = serde_json::json!({
+            "name": pod.name_any(),
+            "ip": status.pod_ip,
+            "phase": status.phase,
+            "conditions": status.conditions,
+            "containerStatuses": status.container_statuses,
+        });
+        writeln!(out, "{}", value)?;
+        return Ok(());
+    }
+    writeln!(out, "Name: {}", pod.name_any())?;
+    writeln!(out, "IP: {}", status.pod_ip.as_deref().unwrap_or("<none>"))?;
+    writeln!(
+        out,
+        "Phase: {}",
+        status.phase.as_deref().unwrap_or("<none>")
+    )?;
+    writeln!(
+        out,
+        "Conditions: {}",
+        format_pod_conditions(pod).unwrap_or_else(|| "<none>".to_string())
+    )?;
+    writeln!(out, "Containers:")?;
+    for c in status.container_statuses.iter().flatten() {
+        writeln!(
+            out,
+            "  {}: ready={} restarts={} state={}",
+            c.name,
+            c.ready,
+            c.restart_count,
+            format_container_state(c.state.as_ref())
+        )?;
+    }
+    Ok(())
+}
+
+//pods allocated by this crate in the namespace of cluster, sorted by name
+pub async fn list_with<C: Cluster>(cluster: &C) -> Result<Vec<PodSummary>> {
+    let list_params = ListParams::default().labels("app=resalloc-kubernetes");
+    let now = k8s_openapi::chrono::Utc::now();
+    let mut pods: Vec<PodSummary> = cluster
+        .list_pods(&list_params)
+        .await?
+        .iter()
+        .map(|p| PodSummary::from_pod(p, now))
+        .collect();
+    pods.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(pods)
+}
+
+pub fn write_pod_list<W: Write>(
+    out: &mut W,
+    list_command: &ListRequest,
+    pods: &[PodSummary],
+) -> Result<()> {
+    match list_command.output {
+        ListOutput::Table => write_pod_table(out, pods, false),
+        ListOutput::Wide => write_pod_table(out, pods, true),
+        ListOutput::Json => {
+            writeln!(out, "{}", serde_json::to_string(pods)?)?;
+            Ok(())
+        }
+    }
+}
+
+pub async fn deallocate(
+    delete_command: &DeallocationRequest,
+    namespace: &str,
+) -> Result<Vec<DeletedPod>> {
+    deallocate_with(&KubeCluster::new(namespace), delete_command).await
+}
+
+pub async fn deallocate_with<C: Cluster>(
+    cluster: &C,
+    delete_command: &DeallocationRequest,
+) -> Result<Vec<DeletedPod>> {
+    info!("starting to delete {} resource", delete_command.target());
+
+    //get pod by name, ip address or allocation id
+    let pods: Vec<Pod> = match delete_command.pod_name() {
+        Some(ref pod_name) => cluster.get_pod(pod_name).await?.into_iter().collect(),
+        None => {
+            let list_params = delete_command.list_params();
+            list_with_retry(delete_command.retry, delete_command.retry_interval, || {
+                cluster.list_pods(&list_params)
+            })
+            .await?
+        }
+    };
+    if pods.is_empty() {
+        return Err(categorized_error(
+            ErrorCategory::NotFound,
+            format!(
+                "failed to get get any pods within {}",
+                delete_command.target()
+            ),
+        ));
+    }
+    //confirm it's created by our applications
+    let pods: Vec<Pod> = pods
+        .into_iter()
+        .filter(|p| {
+            p.metadata
+                .labels
+                .as_ref()
+                .and_then(|l| l.get("app"))
+                .map(String::as_str)
+                == Some("resalloc-kubernetes")
+        })
+        .collect();
+
+    //guard pods of other tenants in shared namespace
+    for p in pods.iter() {
+        let missing = missing_required_labels(p, &delete_command.require_labels);
+        if !missing.is_empty() {
+            return Err(categorized_error(
+                ErrorCategory::Validation,
+                format!(
+                    "refuse to delete pod {}, required labels {} are not matched",
+                    p.name_any(),
+                    missing.join(", ")
+                ),
+            ));
+        }
+    }
+
+    //pvc shared with other pods must be kept
+    let all_pods = match pods.iter().any(|p| get_pvc_name(p).is_some()) {
+        true => cluster.list_pods(&ListParams::default()).await?,
+        false => Vec::new(),
+    };
+    let deleted = plan_deallocation(&pods, &all_pods);
+    if delete_command.dry_run {
+        return Ok(deleted);
+    }
+    let wait_timeout = delete_command.wait.then_some(delete_command.timeout);
+    delete_planned(cluster, &pods, &deleted, wait_timeout).await?;
+    Ok(deleted)
+}
+
+//deletes pods together with their pvcs and services as planned, waits for pods when timeout is given
+async fn delete_planned<C: Cluster>(
+    cluster: &C,
+    pods: &[Pod],
+    deleted: &[DeletedPod],
+    wait_timeout: Option<u64>,
+) -> Result<()> {
+    // delete pod and pvc
+    for (p, d) in pods.iter().zip(deleted.iter()) {
+        cluster
+            .delete_pod(&p.name_any(), &pod_delete_params(p))
+            .await?;
+        if let Some(timeout) = wait_timeout {
+            wait_pod_deleted(cluster, p, timeout).await?;
+        }
+        info!("pod {} has been deleted", &p.name_any());
+
+        //delete pvc if needed
+        if let Some(ref pvc_name) = d.pvc {
+            if d.pvc_still_referenced {
+                info!("pod's pvc {} is skipped, still referenced", pvc_name);
+            } else {
+                cluster
+                    .delete_pvc(pvc_name, &DeleteParams::default())
+                    .await?;
+                info!("pod's pvc {} has been deleted", pvc_name);
+            }
+        }
+
+        //delete services linked by allocation id
+        if let Some(ref list_params) = linked_service_list_params(p) {
+            delete_services(cluster, list_params, &DeleteParams::default()).await?;
+        }
+    }
+    Ok(())
+}
+
+//deletes all pods allocated by this crate, e.g. leaked by crashed clients
+pub async fn delete_all_with<C: Cluster>(
+    cluster: &C,
+    delete_all_command: &DeleteAllRequest,
+) -> Result<Vec<DeletedPod>> {
+    let all_pods = cluster.list_pods(&ListParams::default()).await?;
+    let now = k8s_openapi::chrono::Utc::now();
+    let pods: Vec<Pod> = all_pods
+        .iter()
+        .filter(|p| p.labels().get("app").map(String::as_str) == Some("resalloc-kubernetes"))
+        .filter(|p| match delete_all_command.older_than {
+            //pods of unknown age are kept
+            Some(older_than) => p
+                .metadata
+                .creation_timestamp
+                .as_ref()
+                .is_some_and(|t| (now - t.0).to_std().is_ok_and(|age| age >= older_than)),
+            None => true,
+        })
+        .cloned()
+        .collect();
+    info!("{} pods are selected to delete", pods.len());
+    let deleted = plan_deallocation(&pods, &all_pods);
+    if !delete_all_command.dry_run {
+        delete_planned(cluster, &pods, &deleted, None).await?;
+    }
+    Ok(deleted)
+}
+
+async fn list_with_retry<F, Fut>(retry: u32, interval: u64, mut list: F) -> Result<Vec<Pod>>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<Vec<Pod>>>,
+{
+    let mut backoff = std::time::Duration::from_millis(interval);
+    let mut pods = list().await?;
+    for attempt in 1..=retry {
+        if !pods.is_empty() {
+            break;
+        }
+        debug!(
+            "no pods found, retrying in {:?} ({}/{})",
+            backoff, attempt, retry
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+        pods = list().await?;
+    }
+    Ok(pods)
+}
+
+fn missing_required_labels(pod: &Pod, required: &[(String, String)]) -> Vec<String> {
+    let labels = pod.metadata.labels.clone().unwrap_or_default();
+    required
+        .iter()
+        .filter(|(key, value)| labels.get(key) != Some(value))
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect()
+}
+
+fn plan_deallocation(deleting: &[Pod], all_pods: &[Pod]) -> Vec<DeletedPod> {
+    deleting
+        .iter()
+        .map(|p| {
+            let pvc = get_pvc_name(p);
+            DeletedPod {
+                name: p.name_any(),
+                pvc_still_referenced: pvc
+                    .as_ref()
+                    .is_some_and(|name| pvc_still_referenced(name, deleting, all_pods)),
+                pvc,
+            }
+        })
+        .collect()
+}
+
+fn get_pvc_name(pod: &Pod) -> Option<String> {
+    let has_volume = pod.metadata.labels.as_ref()?.get("has_volume")?;
+    if has_volume != "true" {
+        return None;
+    }
+    //the claim is named after the pod unless the volume tells otherwise
+    let claim = pod
+        .spec
+        .iter()
+        .flat_map(|spec| spec.volumes.iter().flatten())
+        .find_map(|v| v.persistent_volume_claim.as_ref())
+        .map(|c| c.claim_name.clone());
+    Some(claim.unwrap_or_else(|| pod.name_any()))
+}
+
+fn pvc_still_referenced(pvc_name: &str, deleting: &[Pod], all_pods: &[Pod]) -> bool {
+    let deleting_names: Vec<String> = deleting.iter().map(|p| p.name_any()).collect();
+    all_pods
+        .iter()
+        .filter(|p| !deleting_names.contains(&p.name_any()))
+        .flat_map(|p| p.spec.iter().flat_map(|spec| spec.volumes.iter().flatten()))
+        .filter_map(|v| v.persistent_volume_claim.as_ref())
+        .any(|c| c.claim_name == pvc_name)
+}
+
+async fn wait_pvc_bound<C: Cluster>(
+    cluster: &C,
+    pvc: &PersistentVolumeClaim,
+    timeout: u64,
+) -> Result<()> {
+    let name = pvc.name_any();
+    let bound = cluster.wait_pvc(
+        &name,
+        Box::new(|p: Option<&PersistentVolumeClaim>| {
+            p.and_then(|p| p.status.as_ref())
+                .and_then(|s| s.phase.as_deref())
+                == Some("Bound")
+        }),
+    );
+    match tokio::time::timeout(std::time::Duration::from_secs(timeout), bound).await {
+        Ok(res) => {
+            res?;
+            info!("pvc {} has been bound", &name);
+            Ok(())
+        }
+        Err(_) => Err(categorized_error(
+            ErrorCategory::Timeout,
+            format!(
+                "pvc {} is not bound after {} seconds, check the provisioner of storage class {}",
+                name,
+                timeout,
+                pvc.spec
+                    .as_ref()
+                    .and_then(|s| s.storage_class_name.as_deref())
+                    .unwrap_or("default")
+            ),
+        )),
+    }
+}
+
+async fn wait_pod_deleted<C: Cluster>(cluster: &C, pod: &Pod, timeout: u64) -> Result<()> {
+    let name = pod.name_any();
+    let uid = pod.uid().unwrap_or_default();
+    let deleted = cluster.wait_pod(
+        &name,
+        Box::new(move |p: Option<&Pod>| conditions::is_deleted(&uid).matches_object(p)),
+    );
+    match tokio::time::timeout(std::time::Duration::from_secs(timeout), deleted).await {
+        Ok(res) => {
+            res?;
+            Ok(())
+        }
+        Err(_) => match cluster.get_pod(&name).await? {
+            Some(current) => Err(stuck_terminating_error(&current, timeout)),
+            None => Ok(()),
+        },
+    }
+}
+
+fn stuck_terminating_error(pod: &Pod, timeout: u64) -> anyhow::Error {
+    let finalizers = pod.finalizers();
+    let remaining = match finalizers.is_empty() {
+        true => "none".to_string(),
+        false => finalizers.join(", "),
+    };
+    categorized_error(
+        ErrorCategory::Timeout,
+        format!(
+            "pod {} is still terminating after {} seconds, remaining finalizers: {}",
+            pod.name_any(),
+            timeout,
+            remaining
+        ),
+    )
+}
+
+async fn delete_services<C: Cluster>(
+    cluster: &C,
+    list_params: &ListParams,
+    delete_params: &DeleteParams,
+) -> Result<()> {
+    for service in cluster.list_services(list_params).await? {
+        cluster
+            .delete_service(&service.name_any(), delete_params)
+            .await?;
+        info!("pod's service {} has been deleted", &service.name_any());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fake::FakeCluster;
+    use crate::{
+        add_protect_finalizer, allocate, allocate_with, allocation_id, batch_pod_outcome,
+        categorized_error, cleanup_delete_params, create_simple_pod_yaml, created_resources,
+        deallocate_with, delete_all_with, ensure_with, error_category, error_json, error_pod,
+        error_trace_id, existing_allocation, generate_pod_resource, generate_pvc_resource,
+        generate_service_resource, get_pvc_name, inherit_namespace_labels,
+        linked_service_list_params, list_with, list_with_retry, missing_required_labels,
+        parse_annotation, parse_capability, parse_configmap_mount, parse_duration,
+        parse_emptydir_mount, parse_env, parse_grpc_probe, parse_node_affinity,
+        parse_prometheus_scrape, parse_required_label, parse_secret_source, parse_toleration,
+        parse_volume_mount, plan_deallocation, pod_delete_params, pod_failure_reason,
+        pod_name_from_uuid, qualified_name_error, release_protect_finalizer, render_template,
+        report_timing, sandbox_warnings, security_warnings, set_owner_reference, show_manifests,
+        stamp_allocation_id, status_with, stuck_terminating_error, template_registry,
+        validate_annotation_size, validate_manifests, validate_request, without_protect_finalizer,
+        write_pod_list, write_pod_status, write_pod_table, AccessMode, DeleteAllRequest,
+        EnsureRequest, ListOutput, ListRequest, PodSummary, PreemptionPolicy, StatusOutput,
+        StatusRequest, MAX_ANNOTATION_SIZE, MAX_NAME_ATTEMPTS, RAW_VOLUME, TRACE_ID_ANNOTATION,
+    };
+    use crate::{
+        AllocationRequest, Arch, Config, CreatedResource, DeallocationRequest, DeletedPod,
+    };
+    use crate::{ErrorCategory, GpuVendor, ImagePullPolicy, Os, RestartPolicy, Sandbox, WaitFor};
+    use k8s_openapi::api::core::v1::{
+        ContainerState, ContainerStateTerminated, ContainerStatus, NodeSelectorRequirement,
+        PersistentVolumeClaimStatus, Pod, PodStatus,
+    };
+    use kube::runtime::wait::{conditions::is_pod_running, Condition};
+
+    const TEST_POD_NAME: &str = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
+
+    fn mock_request() -> AllocationRequest {
+        AllocationRequest {
+            image_tag: "openeuler/openeuler:22.03".to_string(),
+            cpu_resource: "100m".to_string(),
+            memory_resource: "500Mi".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_witout_volume() {
+        let yaml_str = r#"apiVersion: v1
+kind: Pod
+metadata:
+  labels:
+    app: resalloc-kubernetes
+    has_volume: 'false'
+  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+  namespace: test_ns
+spec:
+  containers:
+  - image: openeuler/openeuler:22.03
+    imagePullPolicy: IfNotPresent
+    name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+    resources:
+      limits:
+        cpu: 100m
+        memory: 500Mi
+      requests:
+        cpu: 100m
+        memory: 500Mi
+    securityContext:
+      privileged: false
+"#;
+
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            image_tag: "openeuler/openeuler:22.03".to_string(),
+            cpu_resource: "100m".to_string(),
+            memory_resource: "500Mi".to_string(),
+            node_selector: Vec::new(),
+            privileged: false,
+            additional_pvc_labels: Vec::new(),
+            additional_labels: Vec::new(),
+            additional_volume_class: None,
+            additional_volume_size: None,
+            additional_volume_mount_path: None,
+            dry_run: false,
+            secret: None,
+            ..Default::default()
+        };
+        let name = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
+        let namespace = "test_ns";
+        let pod_generated = generate_pod_resource(&mock_command, namespace, name, "", false)
+            .await
+            .unwrap();
+
+        assert_eq!(pod_generated.metadata.name.as_ref().unwrap(), name);
+        assert_eq!(
+            pod_generated.metadata.namespace.as_ref().unwrap(),
+            namespace
+        );
+        assert_eq!(serde_yaml::to_string(&pod_generated).unwrap(), yaml_str);
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_labels() {
+        let yaml_str = r#"apiVersion: v1
+kind: Pod
+metadata:
+  labels:
+    app: resalloc-kubernetes
+    failure-domain.beta.kubernetes.io/region: region1
+    has_volume: 'false'
+  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+  namespace: test_ns
+spec:
+  containers:
+  - image: openeuler/openeuler:22.03
+    imagePullPolicy: IfNotPresent
+    name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+    resources:
+      limits:
+        cpu: 100m
+        memory: 500Mi
+      requests:
+        cpu: 100m
+        memory: 500Mi
+    securityContext:
+      privileged: false
+"#;
+
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            image_tag: "openeuler/openeuler:22.03".to_string(),
+            cpu_resource: "100m".to_string(),
+            memory_resource: "500Mi".to_string(),
+            node_selector: Vec::new(),
+            privileged: false,
+            additional_pvc_labels: Vec::new(),
+            additional_labels: vec![("failure-domain.beta.kubernetes.io/region=region1".to_string())],
+            additional_volume_class: None,
+            additional_volume_size: None,
+            additional_volume_mount_path: None,
+            dry_run: false,
+            secret: None,
+            ..Default::default()
+        };
+        let name = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
+        let namespace = "test_ns";
+        let pod_generated = generate_pod_resource(&mock_command, namespace, name, "", false)
+            .await
+            .unwrap();
+
+        assert_eq!(pod_generated.metadata.name.as_ref().unwrap(), name);
+        assert_eq!(
+            pod_generated.metadata.namespace.as_ref().unwrap(),
+            namespace
+        );
+        assert_eq!(serde_yaml::to_string(&pod_generated).unwrap(), yaml_str);
+    }
+    
+    #[tokio::test]
+    async fn test_pod_template_with_volume_and_secret() {
+        let yaml_str = r#"apiVersion: v1
+kind: Pod
+metadata:
+  labels:
+    app: resalloc-kubernetes
+    has_volume: 'true'
+  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+  namespace: test_ns
+spec:
+  containers:
+  - image: openeuler/openeuler:22.03
+    imagePullPolicy: IfNotPresent
+    name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+    resources:
+      limits:
+        cpu: 100m
+        memory: 500Mi
+      requests:
+        cpu: 100m
+        memory: 500Mi
+    securityContext:
+      privileged: false
+    volumeMounts:
+    - mountPath: /home/copr/server.crt
+      name: copr-secrets
+      subPath: server-crt
+    - mountPath: /etc/test_mount
+      name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+  volumes:
+  - name: copr-secrets
+    secret:
+      secretName: copr-secrets
+  - name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+    persistentVolumeClaim:
+      claimName: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+"#;
+
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            image_tag: "openeuler/openeuler:22.03".to_string(),
+            cpu_resource: "100m".to_string(),
+            memory_resource: "500Mi".to_string(),
+            node_selector: Vec::new(),
+            privileged: false,
+            additional_pvc_labels: Vec::new(),
+            additional_labels: Vec::new(),
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            dry_run: false,
+            secret: Some(k8s_openapi::api::core::v1::VolumeMount {
+                mount_path: "/home/copr/server.crt".to_string(),
+                mount_propagation: None,
+                name: "copr-secrets".to_string(),
+                read_only: None,
+                sub_path: Some("server-crt".to_string()),
+                sub_path_expr: None,
+            }),
+            ..Default::default()
+        };
+        let name = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
+        let namespace = "test_ns";
+        let pvc_name = name;
+        let pod_generated = generate_pod_resource(&mock_command, namespace, name, pvc_name, true)
+            .await
+            .unwrap();
+
+        assert_eq!(pod_generated.metadata.name.as_ref().unwrap(), name);
+        assert_eq!(
+            pod_generated.metadata.namespace.as_ref().unwrap(),
+            namespace
+        );
+        assert_eq!(serde_yaml::to_string(&pod_generated).unwrap(), yaml_str);
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_volume() {
+        let pvc_yaml_str = r#"apiVersion: v1
+kind: PersistentVolumeClaim
+metadata:
+  labels:
+    app: resalloc-kubernetes
+    failure-domain.beta.kubernetes.io/region: region1
+  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+  namespace: test_ns
+spec:
+  accessModes:
+  - ReadWriteOnce
+  resources:
+    requests:
+      storage: 10Gi
+  storageClassName: test_pvc
+"#;
+        let pod_yaml_str = r#"apiVersion: v1
+kind: Pod
+metadata:
+  labels:
+    app: resalloc-kubernetes
+    has_volume: 'true'
+  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+  namespace: test_ns
+spec:
+  containers:
+  - image: openeuler/openeuler:22.03
+    imagePullPolicy: IfNotPresent
+    name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+    resources:
+      limits:
+        cpu: '1'
+        memory: 500Mi
+      requests:
+        cpu: '1'
+        memory: 500Mi
+    securityContext:
+      privileged: false
+    volumeMounts:
+    - mountPath: /etc/test_mount
+      name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+  volumes:
+  - name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+    persistentVolumeClaim:
+      claimName: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+"#;
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            image_tag: "openeuler/openeuler:22.03".to_string(),
+            cpu_resource: "1".to_string(),
+            memory_resource: "500Mi".to_string(),
+            node_selector: Vec::new(),
+            privileged: false,
+            additional_pvc_labels: vec![("failure-domain.beta.kubernetes.io/region=region1".to_string())],
+            additional_labels: Vec::new(),
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            dry_run: false,
+            secret: None,
+            ..Default::default()
+        };
+
+        let name = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
+        let namespace = "test_ns";
+        let pvc_name = name;
+        let pod_generated = generate_pod_resource(&mock_command, namespace, name, pvc_name, true)
+            .await
+            .unwrap();
+
+        let pvc = generate_pvc_resource(&mock_command, namespace, pvc_name)
+            .await
+            .unwrap();
+        assert_eq!(pod_generated.metadata.name.as_ref().unwrap(), name);
+        assert_eq!(
+            pod_generated.metadata.namespace.as_ref().unwrap(),
+            namespace
+        );
+        assert_eq!(serde_yaml::to_string(&pod_generated).unwrap(), pod_yaml_str);
+        assert_eq!(serde_yaml::to_string(&pvc).unwrap(), pvc_yaml_str);
+    }
+
+    #[tokio::test]
+    async fn test_additional_volume_access_mode() {
+        let mock_command = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        assert_eq!(
+            pvc.spec.unwrap().access_modes,
+            Some(vec!["ReadWriteOnce".to_string()])
+        );
+
+        let mock_command = AllocationRequest {
+            additional_volume_access_mode: AccessMode::ReadWriteMany,
+            ..mock_command
+        };
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        assert_eq!(
+            pvc.spec.unwrap().access_modes,
+            Some(vec!["ReadWriteMany".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pod_with_on_failure_restart_still_running() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            restart_policy: Some(RestartPolicy::OnFailure),
+            ..mock_request()
+        };
+        let mut pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            pod.spec.as_ref().unwrap().restart_policy.as_deref(),
+            Some("OnFailure")
+        );
+
+        //container failed once and has been restarted in place
+        pod.status = Some(PodStatus {
+            phase: Some("Running".to_string()),
+            container_statuses: Some(vec![ContainerStatus {
+                name: TEST_POD_NAME.to_string(),
+                restart_count: 1,
+                ready: true,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        assert!(is_pod_running().matches_object(Some(&pod)));
+    }
+
+    #[tokio::test]
+    async fn test_show_yaml_before_create() {
+        let mut mock_command = AllocationRequest {
+            timeout: 120,
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+
+        let mut output = Vec::new();
+        show_manifests(&mock_command, &mut output, Some(&pvc), &pod, None).unwrap();
+        assert!(output.is_empty());
+
+        mock_command.show_yaml = true;
+        show_manifests(&mock_command, &mut output, Some(&pvc), &pod, None).unwrap();
+        let expected = format!(
+            "---\n{}---\n{}",
+            serde_yaml::to_string(&pvc).unwrap(),
+            serde_yaml::to_string(&pod).unwrap()
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_created_resources_json_with_volume() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+
+        let resources = created_resources(&pod, Some(&pvc), None, &[]);
+        assert_eq!(
+            resources,
+            vec![
+                CreatedResource {
+                    kind: "PersistentVolumeClaim".to_string(),
+                    name: TEST_POD_NAME.to_string(),
+                },
+                CreatedResource {
+                    kind: "Pod".to_string(),
+                    name: TEST_POD_NAME.to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_exit_codes() {
+        let not_found = categorized_error(
+            ErrorCategory::NotFound,
+            "failed to get get any pods within 10.0.0.1 address".to_string(),
+        );
+        assert_eq!(error_category(&not_found).exit_code(), 4);
+
+        let forbidden = anyhow::Error::new(kube::Error::Api(kube::error::ErrorResponse {
+            status: "Failure".to_string(),
+            message: "pods is forbidden".to_string(),
+            reason: "Forbidden".to_string(),
+            code: 403,
+        }));
+        assert_eq!(error_category(&forbidden), ErrorCategory::Auth);
+        assert_eq!(error_category(&forbidden).exit_code(), 3);
+
+        let validation = categorized_error(ErrorCategory::Validation, "invalid".to_string())
+            .context("failed to generate pod resource");
+        assert_eq!(error_category(&validation).exit_code(), 2);
+
+        let elapsed = tokio::time::timeout(
+            std::time::Duration::from_millis(1),
+            std::future::pending::<()>(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error_category(&anyhow::Error::new(elapsed)).exit_code(), 5);
+
+        assert_eq!(error_category(&anyhow::anyhow!("unknown")).exit_code(), 1);
+
+        //container exit codes never collide with the reserved ones and stay in 1..=255
+        for (code, exit_code) in [
+            (2, 102),
+            (5, 105),
+            (137, 237),
+            (155, 255),
+            (256, 255),
+            (0, 100),
+            (-1, 100),
+        ] {
+            assert_eq!(ErrorCategory::ContainerExit(code).exit_code(), exit_code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inherit_namespace_labels_with_prefix() {
+        let mock_command = AllocationRequest {
+            inherit_namespace_labels: Some("org.example/".to_string()),
+            ..mock_request()
+        };
+        let mut pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let namespace: k8s_openapi::api::core::v1::Namespace = serde_yaml::from_str(
+            r#"apiVersion: v1
+kind: Namespace
+metadata:
+  name: test_ns
+  labels:
+    app: other
+    org.example/cost-center: cc-42
+    org.example/team: copr
+    kubernetes.io/metadata.name: test_ns
+"#,
+        )
+        .unwrap();
+
+        inherit_namespace_labels(&mut pod, &namespace, "org.example/");
+        let labels = pod.metadata.labels.unwrap();
+        assert_eq!(labels.len(), 4);
+        assert_eq!(labels["app"], "resalloc-kubernetes");
+        assert_eq!(labels["has_volume"], "false");
+        assert_eq!(labels["org.example/cost-center"], "cc-42");
+        assert_eq!(labels["org.example/team"], "copr");
+    }
+
+    #[tokio::test]
+    async fn test_allocation_id_label_and_delete_selector() {
+        let mock_command = mock_request();
+        let mut pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let id = allocation_id(TEST_POD_NAME);
+        assert_eq!(id, "9a1884fb");
+        stamp_allocation_id(&mut pod.metadata, &id);
+        assert_eq!(
+            pod.metadata.labels.as_ref().unwrap()["resalloc.allocation-id"],
+            "9a1884fb"
+        );
+
+        let delete_command = DeallocationRequest {
+            name: Some("10.0.0.1".to_string()),
+            allocation_id: Some(id),
+            ..Default::default()
+        };
+        let list_params = delete_command.list_params();
+        assert_eq!(
+            list_params.label_selector.as_deref(),
+            Some("resalloc.allocation-id=9a1884fb")
+        );
+        assert_eq!(list_params.field_selector, None);
+
+        let delete_command = DeallocationRequest {
+            name: Some("10.0.0.1".to_string()),
+            allocation_id: None,
+            ..Default::default()
+        };
+        let list_params = delete_command.list_params();
+        assert_eq!(
+            list_params.field_selector.as_deref(),
+            Some("status.podIP=10.0.0.1")
+        );
+        assert_eq!(list_params.label_selector, None);
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_prometheus_scrape() {
+        let mock_command = AllocationRequest {
+            prometheus_scrape: Some(parse_prometheus_scrape("9100:/stats").unwrap()),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let annotations = pod.metadata.annotations.unwrap();
+        assert_eq!(annotations.len(), 3);
+        assert_eq!(annotations["prometheus.io/scrape"], "true");
+        assert_eq!(annotations["prometheus.io/port"], "9100");
+        assert_eq!(annotations["prometheus.io/path"], "/stats");
+
+        assert_eq!(parse_prometheus_scrape("8080").unwrap().path, "/metrics");
+        assert!(parse_prometheus_scrape("http").is_err());
+        assert!(parse_prometheus_scrape("8080:metrics").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_spot() {
+        let mut mock_command = AllocationRequest {
+            spot: true,
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        let tolerations = spec.tolerations.unwrap();
+        assert_eq!(tolerations.len(), 4);
+        assert!(tolerations
+            .iter()
+            .any(|t| t.key.as_deref() == Some("cloud.google.com/gke-spot")
+                && t.value.as_deref() == Some("true")
+                && t.effect.as_deref() == Some("NoSchedule")));
+        let preferred = spec
+            .affinity
+            .unwrap()
+            .node_affinity
+            .unwrap()
+            .preferred_during_scheduling_ignored_during_execution
+            .unwrap();
+        assert_eq!(preferred.len(), 4);
+        assert!(preferred.iter().all(|p| p.weight == 100));
+
+        //keys overridden by config
+        mock_command.config = serde_yaml::from_str::<Config>(
+            r#"spot:
+  tolerations:
+  - key: example.com/spot
+    operator: Exists
+    effect: NoSchedule
+  node-labels:
+    example.com/lifecycle: spot
+"#,
+        )
+        .unwrap();
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let expected = r#"nodeAffinity:
+  preferredDuringSchedulingIgnoredDuringExecution:
+  - preference:
+      matchExpressions:
+      - key: example.com/lifecycle
+        operator: In
+        values:
+        - spot
+    weight: 100
+"#;
+        let spec = pod.spec.unwrap();
+        assert_eq!(serde_yaml::to_string(&spec.affinity).unwrap(), expected);
+        let tolerations = spec.tolerations.unwrap();
+        assert_eq!(tolerations.len(), 1);
+        assert_eq!(tolerations[0].operator.as_deref(), Some("Exists"));
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_arch_and_os() {
+        let mock_command = AllocationRequest {
+            node_selector: vec!["node-role.kubernetes.io/builder=true".to_string()],
+            arch: Some(Arch::Arm64),
+            os: Some(Os::Linux),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let node_selector = pod.spec.unwrap().node_selector.unwrap();
+        assert_eq!(node_selector.len(), 3);
+        assert_eq!(node_selector["kubernetes.io/arch"], "arm64");
+        assert_eq!(node_selector["kubernetes.io/os"], "linux");
+        assert_eq!(node_selector["node-role.kubernetes.io/builder"], "true");
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_windows() {
+        let mut mock_command = AllocationRequest {
+            image_tag: "mcr.microsoft.com/windows/servercore:ltsc2022".to_string(),
+            cpu_resource: "1".to_string(),
+            memory_resource: "2Gi".to_string(),
+            windows: true,
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        assert_eq!(spec.os.unwrap().name, "windows");
+        assert_eq!(spec.node_selector.unwrap()["kubernetes.io/os"], "windows");
+        let security_context = spec.containers[0].security_context.clone().unwrap();
+        assert_eq!(security_context.privileged, None);
+        assert_eq!(
+            security_context.windows_options.unwrap().run_as_user_name,
+            Some("ContainerUser".to_string())
+        );
+
+        mock_command.privileged = true;
+        let err = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap_err();
+        assert_eq!(crate::error_category(&err), ErrorCategory::Validation);
+        assert!(err.to_string().contains("privileged"));
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_gpu_vendor() {
+        let mut mock_command = AllocationRequest {
+            cpu_resource: "1".to_string(),
+            memory_resource: "2Gi".to_string(),
+            gpu: Some(2),
+            gpu_vendor: GpuVendor::Amd,
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let resources = pod.spec.unwrap().containers[0].resources.clone().unwrap();
+        for quantity in [resources.limits.unwrap(), resources.requests.unwrap()] {
+            assert_eq!(quantity["amd.com/gpu"].0, "2");
+            assert!(!quantity.contains_key("nvidia.com/gpu"));
+        }
+
+        mock_command.mig_profile = Some("1g.5gb".to_string());
+        assert!(
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+                .await
+                .is_err()
+        );
+
+        mock_command.gpu_vendor = GpuVendor::Nvidia;
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        assert_eq!(
+            spec.containers[0]
+                .resources
+                .clone()
+                .unwrap()
+                .limits
+                .unwrap()["nvidia.com/gpu"]
+                .0,
+            "2"
+        );
+        assert_eq!(
+            spec.node_selector.unwrap()["nvidia.com/mig.config"],
+            "all-1g.5gb"
+        );
+
+        mock_command.gpu = Some(8);
+        assert!(
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_zone() {
+        let mock_command = AllocationRequest {
+            node_selector: vec!["node-role.kubernetes.io/builder=true".to_string()],
+            zone: Some("eu-west-1a".to_string()),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let node_selector = pod.spec.unwrap().node_selector.unwrap();
+        assert_eq!(node_selector.len(), 2);
+        assert_eq!(node_selector["topology.kubernetes.io/zone"], "eu-west-1a");
+        assert_eq!(node_selector["node-role.kubernetes.io/builder"], "true");
+    }
+
+    #[tokio::test]
+    async fn test_cost_center_label_on_pod_and_pvc() {
+        let mut mock_command = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        //the cost center is sourced from environment
+        let command = <AllocationRequest as clap::Args>::augment_args(clap::Command::new("add"));
+        let cost_center = command
+            .get_arguments()
+            .find(|a| a.get_id() == "cost_center")
+            .unwrap();
+        assert_eq!(
+            cost_center.get_env(),
+            Some(std::ffi::OsStr::new("RESALLOC_COST_CENTER"))
+        );
+        mock_command.cost_center = Some("cc-42".to_string());
+
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        assert_eq!(pod.metadata.labels.unwrap()["cost-center"], "cc-42");
+        assert_eq!(pvc.metadata.labels.unwrap()["cost-center"], "cc-42");
+
+        mock_command.cost_label_key = Some("example.com/cost-center".to_string());
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let labels = pod.metadata.labels.unwrap();
+        assert_eq!(labels["example.com/cost-center"], "cc-42");
+        assert!(!labels.contains_key("cost-center"));
+    }
+
+    #[tokio::test]
+    async fn test_owner_configmap_reference_on_pod_and_pvc() {
+        let mock_command = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            owner_configmap: Some("resalloc-pool".to_string()),
+            ..mock_request()
+        };
+        let configmap: k8s_openapi::api::core::v1::ConfigMap = serde_yaml::from_str(
+            r#"apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: resalloc-pool
+  namespace: test_ns
+  uid: 0c6f9a3e-2b7c-4f5e-9d1a-6f0e4b8a1c2d
+"#,
+        )
+        .unwrap();
+        let mut pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let mut pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        set_owner_reference(&mut pod.metadata, &configmap).unwrap();
+        set_owner_reference(&mut pvc.metadata, &configmap).unwrap();
+
+        let expected = r#"- apiVersion: v1
+  kind: ConfigMap
+  name: resalloc-pool
+  uid: 0c6f9a3e-2b7c-4f5e-9d1a-6f0e4b8a1c2d
+"#;
+        assert_eq!(
+            serde_yaml::to_string(&pod.metadata.owner_references).unwrap(),
+            expected
+        );
+        assert_eq!(
+            serde_yaml::to_string(&pvc.metadata.owner_references).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_protect_finalizer_added_and_removed() {
+        let mut metadata = k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+            finalizers: Some(vec!["example.com/other".to_string()]),
+            ..Default::default()
+        };
+        add_protect_finalizer(&mut metadata);
+        add_protect_finalizer(&mut metadata);
+        assert_eq!(
+            metadata.finalizers.as_ref().unwrap(),
+            &vec![
+                "example.com/other".to_string(),
+                "resalloc-kubernetes/protect".to_string()
+            ]
+        );
+
+        //finalizers of others are kept when released
+        assert_eq!(
+            without_protect_finalizer(&metadata),
+            vec!["example.com/other".to_string()]
+        );
+        assert!(without_protect_finalizer(&Default::default()).is_empty());
+    }
+
+    #[test]
+    fn test_cleanup_delete_params() {
+        let mut mock_command = AllocationRequest {
+            grace_period: Some(5),
+            ..mock_request()
+        };
+        let delete_params = cleanup_delete_params(&mock_command);
+        assert_eq!(delete_params.grace_period_seconds, Some(5));
+        assert!(matches!(
+            delete_params.propagation_policy,
+            Some(kube::api::PropagationPolicy::Background)
+        ));
+
+        mock_command.force = true;
+        assert_eq!(
+            cleanup_delete_params(&mock_command).grace_period_seconds,
+            Some(0)
+        );
+
+        assert_eq!(
+            cleanup_delete_params(&mock_request()).grace_period_seconds,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_label_and_annotation_key_syntax() {
+        for key in [
+            "app",
+            "has_volume",
+            "failure-domain.beta.kubernetes.io/region",
+            "prometheus.io/scrape",
+            "resalloc.allocation-id",
+            "Example_Key.1",
+        ] {
+            assert_eq!(qualified_name_error(key), None, "{}", key);
+        }
+        for key in [
+            "",
+            "/name",
+            "example.com/",
+            "Example.com/name",
+            "example..com/name",
+            "-example.com/name",
+            "example.com/-name",
+            "example.com/name_",
+            "example.com/na me",
+            "a/b/c",
+            &"a".repeat(64),
+            &format!("{}/name", "a".repeat(254)),
+        ] {
+            assert!(qualified_name_error(key).is_some(), "{}", key);
+        }
+
+        let mock_command = AllocationRequest {
+            additional_labels: vec!["example.com/bad key=value".to_string()],
+            ..mock_request()
+        };
+        let err = generate_pod_resource(&mock_command, "test_ns", "resalloc-test", "", false)
+            .await
+            .unwrap_err();
+        assert_eq!(crate::error_category(&err), ErrorCategory::Validation);
+        assert!(err
+            .to_string()
+            .starts_with("invalid label key 'example.com/bad key'"));
+    }
+
+    #[test]
+    fn test_annotation_size_limit() {
+        let mut annotations = std::collections::BTreeMap::new();
+        annotations.insert("example.com/small".to_string(), "value".to_string());
+        annotations.insert("example.com/large".to_string(), "x".repeat(200 * 1024));
+        let mut metadata = k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+            annotations: Some(annotations),
+            ..Default::default()
+        };
+        assert!(validate_annotation_size(&metadata).is_ok());
+
+        metadata
+            .annotations
+            .as_mut()
+            .unwrap()
+            .insert("example.com/huge".to_string(), "x".repeat(100 * 1024));
+        let err = validate_annotation_size(&metadata).unwrap_err();
+        assert_eq!(crate::error_category(&err), ErrorCategory::Validation);
+        assert!(err
+            .to_string()
+            .contains("exceeds the limit of 262144 bytes"));
+        assert!(err.to_string().contains("'example.com/large'"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_wait_timeout_lists_finalizers() {
+        let pod: k8s_openapi::api::core::v1::Pod = serde_yaml::from_str(
+            r#"apiVersion: v1
+kind: Pod
+metadata:
+  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
+  deletionTimestamp: "2023-01-05T00:00:00Z"
+  finalizers:
+  - resalloc-kubernetes/protect
+  - example.com/backup
+"#,
+        )
+        .unwrap();
+        let err = stuck_terminating_error(&pod, 10);
+        assert_eq!(crate::error_category(&err), ErrorCategory::Timeout);
+        assert_eq!(
+            err.to_string(),
+            "pod resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71 is still terminating after 10 seconds, remaining finalizers: resalloc-kubernetes/protect, example.com/backup"
+        );
+    }
+
+    #[test]
+    fn test_delete_by_uuid_resolves_pod_name() {
+        assert_eq!(
+            pod_name_from_uuid("9a1884fb-8a7b-459f-aefe-c54ac1188d71"),
+            TEST_POD_NAME
+        );
+        assert_eq!(pod_name_from_uuid(TEST_POD_NAME), TEST_POD_NAME);
+
+        let delete_command = DeallocationRequest {
+            uuid: Some("9a1884fb-8a7b-459f-aefe-c54ac1188d71".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(delete_command.target(), format!("pod {}", TEST_POD_NAME));
+    }
+
+    #[tokio::test]
+    async fn test_delete_pod_selects_linked_service() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            expose: vec![22],
+            ..mock_request()
+        };
+        let mut pod = generate_pod_resource(
+            &mock_command,
+            "test_ns",
+            TEST_POD_NAME,
+            TEST_POD_NAME,
+            false,
+        )
+        .await
+        .unwrap();
+        stamp_allocation_id(&mut pod.metadata, "9a1884fb");
+        let service =
+            generate_service_resource(&mock_command, "test_ns", TEST_POD_NAME, "9a1884fb").unwrap();
+
+        //the selector used on deleting the pod matches the labels of its service
+        let list_params = linked_service_list_params(&pod).unwrap();
+        assert_eq!(
+            list_params.label_selector,
+            Some("resalloc.allocation-id=9a1884fb".to_string())
+        );
+        assert_eq!(
+            service
+                .metadata
+                .labels
+                .unwrap()
+                .get("resalloc.allocation-id")
+                .unwrap(),
+            "9a1884fb"
+        );
+        let ports = service.spec.unwrap().ports.unwrap();
+        assert_eq!(ports[0].port, 22);
+
+        //pods without a service never trigger a service lookup
+        let mock_command = AllocationRequest {
+            expose: vec![],
+            ..mock_command
+        };
+        let mut pod = generate_pod_resource(
+            &mock_command,
+            "test_ns",
+            TEST_POD_NAME,
+            TEST_POD_NAME,
+            false,
+        )
+        .await
+        .unwrap();
+        stamp_allocation_id(&mut pod.metadata, "9a1884fb");
+        assert!(linked_service_list_params(&pod).is_none());
+        assert!(
+            generate_service_resource(&mock_command, "test_ns", TEST_POD_NAME, "9a1884fb")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_preview_skips_shared_pvc() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let first = generate_pod_resource(&mock_command, "test_ns", "resalloc-1", "shared", true)
+            .await
+            .unwrap();
+        let second =
+            generate_pod_resource(&mock_command, "test_ns", "resalloc-2", "resalloc-2", true)
+                .await
+                .unwrap();
+        let other = generate_pod_resource(&mock_command, "test_ns", "resalloc-3", "shared", true)
+            .await
+            .unwrap();
+        let deleting = vec![first, second];
+        let mut all_pods = deleting.clone();
+        all_pods.push(other);
+
+        assert_eq!(
+            plan_deallocation(&deleting, &all_pods),
+            vec![
+                DeletedPod {
+                    name: "resalloc-1".to_string(),
+                    pvc: Some("shared".to_string()),
+                    pvc_still_referenced: true,
+                },
+                DeletedPod {
+                    name: "resalloc-2".to_string(),
+                    pvc: Some("resalloc-2".to_string()),
+                    pvc_still_referenced: false,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_list_retried_until_found() {
+        let attempts = std::cell::Cell::new(0);
+        let pods = list_with_retry(3, 0, || async {
+            attempts.set(attempts.get() + 1);
+            match attempts.get() {
+                3 => Ok(vec![Pod::default()]),
+                _ => Ok(vec![]),
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(pods.len(), 1);
+        assert_eq!(attempts.get(), 3);
+
+        //retries are bounded
+        attempts.set(0);
+        let pods = list_with_retry(2, 0, || async {
+            attempts.set(attempts.get() + 1);
+            Ok(vec![])
+        })
+        .await
+        .unwrap();
+        assert!(pods.is_empty());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_gvisor_sandbox() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            privileged: true,
+            sandbox: Some(Sandbox::Gvisor),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(
+            &mock_command,
+            "test_ns",
+            TEST_POD_NAME,
+            TEST_POD_NAME,
+            false,
+        )
+        .await
+        .unwrap();
+        let spec = pod.spec.unwrap();
+        assert_eq!(spec.runtime_class_name.as_deref(), Some("gvisor"));
+        let security_context = spec.containers[0].security_context.as_ref().unwrap();
+        assert_eq!(security_context.privileged, None);
+        assert_eq!(
+            sandbox_warnings(&mock_command),
+            vec!["privileged mode is not supported by gvisor sandbox and is ignored".to_string()]
+        );
+
+        //runtime class TEST_POD_NAME is overridden by config
+        let mock_command = AllocationRequest {
+            privileged: false,
+            config: serde_yaml::from_str(
+                r#"sandbox:
+  gvisor:
+    runtime-class: runsc
+    annotations:
+      example.com/sandbox: gvisor
+"#,
+            )
+            .unwrap(),
+            ..mock_command
+        };
+        let pod = generate_pod_resource(
+            &mock_command,
+            "test_ns",
+            TEST_POD_NAME,
+            TEST_POD_NAME,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            pod.spec.unwrap().runtime_class_name.as_deref(),
+            Some("runsc")
+        );
+        assert_eq!(
+            pod.metadata.annotations.unwrap().get("example.com/sandbox"),
+            Some(&"gvisor".to_string())
+        );
+        assert!(sandbox_warnings(&mock_command).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_batch_pod_succeeded_and_failed() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            restart_policy: Some(RestartPolicy::Never),
+            ..mock_request()
+        };
+        let mut pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+
+        pod.status = Some(PodStatus {
+            phase: Some("Running".to_string()),
+            ..Default::default()
+        });
+        assert!(batch_pod_outcome(&pod).is_none());
+
+        pod.status = Some(PodStatus {
+            phase: Some("Succeeded".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(batch_pod_outcome(&pod).unwrap().unwrap(), 0);
+
+        pod.status = Some(PodStatus {
+            phase: Some("Failed".to_string()),
+            container_statuses: Some(vec![ContainerStatus {
+                name: TEST_POD_NAME.to_string(),
+                state: Some(ContainerState {
+                    terminated: Some(ContainerStateTerminated {
+                        exit_code: 2,
+                        reason: Some("Error".to_string()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let err = batch_pod_outcome(&pod).unwrap().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "pod {} failed, container {} exited with code 2: Error",
+                TEST_POD_NAME, TEST_POD_NAME
+            )
+        );
+    }
+
+    #[test]
+    fn test_container_exit_code_reflected_in_exit_status() {
+        let mut pod = Pod::default();
+        pod.metadata.name = Some(TEST_POD_NAME.to_string());
+        pod.status = Some(PodStatus {
+            phase: Some("Failed".to_string()),
+            container_statuses: Some(vec![ContainerStatus {
+                name: "build".to_string(),
+                state: Some(ContainerState {
+                    terminated: Some(ContainerStateTerminated {
+                        exit_code: 42,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let err = batch_pod_outcome(&pod).unwrap().unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::ContainerExit(42));
+        assert_eq!(error_category(&err).exit_code(), 142);
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_readonly_sidecar_volume() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            sidecar_image: Some("fluent/fluent-bit:2.0".to_string()),
+            sidecar_volume_readonly: true,
+            ..mock_request()
+        };
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let containers = pod.spec.unwrap().containers;
+        assert_eq!(containers.len(), 2);
+        let main_mount = &containers[0].volume_mounts.as_ref().unwrap()[0];
+        assert_eq!(main_mount.name, TEST_POD_NAME);
+        assert_eq!(main_mount.read_only, None);
+        assert_eq!(containers[1].name, "sidecar");
+        assert_eq!(
+            containers[1].image.as_deref(),
+            Some("fluent/fluent-bit:2.0")
+        );
+        let sidecar_mount = &containers[1].volume_mounts.as_ref().unwrap()[0];
+        assert_eq!(sidecar_mount.name, TEST_POD_NAME);
+        assert_eq!(sidecar_mount.mount_path, "/etc/test_mount");
+        assert_eq!(sidecar_mount.read_only, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_delete_guarded_by_required_labels() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            additional_labels: vec!["tenant=copr".to_string()],
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+
+        let required = vec![parse_required_label("tenant=copr").unwrap()];
+        assert!(missing_required_labels(&pod, &required).is_empty());
+
+        //pod of other tenant lacks the required labels
+        let required = vec![
+            parse_required_label("tenant=koji").unwrap(),
+            parse_required_label("team=infra").unwrap(),
+        ];
+        assert_eq!(
+            missing_required_labels(&pod, &required),
+            vec!["tenant=koji".to_string(), "team=infra".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_reports_existing_pod() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            idempotency_key: Some("job-42".to_string()),
+            ..mock_request()
+        };
+        let mut pending = generate_pod_resource(&mock_command, "test_ns", "resalloc-1", "", false)
+            .await
+            .unwrap();
+        pending.status = Some(PodStatus {
+            phase: Some("Pending".to_string()),
+            ..Default::default()
+        });
+        let mut running = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        stamp_allocation_id(&mut running.metadata, "9a1884fb");
+        running.status = Some(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+
+        //the second attempt reports the running pod instead of allocating a new one
+        assert!(existing_allocation(&[pending.clone()]).is_none());
+        let pods = vec![pending, running];
+        let (existing, pod_ip) = existing_allocation(&pods).unwrap();
+        assert_eq!(existing.metadata.name.as_deref(), Some(TEST_POD_NAME));
+        assert_eq!(
+            existing
+                .metadata
+                .labels
+                .as_ref()
+                .unwrap()
+                .get("resalloc.idempotency-key"),
+            Some(&"job-42".to_string())
+        );
+        assert_eq!(pod_ip, "10.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_grpc_readiness_probe() {
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            readiness_grpc: Some(parse_grpc_probe("9090:build.Health").unwrap()),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let probe = pod.spec.unwrap().containers[0]
+            .readiness_probe
+            .clone()
+            .unwrap();
+        let grpc = probe.grpc.unwrap();
+        assert_eq!(grpc.port, 9090);
+        assert_eq!(grpc.service.as_deref(), Some("build.Health"));
+        assert!(probe.tcp_socket.is_none() && probe.http_get.is_none());
+
+        //service TEST_POD_NAME is optional
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            readiness_grpc: Some(parse_grpc_probe("9090").unwrap()),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let grpc = pod.spec.unwrap().containers[0]
+            .readiness_probe
+            .clone()
+            .unwrap()
+            .grpc
+            .unwrap();
+        assert_eq!(grpc.service, None);
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_conflicting_node_selector() {
+        let mut mock_command = AllocationRequest {
+            timeout: 120,
+            node_selector: vec![
+                "kubernetes.io/arch=arm64".to_string(),
+                "kubernetes.io/arch=amd64".to_string(),
+            ],
+            ..mock_request()
+        };
+        let err = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "conflicting node selector 'kubernetes.io/arch', both 'arm64' and 'amd64' are specified"
+        );
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+
+        //repeating the same pair is harmless
+        mock_command.node_selector[1] = "kubernetes.io/arch=arm64".to_string();
+        assert!(
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_scheduling_file() {
+        let scheduling = r#"nodeSelector:
+  kubernetes.io/arch: amd64
+  example.com/pool: build
+tolerations:
+- key: example.com/dedicated
+  operator: Equal
+  value: build
+  effect: NoSchedule
+affinity:
+  nodeAffinity:
+    requiredDuringSchedulingIgnoredDuringExecution:
+      nodeSelectorTerms:
+      - matchExpressions:
+        - key: example.com/disk
+          operator: In
+          values:
+          - ssd
+"#;
+        let path =
+            std::env::temp_dir().join(format!("resalloc-scheduling-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, scheduling).unwrap();
+        let mock_command = AllocationRequest {
+            timeout: 120,
+            arch: Some(Arch::Arm64),
+            spot: true,
+            scheduling_file: Some(path.clone()),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let spec = pod.spec.unwrap();
+        let tolerations = spec.tolerations.unwrap();
+        assert_eq!(tolerations[0].key.as_deref(), Some("example.com/dedicated"));
+        //spot tolerations extend the ones of file
+        assert!(tolerations.len() > 1);
+        let node_affinity = spec.affinity.unwrap().node_affinity.unwrap();
+        let required = node_affinity
+            .required_during_scheduling_ignored_during_execution
+            .unwrap();
+        assert_eq!(
+            required.node_selector_terms[0]
+                .match_expressions
+                .as_ref()
+                .unwrap()[0]
+                .key,
+            "example.com/disk"
+        );
+        //flags take precedence over the file
+        let node_selector = spec.node_selector.unwrap();
+        assert_eq!(node_selector.get("kubernetes.io/arch").unwrap(), "arm64");
+        assert_eq!(node_selector.get("example.com/pool").unwrap(), "build");
+    }
+
+    #[tokio::test]
+    async fn test_allocate_through_library_api() {
+        let request = AllocationRequest {
+            timeout: 120,
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert!(allocation.name.starts_with("resalloc-"));
+        assert_eq!(allocation.allocation_id, allocation_id(&allocation.name));
+        assert_eq!(allocation.ip, Some("10.0.0.1".to_string()));
+        assert_eq!(allocation.exit_code, None);
+        assert_eq!(
+            allocation.resources,
+            vec![
+                CreatedResource {
+                    kind: "PersistentVolumeClaim".to_string(),
+                    name: allocation.name.clone(),
+                },
+                CreatedResource {
+                    kind: "Pod".to_string(),
+                    name: allocation.name.clone(),
+                },
+            ]
+        );
+        assert_eq!(
+            cluster.pods()[0].metadata.name,
+            Some(allocation.name.clone())
+        );
+        assert_eq!(
+            cluster.pvcs()[0].metadata.name,
+            Some(allocation.name.clone())
+        );
+
+        let delete_command = DeallocationRequest {
+            name: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        };
+        let deleted = deallocate_with(&cluster, &delete_command).await.unwrap();
+        assert_eq!(deleted[0].name, allocation.name);
+        assert!(cluster.pods().is_empty());
+        assert!(cluster.pvcs().is_empty());
+
+        //the pod never runs, it's cleaned up once timed out
+        let request = AllocationRequest {
+            timeout: 0,
+            ..request
+        };
+        let cluster = FakeCluster::new();
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Timeout);
+        assert!(cluster.pods().is_empty());
+        assert!(cluster.pvcs().is_empty());
+
+        //invalid request never reaches the cluster
+        let request = AllocationRequest {
+            additional_volume_class: None,
+            ..request
+        };
+        let err = allocate(&request, "test_ns").await.unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+    }
+
+    #[tokio::test]
+    async fn test_allocate_with_fake_cluster() {
+        let request = AllocationRequest {
+            timeout: 0,
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let running = |pod_ip: Option<&str>| PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: pod_ip.map(str::to_string),
+            ..Default::default()
+        };
+
+        //success, the pod ip is reported and resources are kept
+        let cluster = FakeCluster::new().with_pod_status(running(Some("10.0.0.1")));
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert_eq!(allocation.ip, Some("10.0.0.1".to_string()));
+        assert_eq!(cluster.pods().len(), 1);
+        assert_eq!(cluster.pvcs().len(), 1);
+
+        //timeout, the pod never runs and everything is cleaned up
+        let cluster = FakeCluster::new();
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Timeout);
+        assert!(cluster.pods().is_empty());
+        assert!(cluster.pvcs().is_empty());
+
+        //empty ip, the running pod is cleaned up
+        let cluster = FakeCluster::new().with_pod_status(running(None));
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "container ip address empty");
+        assert!(cluster.pods().is_empty());
+        assert!(cluster.pvcs().is_empty());
+
+        //no wait, the name is returned although the pod never runs
+        let no_wait = AllocationRequest {
+            no_wait: true,
+            ..request.clone()
+        };
+        let cluster = FakeCluster::new();
+        let allocation = allocate_with(&cluster, &no_wait, "test_ns").await.unwrap();
+        assert_eq!(allocation.ip, None);
+        assert_eq!(allocation.exit_code, None);
+        assert_eq!(cluster.pods()[0].metadata.name, Some(allocation.name));
+        assert_eq!(cluster.pvcs().len(), 1);
+
+        //evicted right after getting an ip, the dead ip is not reported
+        let cluster = FakeCluster::new()
+            .with_pod_status(running(Some("10.0.0.1")))
+            .with_pod_status_after_ready(PodStatus {
+                phase: Some("Failed".to_string()),
+                reason: Some("Evicted".to_string()),
+                message: Some("The node was low on resource: memory.".to_string()),
+                pod_ip: Some("10.0.0.1".to_string()),
+                ..Default::default()
+            });
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("is Failed (Evicted: The node was low on resource: memory.) after getting ip address 10.0.0.1"));
+        assert!(cluster.pods().is_empty());
+        assert!(cluster.pvcs().is_empty());
+    }
+
+    //manifests are reviewed through snapshot diffs, run `cargo insta review` after changing templates
+    #[tokio::test]
+    async fn test_manifest_snapshots() {
+        let namespace = "test_ns";
+        let base = AllocationRequest {
+            timeout: 120,
+            ..mock_request()
+        };
+        let with_volume = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            additional_pvc_labels: vec![
+                "failure-domain.beta.kubernetes.io/region=region1".to_string()
+            ],
+            ..base.clone()
+        };
+        let cases = [
+            ("pod_without_volume", base.clone(), false),
+            (
+                "pod_with_labels_and_node_selector",
+                AllocationRequest {
+                    additional_labels: vec![
+                        "failure-domain.beta.kubernetes.io/region=region1".to_string()
+                    ],
+                    node_selector: vec!["kubernetes.io/arch=amd64".to_string()],
+                    ..base.clone()
+                },
+                false,
+            ),
+            ("pod_with_volume", with_volume.clone(), true),
+            (
+                "pod_with_host_network",
+                AllocationRequest {
+                    host_network: true,
+                    ..base.clone()
+                },
+                false,
+            ),
+            (
+                "pod_with_volume_and_secret",
+                AllocationRequest {
+                    secret: Some(k8s_openapi::api::core::v1::VolumeMount {
+                        mount_path: "/home/copr/server.crt".to_string(),
+                        name: "copr-secrets".to_string(),
+                        sub_path: Some("server-crt".to_string()),
+                        ..Default::default()
+                    }),
+                    ..with_volume.clone()
+                },
+                true,
+            ),
+        ];
+        for (snapshot, command, additional_volume) in cases {
+            let pod = generate_pod_resource(
+                &command,
+                namespace,
+                TEST_POD_NAME,
+                TEST_POD_NAME,
+                additional_volume,
+            )
+            .await
+            .unwrap();
+            insta::assert_snapshot!(snapshot, serde_yaml::to_string(&pod).unwrap());
+        }
+
+        //manifest printed by dry run, secret is loaded as environment variables and mounted as files
+        let with_env_from_secret = AllocationRequest {
+            env_from_secret: vec!["copr-env".to_string(), "copr-secrets".to_string()],
+            secret: Some(k8s_openapi::api::core::v1::VolumeMount {
+                mount_path: "/home/copr/server.crt".to_string(),
+                name: "copr-secrets".to_string(),
+                sub_path: Some("server-crt".to_string()),
+                ..Default::default()
+            }),
+            ..base.clone()
+        };
+        let pod = generate_pod_resource(
+            &with_env_from_secret,
+            namespace,
+            TEST_POD_NAME,
+            TEST_POD_NAME,
+            false,
+        )
+        .await
+        .unwrap();
+        insta::assert_snapshot!(
+            "pod_with_env_from_secret_and_secret_mount",
+            serde_yaml::to_string(&pod).unwrap()
+        );
+
+        let pvc = generate_pvc_resource(&with_volume, namespace, TEST_POD_NAME)
+            .await
+            .unwrap();
+        insta::assert_snapshot!("pvc_with_labels", serde_yaml::to_string(&pvc).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_secret_mount_with_and_without_sub_path() {
+        let whole = parse_volume_mount("/etc/copr:copr-secrets").unwrap();
+        assert_eq!(whole.sub_path, None);
+        let file = parse_volume_mount("/home/copr/server.crt:copr-secrets:server-crt").unwrap();
+        assert_eq!(file.sub_path, Some("server-crt".to_string()));
+        assert!(parse_volume_mount("/etc/copr").is_err());
+        assert!(parse_volume_mount("/etc/copr:").is_err());
+
+        for (secret, sub_path) in [(whole, None), (file, Some("server-crt"))] {
+            let mock_command = AllocationRequest {
+                secret: Some(secret.clone()),
+                ..mock_request()
+            };
+            let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+                .await
+                .unwrap();
+            let mounts = pod.spec.unwrap().containers[0]
+                .volume_mounts
+                .clone()
+                .unwrap();
+            assert_eq!(mounts.len(), 1);
+            assert_eq!(mounts[0].mount_path, secret.mount_path);
+            assert_eq!(mounts[0].name, "copr-secrets");
+            assert_eq!(mounts[0].sub_path.as_deref(), sub_path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_request_reports_all_conflicts() {
+        let mock_command = AllocationRequest {
+            cpu_resource: "1".to_string(),
+            memory_resource: "2Gi".to_string(),
+            ..mock_request()
+        };
+        assert!(validate_request(&mock_command).is_ok());
+
+        let conflicting = AllocationRequest {
+            windows: true,
+            sandbox: Some(Sandbox::Gvisor),
+            wait_for: WaitFor::Succeeded,
+            restart_policy: Some(RestartPolicy::Always),
+            ..mock_command.clone()
+        };
+        let err = validate_request(&conflicting).unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert_eq!(
+            err.to_string(),
+            "sandbox gvisor is not supported by windows pod; waiting for succeeded pod conflicts with restart policy Always"
+        );
+
+        //nodes with many gpus are left to the scheduler, the MIG instances are bound by a gpu
+        let gpus = AllocationRequest {
+            gpu: Some(16),
+            ..mock_command.clone()
+        };
+        assert!(validate_request(&gpus).is_ok());
+        let mig = AllocationRequest {
+            mig_profile: Some("1g.5gb".to_string()),
+            gpu: Some(8),
+            ..mock_command.clone()
+        };
+        assert_eq!(
+            validate_request(&mig).unwrap_err().to_string(),
+            "mig instance count 8 exceeds the maximum 7 of a gpu"
+        );
+
+        let partial_volume = AllocationRequest {
+            additional_volume_size: Some("10Gi".to_string()),
+            mig_profile: Some("1g.5gb".to_string()),
+            ..mock_command
+        };
+        let err = validate_request(&partial_volume).unwrap_err();
+        assert!(err.to_string().contains("must be specified together"));
+        assert!(err.to_string().contains("mig profile requires gpu"));
+    }
+
+    #[tokio::test]
+    async fn test_create_secret_from_files() {
+        let dir = std::env::temp_dir().join(format!("resalloc-secret-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ca.crt"), "hello").unwrap();
+        std::fs::write(dir.join("token"), "world").unwrap();
+        let source = parse_secret_source(&format!(
+            "copr-secrets:ca.crt={},token={}",
+            dir.join("ca.crt").display(),
+            dir.join("token").display()
+        ))
+        .unwrap();
+        assert_eq!(source.name, "copr-secrets");
+        assert!(parse_secret_source("copr-secrets:ca.crt").is_err());
+
+        let request = AllocationRequest {
+            timeout: 1,
+            create_secret: Some(source),
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+
+        //the secrets of concurrent allocations don't conflict
+        let concurrent = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.2".to_string()),
+            ..Default::default()
+        });
+        allocate_with(&concurrent, &request, "test_ns")
+            .await
+            .unwrap();
+        allocate_with(&concurrent, &request, "test_ns")
+            .await
+            .unwrap();
+        assert_eq!(concurrent.secrets().len(), 2);
+
+        //the secrets are deleted once the allocation fails after creating them
+        let request_with_volume = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..request.clone()
+        };
+        for (request, operation) in [
+            (&request_with_volume, "create_pvc"),
+            (&request, "set_secret_owner_references"),
+        ] {
+            let failing = FakeCluster::new()
+                .with_pod_status(PodStatus {
+                    phase: Some("Running".to_string()),
+                    pod_ip: Some("10.0.0.3".to_string()),
+                    ..Default::default()
+                })
+                .with_failing_operation(operation);
+            let err = allocate_with(&failing, request, "test_ns")
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains(operation), "{}", err);
+            assert!(failing.secrets().is_empty(), "{}", operation);
+            assert!(failing.pods().is_empty(), "{}", operation);
+            assert!(failing.pvcs().is_empty(), "{}", operation);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let secrets = cluster.secrets();
+        assert_eq!(secrets.len(), 1);
+        let secret = serde_json::to_value(&secrets[0]).unwrap();
+        assert_eq!(secret["data"]["ca.crt"], "aGVsbG8=");
+        assert_eq!(secret["data"]["token"], "d29ybGQ=");
+        let owner = &secrets[0].metadata.owner_references.as_ref().unwrap()[0];
+        assert_eq!(owner.kind, "Pod");
+        assert_eq!(owner.name, allocation.name);
+
+        let pod = cluster.pods().remove(0);
+        let mount = &pod.spec.clone().unwrap().containers[0]
+            .volume_mounts
+            .clone()
+            .unwrap()[0];
+        assert_eq!(mount.mount_path, "/run/secrets/copr-secrets");
+        assert_eq!(mount.sub_path, None);
+        let volume = &pod.spec.unwrap().volumes.unwrap()[0];
+        assert_eq!(volume.name, "copr-secrets");
+        assert_eq!(
+            volume.secret.clone().unwrap().secret_name,
+            secrets[0].metadata.name
+        );
+        assert_eq!(
+            secrets[0].metadata.name,
+            Some(format!("{}-copr-secrets", allocation.name))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_image_pull_policy_resolved_by_config_rules() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+image-pull-policy:
+- namespace: dev
+  policy: Never
+- image-suffix: ":latest"
+  policy: Always
+"#,
+        )
+        .unwrap();
+        let mut mock_command = AllocationRequest {
+            image_tag: "docker.io/organization/builder:latest".to_string(),
+            cpu_resource: "1".to_string(),
+            memory_resource: "2Gi".to_string(),
+            config,
+            ..mock_request()
+        };
+        let policy = |pod: Pod| pod.spec.unwrap().containers[0].image_pull_policy.clone();
+
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(policy(pod), Some("Always".to_string()));
+        let pod = generate_pod_resource(&mock_command, "dev", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(policy(pod), Some("Never".to_string()));
+
+        mock_command.image_tag = "docker.io/organization/builder:1.0.5".to_string();
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(policy(pod), Some("IfNotPresent".to_string()));
+
+        //explicit flag takes precedence over the rules
+        mock_command.image_pull_policy = Some(ImagePullPolicy::Always);
+        let pod = generate_pod_resource(&mock_command, "dev", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(policy(pod), Some("Always".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_timings_reported_on_success() {
+        let mut request = AllocationRequest {
+            timeout: 1,
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert!(allocation.elapsed.unwrap() < std::time::Duration::from_secs(1));
+
+        let elapsed = std::time::Duration::from_millis(12345);
+        let mut output = Vec::new();
+        report_timing(&request, &mut output, &allocation.name, "ready", elapsed).unwrap();
+        assert!(output.is_empty());
+        request.timings = true;
+        report_timing(&request, &mut output, &allocation.name, "ready", elapsed).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("pod {} ready in 12.3s\n", allocation.name)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_run_as_non_root() {
+        let mut mock_command = AllocationRequest {
+            run_as_non_root: true,
+            run_as_user: Some(1000),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let security_context = pod.spec.unwrap().containers[0]
+            .security_context
+            .clone()
+            .unwrap();
+        assert_eq!(security_context.run_as_non_root, Some(true));
+        assert_eq!(security_context.run_as_user, Some(1000));
+        assert_eq!(security_context.privileged, Some(false));
+        assert!(security_warnings(&mock_command).is_empty());
+
+        mock_command.privileged = true;
+        assert_eq!(security_warnings(&mock_command).len(), 1);
+
+        mock_command.run_as_user = Some(0);
+        let err = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_read_only_root_fs() {
+        let mock_command = AllocationRequest {
+            read_only_root_fs: true,
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        let container = &spec.containers[0];
+        assert_eq!(
+            container
+                .security_context
+                .as_ref()
+                .unwrap()
+                .read_only_root_filesystem,
+            Some(true)
+        );
+        let mount = &container.volume_mounts.as_ref().unwrap()[0];
+        assert_eq!(mount.mount_path, "/tmp");
+        let volume = &spec.volumes.unwrap()[0];
+        assert_eq!(volume.name, mount.name);
+        assert!(volume.empty_dir.is_some());
+
+        //the volume mounted by user is kept
+        let mock_command = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/tmp".to_string()),
+            ..mock_command
+        };
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let spec = pod.spec.unwrap();
+        assert_eq!(spec.containers[0].volume_mounts.as_ref().unwrap().len(), 1);
+        assert!(spec.volumes.unwrap().iter().all(|v| v.empty_dir.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_no_privilege_escalation() {
+        let mut mock_command = AllocationRequest {
+            no_privilege_escalation: true,
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let yaml = serde_yaml::to_string(&pod).unwrap();
+        assert!(yaml.contains("    securityContext:\n      allowPrivilegeEscalation: false\n      privileged: false\n"));
+
+        mock_command.privileged = true;
+        let err = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert!(err.to_string().contains("privileged mode"));
+    }
+
+    #[tokio::test]
+    async fn test_pvc_not_bound_within_volume_timeout() {
+        let request = AllocationRequest {
+            timeout: 120,
+            volume_timeout: Some(0),
+            additional_volume_class: Some("slow-nfs".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        //pvc stays pending
+        let cluster = FakeCluster::new();
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Timeout);
+        assert!(err.to_string().contains("storage class slow-nfs"));
+        assert!(cluster.pvcs().is_empty());
+        assert!(cluster.pods().is_empty());
+
+        let cluster = FakeCluster::new()
+            .with_pvc_status(PersistentVolumeClaimStatus {
+                phase: Some("Bound".to_string()),
+                ..Default::default()
+            })
+            .with_pod_status(PodStatus {
+                phase: Some("Running".to_string()),
+                pod_ip: Some("10.0.0.1".to_string()),
+                ..Default::default()
+            });
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert_eq!(allocation.ip, Some("10.0.0.1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_env() {
+        assert!(parse_env("COPR_BUILDER_ID").is_err());
+        assert!(parse_env("=1").is_err());
+        let mock_command = AllocationRequest {
+            env: vec![
+                parse_env("COPR_BUILDER_ID=42").unwrap(),
+                parse_env("COPR_URL=https://copr.example.com:8443/a=b").unwrap(),
+                parse_env("EMPTY=").unwrap(),
+            ],
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let env: Vec<(String, Option<String>)> = pod.spec.unwrap().containers[0]
+            .env
+            .clone()
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.name, e.value))
+            .collect();
+        assert_eq!(
+            env,
+            vec![
+                ("COPR_BUILDER_ID".to_string(), Some("42".to_string())),
+                (
+                    "COPR_URL".to_string(),
+                    Some("https://copr.example.com:8443/a=b".to_string())
+                ),
+                ("EMPTY".to_string(), Some("".to_string())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_multiple_emptydirs() {
+        assert!(parse_emptydir_mount("/cache:1Gi:Disk").is_err());
+        assert!(parse_emptydir_mount(":1Gi").is_err());
+        let mock_command = AllocationRequest {
+            emptydir_mount: vec![
+                parse_emptydir_mount("/tmp:512Mi:Memory").unwrap(),
+                parse_emptydir_mount("/cache:10Gi").unwrap(),
+                parse_emptydir_mount("/scratch").unwrap(),
+            ],
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        let mounts: Vec<(String, String)> = spec.containers[0]
+            .volume_mounts
+            .clone()
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.mount_path, m.name))
+            .collect();
+        assert_eq!(
+            mounts,
+            vec![
+                ("/tmp".to_string(), "emptydir-0".to_string()),
+                ("/cache".to_string(), "emptydir-1".to_string()),
+                ("/scratch".to_string(), "emptydir-2".to_string()),
+            ]
+        );
+        let volumes = spec.volumes.unwrap();
+        let emptydir = |index: usize| volumes[index].empty_dir.clone().unwrap();
+        assert_eq!(emptydir(0).medium.as_deref(), Some("Memory"));
+        assert_eq!(emptydir(0).size_limit.unwrap().0, "512Mi");
+        assert_eq!(emptydir(1).medium, None);
+        assert_eq!(emptydir(1).size_limit.unwrap().0, "10Gi");
+        assert_eq!(emptydir(2), Default::default());
+
+        let duplicated = AllocationRequest {
+            emptydir_mount: vec![
+                parse_emptydir_mount("/cache").unwrap(),
+                parse_emptydir_mount("/cache:1Gi").unwrap(),
+            ],
+            ..mock_command
+        };
+        assert!(validate_request(&duplicated).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resources_created_in_requested_namespace() {
+        let request = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            headless: true,
+            ..mock_request()
+        };
+        //the namespace of the kubeconfig context only matters when --namespace is missing
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+        allocate_with(&cluster, &request, "builders").await.unwrap();
+        let namespaces: Vec<Option<String>> = cluster
+            .pods()
+            .into_iter()
+            .map(|p| p.metadata.namespace)
+            .chain(cluster.pvcs().into_iter().map(|p| p.metadata.namespace))
+            .chain(cluster.services().into_iter().map(|s| s.metadata.namespace))
+            .collect();
+        assert_eq!(namespaces, vec![Some("builders".to_string()); 3]);
+    }
+
+    #[tokio::test]
+    async fn test_configmap_mounted_together_with_secret() {
+        assert!(parse_configmap_mount("/opt/scripts").is_err());
+        let mock_command = AllocationRequest {
+            secret: Some(parse_volume_mount("/etc/copr:copr").unwrap()),
+            configmap: Some(parse_configmap_mount("/opt/scripts/build.sh:copr:build.sh").unwrap()),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        let mounts: Vec<(String, String, Option<String>)> = spec.containers[0]
+            .volume_mounts
+            .clone()
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.mount_path, m.name, m.sub_path))
+            .collect();
+        assert_eq!(
+            mounts,
+            vec![
+                ("/etc/copr".to_string(), "copr".to_string(), None),
+                (
+                    "/opt/scripts/build.sh".to_string(),
+                    "configmap-copr".to_string(),
+                    Some("build.sh".to_string())
+                ),
+            ]
+        );
+        let volumes = spec.volumes.unwrap();
+        assert_eq!(volumes.len(), 2);
+        assert_eq!(
+            volumes[0].secret.clone().unwrap().secret_name.as_deref(),
+            Some("copr")
+        );
+        assert_eq!(volumes[1].name, "configmap-copr");
+        assert_eq!(
+            serde_json::to_value(&volumes[1].config_map).unwrap()["name"],
+            "copr"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trace_id_annotated_on_pod_and_pvc() {
+        let request = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+        allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        let annotations = cluster
+            .pods()
+            .into_iter()
+            .map(|p| p.metadata.annotations)
+            .chain(cluster.pvcs().into_iter().map(|p| p.metadata.annotations));
+        for annotation in annotations {
+            assert_eq!(
+                annotation
+                    .unwrap()
+                    .get(TRACE_ID_ANNOTATION)
+                    .map(String::as_str),
+                Some("4bf92f3577b34da6a3ce929d0e0e4736")
+            );
+        }
+        assert_eq!(cluster.pods().len() + cluster.pvcs().len(), 2);
+
+        //the failed allocation reports the trace id
+        let request = AllocationRequest {
+            timeout: 0,
+            ..request
+        };
+        let cluster = FakeCluster::new();
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error_trace_id(&err),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+        assert_eq!(
+            error_json(&err)["trace_id"],
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+
+        //the annotation is validated as the other ones
+        let request = AllocationRequest {
+            trace_id: Some("0".repeat(MAX_ANNOTATION_SIZE)),
+            ..request
+        };
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert!(err.to_string().contains(TRACE_ID_ANNOTATION));
+    }
+
+    #[tokio::test]
+    async fn test_image_pull_secret_from_file() {
+        let path =
+            std::env::temp_dir().join(format!("resalloc-dockercfg-{}", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"{"auths":{"registry.example.com":{"auth":"dXNlcjpwYXNz"}}}"#,
+        )
+        .unwrap();
+        let request = AllocationRequest {
+            image_tag: "registry.example.com/openeuler:22.03".to_string(),
+            image_pull_secret_from_file: Some(path.clone()),
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+
+        let secrets = cluster.secrets();
+        assert_eq!(secrets.len(), 1);
+        assert_eq!(
+            secrets[0].metadata.name.clone().unwrap(),
+            format!("{}-pull-secret", allocation.name)
+        );
+        assert_eq!(
+            secrets[0].type_.as_deref(),
+            Some("kubernetes.io/dockerconfigjson")
+        );
+        assert!(secrets[0]
+            .data
+            .as_ref()
+            .unwrap()
+            .contains_key(".dockerconfigjson"));
+        let owner = &secrets[0].metadata.owner_references.as_ref().unwrap()[0];
+        assert_eq!(owner.name, allocation.name);
+
+        let pod = cluster.pods().remove(0);
+        let pull_secrets = serde_json::to_value(pod.spec.unwrap().image_pull_secrets).unwrap();
+        assert_eq!(
+            pull_secrets[0]["name"],
+            secrets[0].metadata.name.clone().unwrap()
+        );
+
+        //malformed docker config is rejected before anything is created
+        std::fs::write(&path, "not json").unwrap();
+        let cluster = FakeCluster::new();
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert!(cluster.secrets().is_empty());
+        assert!(cluster.pods().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_command_and_args() {
+        let mock_command = mock_request();
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let container = &pod.spec.unwrap().containers[0];
+        assert_eq!(container.command, None);
+        assert_eq!(container.args, None);
+
+        let command = vec!["/bin/sh".to_string(), "-c".to_string()];
+        let args = vec![
+            "echo 'key: value' && exit 0 # done".to_string(),
+            "--flag=\"quoted\"".to_string(),
+            "true".to_string(),
+        ];
+        let mock_command = AllocationRequest {
+            command: command.clone(),
+            args: args.clone(),
+            ..mock_command
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let container = &pod.spec.unwrap().containers[0];
+        assert_eq!(container.command, Some(command));
+        assert_eq!(container.args, Some(args));
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_tolerations() {
+        assert!(parse_toleration("dedicated=builders:NoRun").is_err());
+        assert!(parse_toleration("=builders:NoSchedule").is_err());
+        let mock_command = AllocationRequest {
+            toleration: vec![
+                parse_toleration("dedicated=builders:NoSchedule").unwrap(),
+                parse_toleration("gpu:NoExecute").unwrap(),
+                parse_toleration("example.com/maintenance").unwrap(),
+            ],
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let tolerations: Vec<(String, String, Option<String>, Option<String>)> = pod
+            .spec
+            .unwrap()
+            .tolerations
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.key.unwrap(), t.operator.unwrap(), t.value, t.effect))
+            .collect();
+        assert_eq!(
+            tolerations,
+            vec![
+                (
+                    "dedicated".to_string(),
+                    "Equal".to_string(),
+                    Some("builders".to_string()),
+                    Some("NoSchedule".to_string())
+                ),
+                (
+                    "gpu".to_string(),
+                    "Exists".to_string(),
+                    None,
+                    Some("NoExecute".to_string())
+                ),
+                (
+                    "example.com/maintenance".to_string(),
+                    "Exists".to_string(),
+                    None,
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_preemption_policy() {
+        let mock_command = mock_request();
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(pod.spec.unwrap().preemption_policy, None);
+
+        for (policy, expected) in [
+            (PreemptionPolicy::Never, "Never"),
+            (
+                PreemptionPolicy::PreemptLowerPriority,
+                "PreemptLowerPriority",
+            ),
+        ] {
+            let mock_command = AllocationRequest {
+                preemption_policy: Some(policy),
+                ..mock_command.clone()
+            };
+            let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+                .await
+                .unwrap();
+            assert_eq!(
+                pod.spec.unwrap().preemption_policy.as_deref(),
+                Some(expected)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_tolerates_node_conditions() {
+        let mock_command = AllocationRequest {
+            tolerate_not_ready: Some(300),
+            tolerate_unreachable: Some(120),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let tolerations = pod.spec.unwrap().tolerations.unwrap();
+        assert_eq!(tolerations.len(), 2);
+        for (toleration, (key, seconds)) in tolerations.iter().zip([
+            ("node.kubernetes.io/not-ready", 300),
+            ("node.kubernetes.io/unreachable", 120),
+        ]) {
+            assert_eq!(toleration.key.as_deref(), Some(key));
+            assert_eq!(toleration.operator.as_deref(), Some("Exists"));
+            assert_eq!(toleration.effect.as_deref(), Some("NoExecute"));
+            assert_eq!(toleration.toleration_seconds, Some(seconds));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_node_affinity() {
+        assert!(parse_node_affinity("topology.kubernetes.io/region:Gt:1").is_err());
+        assert!(parse_node_affinity("topology.kubernetes.io/region:In").is_err());
+        assert!(parse_node_affinity("example.com/gpu:Exists:true").is_err());
+        let mock_command = AllocationRequest {
+            node_selector: vec!["kubernetes.io/arch=arm64".to_string()],
+            node_affinity: vec![
+                parse_node_affinity("topology.kubernetes.io/region:In:region1,region2").unwrap(),
+                parse_node_affinity("example.com/maintenance:DoesNotExist").unwrap(),
+            ],
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        assert_eq!(
+            spec.node_selector.unwrap()["kubernetes.io/arch"],
+            "arm64".to_string()
+        );
+        let terms = spec
+            .affinity
+            .unwrap()
+            .node_affinity
+            .unwrap()
+            .required_during_scheduling_ignored_during_execution
+            .unwrap()
+            .node_selector_terms;
+        assert_eq!(terms.len(), 1);
+        let expressions = terms[0].match_expressions.clone().unwrap();
+        assert_eq!(
+            expressions,
+            vec![
+                NodeSelectorRequirement {
+                    key: "topology.kubernetes.io/region".to_string(),
+                    operator: "In".to_string(),
+                    values: Some(vec!["region1".to_string(), "region2".to_string()]),
+                },
+                NodeSelectorRequirement {
+                    key: "example.com/maintenance".to_string(),
+                    operator: "DoesNotExist".to_string(),
+                    values: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_annotations() {
+        assert!(parse_annotation("example.com/cost-center").is_err());
+        assert!(parse_annotation("=value").is_err());
+        let mock_command = AllocationRequest {
+            annotation: vec![
+                parse_annotation("example.com/cost-center=builders").unwrap(),
+                parse_annotation(r#"k8s.v1.cni.cncf.io/networks=[{"name":"net","ips":["a=b"]}]"#)
+                    .unwrap(),
+            ],
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let annotations = pod.metadata.annotations.unwrap();
+        assert_eq!(annotations["example.com/cost-center"], "builders");
+        assert_eq!(
+            annotations["k8s.v1.cni.cncf.io/networks"],
+            r#"[{"name":"net","ips":["a=b"]}]"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pvc_template_with_volume_limit() {
+        let mock_command = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            volume_limit: Some("20Gi".to_string()),
+            ..mock_request()
+        };
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        let resources = serde_json::to_value(pvc.spec.unwrap().resources).unwrap();
+        assert_eq!(resources["requests"]["storage"], "10Gi");
+        assert_eq!(resources["limits"]["storage"], "20Gi");
+
+        for (limit, valid) in [
+            ("10Gi", true),
+            ("11G", true),
+            ("9Gi", false),
+            ("lots", false),
+        ] {
+            let mock_command = AllocationRequest {
+                volume_limit: Some(limit.to_string()),
+                ..mock_command.clone()
+            };
+            assert_eq!(validate_request(&mock_command).is_ok(), valid, "{}", limit);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_service_account() {
+        let mock_command = AllocationRequest {
+            dry_run: true,
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(pod.spec.unwrap().service_account_name, None);
+
+        let mock_command = AllocationRequest {
+            service_account: Some("copr-builder".to_string()),
+            ..mock_command
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let rendered = serde_yaml::to_string(&pod).unwrap();
+        assert!(rendered.contains("serviceAccountName: copr-builder"));
+        assert_eq!(
+            pod.spec.unwrap().service_account_name.as_deref(),
+            Some("copr-builder")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timeout_error_contains_pod_conditions() {
+        let request = AllocationRequest {
+            timeout: 0,
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Pending".to_string()),
+            conditions: Some(vec![k8s_openapi::api::core::v1::PodCondition {
+                type_: "Initialized".to_string(),
+                status: "False".to_string(),
+                reason: Some("ContainersNotInitialized".to_string()),
+                message: Some("containers with incomplete status: [init]".to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Timeout);
+        assert!(err.to_string().ends_with(
+            r#", pod conditions: Initialized=False reason=ContainersNotInitialized message="containers with incomplete status: [init]""#
+        ));
+        assert!(cluster.pods().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_user_group_and_fs_group() {
+        let mock_command = mock_request();
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        let security_context = spec.containers[0].security_context.clone().unwrap();
+        assert_eq!(security_context.run_as_user, None);
+        assert_eq!(security_context.run_as_group, None);
+        assert_eq!(spec.security_context, None);
+
+        let mock_command = AllocationRequest {
+            privileged: true,
+            run_as_user: Some(1000),
+            run_as_group: Some(1001),
+            fs_group: Some(2000),
+            ..mock_command
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let spec = pod.spec.unwrap();
+        let security_context = spec.containers[0].security_context.clone().unwrap();
+        assert_eq!(security_context.privileged, Some(true));
+        assert_eq!(security_context.run_as_user, Some(1000));
+        assert_eq!(security_context.run_as_group, Some(1001));
+        assert_eq!(spec.security_context.unwrap().fs_group, Some(2000));
+    }
+
+    #[tokio::test]
+    async fn test_allocate_retries_on_pod_name_collision() {
+        let request = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let running = PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        };
+        let cluster = FakeCluster::new()
+            .with_pod_status(running.clone())
+            .with_pod_name_conflicts(1);
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert_eq!(allocation.ip, Some("10.0.0.1".to_string()));
+        //the pod taken the name is kept
+        let pods = cluster.pods();
+        assert_eq!(pods.len(), 2);
+        assert!(pods
+            .iter()
+            .any(|p| p.metadata.name == Some(allocation.name.clone())));
+        //pvc of the collided attempt is removed
+        let pvcs = cluster.pvcs();
+        assert_eq!(pvcs.len(), 1);
+        assert_eq!(pvcs[0].metadata.name, Some(allocation.name.clone()));
+
+        //the pod taken the name has a pvc with the same name, which is reused and must be kept
+        let cluster = FakeCluster::new()
+            .with_pod_status(running.clone())
+            .with_pvc_create_conflicts(1)
+            .with_pod_name_conflicts(1);
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        let pods = cluster.pods();
+        let pvcs = cluster.pvcs();
+        assert_eq!(pods.len(), 2);
+        assert_eq!(pvcs.len(), 2);
+        let existing = pods
+            .iter()
+            .find(|p| p.metadata.name != Some(allocation.name.clone()))
+            .unwrap();
+        assert!(pvcs
+            .iter()
+            .any(|p| p.metadata.name == existing.metadata.name));
+
+        //attempts are bounded, only the pods taken the names are left
+        let cluster = FakeCluster::new()
+            .with_pod_status(running)
+            .with_pod_name_conflicts(MAX_NAME_ATTEMPTS);
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().ends_with("already exists"));
+        assert_eq!(cluster.pods().len(), MAX_NAME_ATTEMPTS as usize);
+        assert!(cluster.pvcs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_pod_info_annotations() {
+        let mock_command = AllocationRequest {
+            cpu_resource: "2".to_string(),
+            memory_resource: "4Gi".to_string(),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(pod.metadata.annotations, None);
+
+        let mock_command = AllocationRequest {
+            pod_info_annotations: true,
+            ..mock_command
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let annotations = pod.metadata.annotations.unwrap();
+        assert_eq!(annotations["resalloc.image"], "openeuler/openeuler:22.03");
+        assert_eq!(annotations["resalloc.cpu.request"], "2");
+        assert_eq!(annotations["resalloc.cpu.limit"], "2");
+        assert_eq!(annotations["resalloc.memory.request"], "4Gi");
+        assert_eq!(annotations["resalloc.memory.limit"], "4Gi");
+
+        //the overrides are what the container is created with
+        let mock_command = AllocationRequest {
+            cpu_request: Some("500m".to_string()),
+            memory_limit: Some("8Gi".to_string()),
+            ..mock_command
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let annotations = pod.metadata.annotations.unwrap();
+        assert_eq!(annotations["resalloc.cpu.request"], "500m");
+        assert_eq!(annotations["resalloc.cpu.limit"], "2");
+        assert_eq!(annotations["resalloc.memory.request"], "4Gi");
+        assert_eq!(annotations["resalloc.memory.limit"], "8Gi");
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_capabilities() {
+        assert!(parse_capability("SYSADMIN").is_err());
+        assert!(parse_capability("sys_admin").is_err());
+        assert_eq!(parse_capability("CAP_SYS_ADMIN").unwrap(), "SYS_ADMIN");
+        let mock_command = AllocationRequest {
+            cap_add: vec![parse_capability("SYS_ADMIN").unwrap()],
+            cap_drop: vec![parse_capability("ALL").unwrap()],
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let security_context = pod.spec.unwrap().containers[0]
+            .security_context
+            .clone()
+            .unwrap();
+        assert_eq!(security_context.privileged, Some(false));
+        let capabilities = security_context.capabilities.unwrap();
+        assert_eq!(capabilities.add, Some(vec!["SYS_ADMIN".to_string()]));
+        assert_eq!(capabilities.drop, Some(vec!["ALL".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_strict_dry_run_validates_manifests() {
+        let request = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            restart_policy: Some(RestartPolicy::Never),
+            dry_run: true,
+            strict: true,
+            ..mock_request()
+        };
+        allocate_with(&FakeCluster::new(), &request, "test_ns")
+            .await
+            .unwrap();
+
+        let mut pod =
+            generate_pod_resource(&request, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let pvc = generate_pvc_resource(&request, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        assert!(validate_manifests(&pod, Some(&pvc)).is_ok());
+
+        let spec = pod.spec.as_mut().unwrap();
+        spec.restart_policy = Some("Sometimes".to_string());
+        spec.containers[0].image_pull_policy = Some("Daily".to_string());
+        let err = validate_manifests(&pod, Some(&pvc)).unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert_eq!(
+            err.to_string(),
+            "restartPolicy 'Sometimes' is invalid, it should be one of Always, OnFailure, Never; imagePullPolicy 'Daily' is invalid, it should be one of Always, IfNotPresent, Never"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_labels_applied_per_resource() {
+        let mock_command = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            headless: true,
+            additional_labels: vec!["tenant=copr".to_string()],
+            pod_label: vec!["role=builder".to_string()],
+            additional_pvc_labels: vec!["backup=daily".to_string()],
+            service_label: vec!["exposed=true".to_string()],
+            ..mock_request()
+        };
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        let service =
+            generate_service_resource(&mock_command, "test_ns", TEST_POD_NAME, "9a1884fb").unwrap();
+
+        let keys = |labels: Option<std::collections::BTreeMap<String, String>>| -> Vec<String> {
+            let mut keys: Vec<String> = labels
+                .unwrap()
+                .into_keys()
+                .filter(|k| ["tenant", "role", "backup", "exposed"].contains(&k.as_str()))
+                .collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(pod.metadata.labels), vec!["role", "tenant"]);
+        assert_eq!(keys(pvc.metadata.labels), vec!["backup", "tenant"]);
+        assert_eq!(keys(service.metadata.labels), vec!["exposed", "tenant"]);
+    }
+
+    #[tokio::test]
+    async fn test_termination_grace_period_used_on_deletion() {
+        let mock_command = mock_request();
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            pod.spec.as_ref().unwrap().termination_grace_period_seconds,
+            None
+        );
+        assert_eq!(pod_delete_params(&pod).grace_period_seconds, None);
+        assert_eq!(
+            cleanup_delete_params(&mock_command).grace_period_seconds,
+            None
+        );
+
+        let mock_command = AllocationRequest {
+            termination_grace_period: Some(300),
+            ..mock_command
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            pod.spec.as_ref().unwrap().termination_grace_period_seconds,
+            Some(300)
+        );
+        assert_eq!(pod_delete_params(&pod).grace_period_seconds, Some(300));
+        assert_eq!(
+            cleanup_delete_params(&mock_command).grace_period_seconds,
+            Some(300)
+        );
+        //explicit grace period of failed allocation takes precedence
+        let mock_command = AllocationRequest {
+            grace_period: Some(5),
+            ..mock_command
+        };
+        assert_eq!(
+            cleanup_delete_params(&mock_command).grace_period_seconds,
+            Some(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_pod_table_wide() {
+        let mock_command = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let mut pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let now = k8s_openapi::chrono::Utc::now();
+        pod.metadata.creation_timestamp =
+            Some(k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(
+                now - k8s_openapi::chrono::Duration::minutes(5),
+            ));
+        pod.spec.as_mut().unwrap().node_name = Some("node-1".to_string());
+        pod.status = Some(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+        let pending = Pod {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                name: Some("resalloc-pending".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let pods = [
+            PodSummary::from_pod(&pod, now),
+            PodSummary::from_pod(&pending, now),
+        ];
+
+        let mut out = Vec::new();
+        write_pod_table(&mut out, &pods, true).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        let columns = |line: &str| {
+            line.split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            columns(lines[0]),
+            vec!["NAME", "IP", "NODE", "STATUS", "AGE", "CPU", "MEMORY", "VOLUME"]
+        );
+        assert_eq!(
+            columns(lines[1]),
+            vec![
+                TEST_POD_NAME,
+                "10.0.0.1",
+                "node-1",
+                "Running",
+                "5m",
+                "100m",
+                "500Mi",
+                TEST_POD_NAME
+            ]
+        );
+        assert_eq!(columns(lines[2])[1], "<none>");
+        //columns are aligned
+        assert_eq!(lines[0].find("IP"), lines[1].find("10.0.0.1"));
+        assert_eq!(lines[0].find("VOLUME"), lines[1].rfind(TEST_POD_NAME));
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_separate_requests_and_limits() {
+        let mut mock_command = AllocationRequest {
+            cpu_resource: "1".to_string(),
+            memory_resource: "2Gi".to_string(),
+            cpu_request: Some("500m".to_string()),
+            memory_limit: Some("4Gi".to_string()),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let resources = pod.spec.unwrap().containers[0].resources.clone().unwrap();
+        let requests = resources.requests.unwrap();
+        let limits = resources.limits.unwrap();
+        assert_eq!(requests["cpu"].0, "500m");
+        assert_eq!(limits["cpu"].0, "1");
+        assert_eq!(requests["memory"].0, "2Gi");
+        assert_eq!(limits["memory"].0, "4Gi");
+
+        mock_command.memory_request = Some("8Gi".to_string());
+        let err = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert!(err
+            .to_string()
+            .contains("memory request 8Gi is greater than memory limit 4Gi"));
+    }
+
+    #[tokio::test]
+    async fn test_pvc_with_static_volume_name() {
+        let mut mock_command = AllocationRequest {
+            timeout: 120,
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            volume_name_static: Some("pv-build-cache".to_string()),
+            ..mock_request()
+        };
+        validate_request(&mock_command).unwrap();
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        let spec = pvc.spec.unwrap();
+        assert_eq!(spec.volume_name.as_deref(), Some("pv-build-cache"));
+        assert_eq!(spec.storage_class_name.as_deref(), Some(""));
+
+        mock_command.additional_volume_class = Some("local-storage".to_string());
+        let err = validate_request(&mock_command).unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert!(err.to_string().contains("static volume pv-build-cache"));
+
+        mock_command.allow_static_volume_class = true;
+        validate_request(&mock_command).unwrap();
+        let pvc = generate_pvc_resource(&mock_command, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap();
+        let spec = pvc.spec.unwrap();
+        assert_eq!(spec.volume_name.as_deref(), Some("pv-build-cache"));
+        assert_eq!(spec.storage_class_name.as_deref(), Some("local-storage"));
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_init_container() {
+        let mut mock_command = AllocationRequest {
+            cpu_resource: "1".to_string(),
+            memory_resource: "2Gi".to_string(),
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        assert!(pod.spec.unwrap().init_containers.is_none());
+
+        //resources default to the ones of container
+        mock_command.init_command = vec!["sh".to_string(), "-c".to_string()];
+        mock_command.init_args = vec!["cp -r /seed/. /etc/test_mount".to_string()];
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let spec = pod.spec.unwrap();
+        let init = &spec.init_containers.as_ref().unwrap()[0];
+        assert_eq!(init.name, "init");
+        assert_eq!(init.image, spec.containers[0].image);
+        assert_eq!(init.image_pull_policy, spec.containers[0].image_pull_policy);
+        assert_eq!(init.command, Some(mock_command.init_command.clone()));
+        assert_eq!(init.args, Some(mock_command.init_args.clone()));
+        assert_eq!(init.resources, spec.containers[0].resources);
+        assert_eq!(init.volume_mounts, spec.containers[0].volume_mounts);
+
+        mock_command.init_cpu = Some("250m".to_string());
+        mock_command.init_memory = Some("256Mi".to_string());
+        let pod =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let spec = pod.spec.unwrap();
+        let init_resources = spec.init_containers.unwrap()[0].resources.clone().unwrap();
+        let resources = spec.containers[0].resources.clone().unwrap();
+        for quantity in [
+            init_resources.limits.unwrap(),
+            init_resources.requests.unwrap(),
+        ] {
+            assert_eq!(quantity["cpu"].0, "250m");
+            assert_eq!(quantity["memory"].0, "256Mi");
+        }
+        assert_eq!(resources.limits.unwrap()["cpu"].0, "1");
+        assert_eq!(resources.requests.unwrap()["memory"].0, "2Gi");
+
+        mock_command.init_memory = Some("lots".to_string());
+        let err =
+            generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+    }
+
+    #[tokio::test]
+    async fn test_pod_template_with_ephemeral_storage() {
+        let mut mock_command = AllocationRequest {
+            cpu_resource: "1".to_string(),
+            memory_resource: "2Gi".to_string(),
+            ..mock_request()
+        };
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let resources = pod.spec.unwrap().containers[0].resources.clone().unwrap();
+        for quantity in [resources.limits.unwrap(), resources.requests.unwrap()] {
+            assert!(!quantity.contains_key("ephemeral-storage"));
+        }
+
+        mock_command.ephemeral_storage = Some("20Gi".to_string());
+        let pod = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        let resources = pod.spec.unwrap().containers[0].resources.clone().unwrap();
+        for quantity in [resources.limits.unwrap(), resources.requests.unwrap()] {
+            assert_eq!(quantity["ephemeral-storage"].0, "20Gi");
+            assert_eq!(quantity["memory"].0, "2Gi");
+        }
+
+        mock_command.ephemeral_storage = Some("20 GB".to_string());
+        let err = generate_pod_resource(&mock_command, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert!(err
+            .to_string()
+            .contains("ephemeral storage 20 GB is not a valid quantity"));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_error_json() {
+        let request = AllocationRequest {
+            timeout: 0,
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new();
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        let pod = error_pod(&err).unwrap().to_string();
+        assert!(pod.starts_with("resalloc-"));
+        assert_eq!(error_category(&err), ErrorCategory::Timeout);
+        assert_eq!(
+            error_json(&err),
+            serde_json::json!({
+                "error": format!("{:#}", err),
+                "category": "timeout",
+                "pod": pod,
+            })
+        );
+        assert!(error_json(&err)["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("failed to creating new pod resource"));
+
+        //errors outside of allocation have no pod
+        let err = categorized_error(ErrorCategory::NotFound, "pod not found".to_string());
+        assert_eq!(
+            error_json(&err),
+            serde_json::json!({"error": "pod not found", "category": "not_found"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_allocate_with_existing_volume_claim() {
+        let mut request = AllocationRequest {
+            additional_volume_mount_path: Some("/var/cache".to_string()),
+            existing_volume_claim: Some("shared-cache".to_string()),
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        });
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert!(cluster.pvcs().is_empty());
+        let pod = cluster.pods().remove(0);
+        assert_eq!(pod.metadata.labels.as_ref().unwrap()["has_volume"], "false");
+        assert_eq!(get_pvc_name(&pod), None);
+        let spec = pod.spec.unwrap();
+        assert_eq!(
+            spec.volumes.unwrap()[0]
+                .persistent_volume_claim
+                .as_ref()
+                .unwrap()
+                .claim_name,
+            "shared-cache"
+        );
+        assert_eq!(
+            spec.containers[0].volume_mounts.as_ref().unwrap()[0].mount_path,
+            "/var/cache"
+        );
+        assert!(allocation
+            .resources
+            .iter()
+            .all(|r| r.kind != "PersistentVolumeClaim"));
+
+        request.additional_volume_size = Some("10Gi".to_string());
+        let err = validate_request(&request).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("existing volume claim shared-cache can not be combined"));
+        request.additional_volume_size = None;
+        request.additional_volume_mount_path = None;
+        let err = validate_request(&request).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("existing volume claim requires additional volume mount path"));
+    }
+
+    #[tokio::test]
+    async fn test_pvc_already_exists_is_reused() {
+        let request = AllocationRequest {
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new()
+            .with_pvc_create_conflicts(1)
+            .with_pod_status(PodStatus {
+                phase: Some("Running".to_string()),
+                pod_ip: Some("10.0.0.1".to_string()),
+                ..Default::default()
+            });
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert_eq!(allocation.ip, Some("10.0.0.1".to_string()));
+        assert_eq!(cluster.pods().len(), 1);
+        //the pod mounts the pvc created concurrently
+        let pvcs = cluster.pvcs();
+        assert_eq!(pvcs.len(), 1);
+        assert_eq!(pvcs[0].metadata.name, Some(allocation.name));
+    }
+
+    #[test]
+    fn test_template_missing_variable_is_error() {
+        let mut handler = template_registry();
+        handler
+            .register_template_string("vol_template", RAW_VOLUME)
+            .unwrap();
+        let mut attribute = std::collections::BTreeMap::new();
+        attribute.insert("volume_name", "data".to_string());
+        let err = render_template(&handler, "vol_template", &attribute).unwrap_err();
+        assert_eq!(err.to_string(), "failed to render template vol_template");
+        assert!(format!("{:#}", err).contains("Variable \"claim_name\" not found"));
+
+        attribute.insert("claim_name", "resalloc-data".to_string());
+        let volume = render_template(&handler, "vol_template", &attribute).unwrap();
+        assert!(volume.contains("claimName: resalloc-data"));
+    }
+
+    #[tokio::test]
+    async fn test_pod_yaml_errors_are_returned() {
+        let request = mock_request();
+        let err = create_simple_pod_yaml(&request, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "additional volume mount path is required to mount the volume"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_yaml_breaking_values_are_validation_errors() {
+        let request = AllocationRequest {
+            image_tag: "a: b".to_string(),
+            ..mock_request()
+        };
+        let err = generate_pod_resource(&request, "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert_eq!(
+            err.to_string(),
+            "failed to parse rendered template pod_template"
+        );
+
+        let request = AllocationRequest {
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_class: Some("a: b".to_string()),
+            ..mock_request()
+        };
+        let err = generate_pvc_resource(&request, "test_ns", TEST_POD_NAME)
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert_eq!(
+            err.to_string(),
+            "failed to parse rendered template pvc_template"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_pods_created_by_tool() {
+        let pod = |name: &str, app: &str, ip: Option<&str>| Pod {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                name: Some(name.to_string()),
+                labels: Some(std::collections::BTreeMap::from([(
+                    "app".to_string(),
+                    app.to_string(),
+                )])),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                phase: Some(if ip.is_some() { "Running" } else { "Pending" }.to_string()),
+                pod_ip: ip.map(str::to_string),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let cluster = FakeCluster::new()
+            .with_pod(pod("resalloc-b", "resalloc-kubernetes", None))
+            .with_pod(pod("resalloc-a", "resalloc-kubernetes", Some("10.0.0.1")))
+            .with_pod(pod("web", "nginx", Some("10.0.0.2")));
+        let pods = list_with(&cluster).await.unwrap();
+        assert_eq!(
+            pods.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            vec!["resalloc-a", "resalloc-b"]
+        );
+
+        let mut output = Vec::new();
+        write_pod_list(&mut output, &ListRequest::default(), &pods).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "NAME         IP         NODE     STATUS\n\
+             resalloc-a   10.0.0.1   <none>   Running\n\
+             resalloc-b   <none>     <none>   Pending\n"
+        );
+
+        let mut output = Vec::new();
+        let list_command = ListRequest {
+            output: ListOutput::Json,
+        };
+        write_pod_list(&mut output, &list_command, &pods).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json[0]["name"], "resalloc-a");
+        assert_eq!(json[0]["ip"], "10.0.0.1");
+        assert_eq!(json[1]["ip"], serde_json::Value::Null);
+        assert_eq!(json[1]["phase"], "Pending");
+    }
+
+    #[tokio::test]
+    async fn test_ensure_converges_to_replicas() {
+        let ensure_command = |replicas: u32| EnsureRequest {
+            selector: vec![("pool".to_string(), "builders".to_string())],
+            replicas,
+            allocation: mock_request(),
+        };
+        let other = Pod {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                name: Some("resalloc-other".to_string()),
+                labels: Some(std::collections::BTreeMap::from([
+                    ("app".to_string(), "resalloc-kubernetes".to_string()),
+                    ("pool".to_string(), "testers".to_string()),
+                ])),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let cluster = FakeCluster::new()
+            .with_pod(other)
+            .with_pod_status(PodStatus {
+                phase: Some("Running".to_string()),
+                pod_ip: Some("10.0.0.1".to_string()),
+                ..Default::default()
+            });
+        let pool_size = || {
+            cluster
+                .pods()
+                .iter()
+                .filter(|p| {
+                    p.metadata
+                        .labels
+                        .as_ref()
+                        .unwrap()
+                        .get("pool")
+                        .map(String::as_str)
+                        == Some("builders")
+                })
+                .count()
+        };
+
+        //the deficit is created
+        let reconciliation = ensure_with(&cluster, &ensure_command(3), "test_ns")
+            .await
+            .unwrap();
+        assert_eq!(reconciliation.created.len(), 3);
+        assert!(reconciliation.deleted.is_empty());
+        assert_eq!(pool_size(), 3);
+
+        //converged already
+        let reconciliation = ensure_with(&cluster, &ensure_command(3), "test_ns")
+            .await
+            .unwrap();
+        assert!(reconciliation.created.is_empty() && reconciliation.deleted.is_empty());
+
+        //the surplus is deleted, pods of other pools are kept
+        let reconciliation = ensure_with(&cluster, &ensure_command(1), "test_ns")
+            .await
+            .unwrap();
+        assert!(reconciliation.created.is_empty());
+        assert_eq!(reconciliation.deleted.len(), 2);
+        assert_eq!(pool_size(), 1);
+        assert!(cluster
+            .pods()
+            .iter()
+            .any(|p| p.metadata.name.as_deref() == Some("resalloc-other")));
+    }
+
+    #[tokio::test]
+    async fn test_status_by_ip() {
+        let pod = Pod {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                name: Some("resalloc-a".to_string()),
+                labels: Some(std::collections::BTreeMap::from([(
+                    "app".to_string(),
+                    "resalloc-kubernetes".to_string(),
+                )])),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                phase: Some("Running".to_string()),
+                pod_ip: Some("10.0.0.1".to_string()),
+                conditions: Some(vec![k8s_openapi::api::core::v1::PodCondition {
+                    type_: "Ready".to_string(),
+                    status: "False".to_string(),
+                    reason: Some("ContainersNotReady".to_string()),
+                    ..Default::default()
+                }]),
+                container_statuses: Some(vec![k8s_openapi::api::core::v1::ContainerStatus {
+                    name: "main".to_string(),
+                    restart_count: 2,
+                    state: Some(k8s_openapi::api::core::v1::ContainerState {
+                        waiting: Some(k8s_openapi::api::core::v1::ContainerStateWaiting {
+                            reason: Some("CrashLoopBackOff".to_string()),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let cluster = FakeCluster::new().with_pod(pod);
+        let mut status_command = StatusRequest {
+            name: "10.0.0.1".to_string(),
+            ..Default::default()
+        };
+        let pod = status_with(&cluster, &status_command).await.unwrap();
+        let mut output = Vec::new();
+        write_pod_status(&mut output, &status_command, &pod).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Name: resalloc-a\n\
+             IP: 10.0.0.1\n\
+             Phase: Running\n\
+             Conditions: Ready=False reason=ContainersNotReady\n\
+             Containers:\n  \
+             main: ready=false restarts=2 state=waiting (CrashLoopBackOff)\n"
+        );
+
+        status_command.output = StatusOutput::Wide;
+        let mut output = Vec::new();
+        write_pod_status(&mut output, &status_command, &pod).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("NAME"));
+        assert!(lines[0].ends_with("AGE      CPU      MEMORY   VOLUME"));
+        assert!(lines[1].starts_with("resalloc-a   10.0.0.1   <none>   Running"));
+
+        status_command.output = StatusOutput::Json;
+        let mut output = Vec::new();
+        write_pod_status(&mut output, &status_command, &pod).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["phase"], "Running");
+        assert_eq!(json["conditions"][0]["reason"], "ContainersNotReady");
+        assert_eq!(
+            json["containerStatuses"][0]["state"]["waiting"]["reason"],
+            "CrashLoopBackOff"
+        );
+
+        status_command.name = "10.0.0.2".to_string();
+        let err = status_with(&cluster, &status_command).await.unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_delete_by_name_without_ip() {
+        let pod = |name: &str, app: &str| Pod {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                name: Some(name.to_string()),
+                labels: Some(std::collections::BTreeMap::from([
+                    ("app".to_string(), app.to_string()),
+                    ("has_volume".to_string(), "true".to_string()),
+                ])),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                phase: Some("Pending".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let cluster = FakeCluster::new()
+            .with_pod(pod("resalloc-pending", "resalloc-kubernetes"))
+            .with_pod(pod("web", "nginx"))
+            .with_pvc(k8s_openapi::api::core::v1::PersistentVolumeClaim {
+                metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                    name: Some("resalloc-pending".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        let delete_command = |name: &str| DeallocationRequest {
+            name: Some(name.to_string()),
+            by_name: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            delete_command("resalloc-pending").target(),
+            "pod resalloc-pending"
+        );
+
+        //pods of other applications are never deleted
+        let deleted = deallocate_with(&cluster, &delete_command("web"))
+            .await
+            .unwrap();
+        assert!(deleted.is_empty());
+        let err = deallocate_with(&cluster, &delete_command("resalloc-missing"))
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::NotFound);
+
+        let deleted = deallocate_with(
+            &cluster,
+            &DeallocationRequest {
+                dry_run: true,
+                ..delete_command("resalloc-pending")
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(deleted[0].pvc.as_deref(), Some("resalloc-pending"));
+        assert_eq!(cluster.pods().len(), 2);
+
+        deallocate_with(&cluster, &delete_command("resalloc-pending"))
+            .await
+            .unwrap();
+        assert_eq!(cluster.pods().len(), 1);
+        assert!(cluster.pvcs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_all_older_than() {
+        let now = k8s_openapi::chrono::Utc::now();
+        let pod = |name: &str, app: &str, age_minutes: i64, has_volume: bool| Pod {
+            metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                name: Some(name.to_string()),
+                labels: Some(std::collections::BTreeMap::from([
+                    ("app".to_string(), app.to_string()),
+                    ("has_volume".to_string(), has_volume.to_string()),
+                ])),
+                creation_timestamp: Some(k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(
+                    now - k8s_openapi::chrono::Duration::minutes(age_minutes),
+                )),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let cluster = FakeCluster::new()
+            .with_pod(pod("resalloc-leaked", "resalloc-kubernetes", 180, true))
+            .with_pod(pod("resalloc-fresh", "resalloc-kubernetes", 5, false))
+            .with_pod(pod("web", "nginx", 600, false))
+            .with_pvc(k8s_openapi::api::core::v1::PersistentVolumeClaim {
+                metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                    name: Some("resalloc-leaked".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        let mut delete_all_command = DeleteAllRequest {
+            dry_run: true,
+            older_than: Some(parse_duration("1h").unwrap()),
+        };
+
+        let deleted = delete_all_with(&cluster, &delete_all_command)
+            .await
+            .unwrap();
+        assert_eq!(
+            deleted,
+            vec![DeletedPod {
+                name: "resalloc-leaked".to_string(),
+                pvc: Some("resalloc-leaked".to_string()),
+                pvc_still_referenced: false,
+            }]
+        );
+        assert_eq!(cluster.pods().len(), 3);
+        assert_eq!(cluster.pvcs().len(), 1);
+
+        delete_all_command.dry_run = false;
+        delete_all_with(&cluster, &delete_all_command)
+            .await
+            .unwrap();
+        assert_eq!(cluster.pods().len(), 2);
+        assert!(cluster.pvcs().is_empty());
+
+        delete_all_command.older_than = None;
+        let deleted = delete_all_with(&cluster, &delete_all_command)
+            .await
+            .unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(cluster.pods()[0].metadata.name.as_deref(), Some("web"));
+
+        assert_eq!(
+            parse_duration("90s").unwrap(),
+            std::time::Duration::from_secs(90)
+        );
+        assert_eq!(
+            parse_duration("2d").unwrap(),
+            std::time::Duration::from_secs(2 * 24 * 60 * 60)
+        );
+        for invalid in ["", "h", "10", "1.5h", "-1h", "3w"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout_error_contains_events_and_logs() {
+        let request = AllocationRequest {
+            timeout: 0,
+            ..mock_request()
+        };
+        let event = |reason: &str, message: &str, count: i32| k8s_openapi::api::core::v1::Event {
+            type_: Some("Warning".to_string()),
+            reason: Some(reason.to_string()),
+            message: Some(message.to_string()),
+            count: Some(count),
+            ..Default::default()
+        };
+        let logs: String = (1..=30).map(|i| format!("line {}\n", i)).collect();
+        let cluster = FakeCluster::new()
+            .with_pod_status(PodStatus {
+                phase: Some("Pending".to_string()),
+                ..Default::default()
+            })
+            .with_pod_event(event("Failed", "Error: ImagePullBackOff", 3))
+            .with_pod_event(event("BackOff", "Back-off pulling image", 1))
+            .with_container_logs(&logs);
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Timeout);
+        let message = err.to_string();
+        assert!(message.contains(
+            ", pod events: Warning Failed: Error: ImagePullBackOff (x3); Warning BackOff: Back-off pulling image\nlast logs of container resalloc-"
+        ));
+        assert!(message.contains(":\nline 11\nline 12\n"));
+        assert!(message.ends_with("line 29\nline 30"));
+        assert!(!message.contains("line 10\n"));
+        assert!(cluster.pods().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_on_image_pull_and_unschedulable() {
+        let request = AllocationRequest {
+            timeout: 90,
+            image_tag: "openeuler/openeuler:22.3".to_string(),
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Pending".to_string()),
+            container_statuses: Some(vec![ContainerStatus {
+                name: "main".to_string(),
+                state: Some(ContainerState {
+                    waiting: Some(k8s_openapi::api::core::v1::ContainerStateWaiting {
+                        reason: Some("ImagePullBackOff".to_string()),
+                        message: Some("Back-off pulling image".to_string()),
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_ne!(error_category(&err), ErrorCategory::Timeout);
+        assert!(err
+            .to_string()
+            .contains("container main is waiting (ImagePullBackOff: Back-off pulling image)"));
+        assert!(cluster.pods().is_empty());
+
+        let cluster = FakeCluster::new().with_pod_status(PodStatus {
+            phase: Some("Pending".to_string()),
+            conditions: Some(vec![k8s_openapi::api::core::v1::PodCondition {
+                type_: "PodScheduled".to_string(),
+                status: "False".to_string(),
+                reason: Some("Unschedulable".to_string()),
+                message: Some("0/3 nodes are available: 3 Insufficient cpu.".to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let request = AllocationRequest {
+            wait_for: WaitFor::Succeeded,
+            ..request
+        };
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains(
+            "is failed before completion, pod is unschedulable: 0/3 nodes are available: 3 Insufficient cpu."
+        ));
+        assert!(cluster.pods().is_empty());
+
+        //waiting for other reasons, e.g. pulling the image, still waits
+        let pending = PodStatus {
+            phase: Some("Pending".to_string()),
+            container_statuses: Some(vec![ContainerStatus {
+                name: "main".to_string(),
+                state: Some(ContainerState {
+                    waiting: Some(k8s_openapi::api::core::v1::ContainerStateWaiting {
+                        reason: Some("ContainerCreating".to_string()),
+                        message: None,
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let pod = Pod {
+            status: Some(pending),
+            ..Default::default()
+        };
+        assert_eq!(pod_failure_reason(&pod), None);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_ready() {
+        let request = AllocationRequest {
+            timeout: 0,
+            wait_for: WaitFor::Ready,
+            ..mock_request()
+        };
+        let status = |ready: &str| PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.12".to_string()),
+            conditions: Some(vec![k8s_openapi::api::core::v1::PodCondition {
+                type_: "Ready".to_string(),
+                status: ready.to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        //running but the readiness probes have not passed yet
+        let cluster = FakeCluster::new().with_pod_status(status("False"));
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Timeout);
+        assert!(cluster.pods().is_empty());
+
+        let cluster = FakeCluster::new().with_pod_status(status("True"));
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert_eq!(allocation.ip.as_deref(), Some("10.0.0.12"));
+
+        //running is enough by default
+        let request = AllocationRequest {
+            wait_for: WaitFor::Running,
+            ..request
+        };
+        let cluster = FakeCluster::new().with_pod_status(status("False"));
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert_eq!(allocation.ip.as_deref(), Some("10.0.0.12"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_allocation_keeps_reused_pvc() {
+        let request = AllocationRequest {
+            timeout: 0,
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        //the pvc already exists when it's created, the pod never runs
+        let cluster = FakeCluster::new().with_pvc_create_conflicts(1);
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Timeout);
+        assert!(cluster.pods().is_empty());
+        assert_eq!(cluster.pvcs().len(), 1);
+
+        //the reused pvc is never bound
+        let request = AllocationRequest {
+            volume_timeout: Some(0),
+            ..request
+        };
+        let cluster = FakeCluster::new().with_pvc_create_conflicts(1);
+        allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(cluster.pvcs().len(), 1);
+
+        //the pvc created by the allocation is still removed
+        let cluster = FakeCluster::new();
+        allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert!(cluster.pvcs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_protect_finalizer_never_left_behind() {
+        let request = AllocationRequest {
+            protect: true,
+            timeout: 120,
+            additional_volume_class: Some("test_pvc".to_string()),
+            additional_volume_size: Some("10Gi".to_string()),
+            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
+            ..mock_request()
+        };
+        let running = PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        };
+        let cluster = FakeCluster::new().with_pod_status(running.clone());
+        allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert_eq!(cluster.pods()[0].metadata.finalizers, Some(vec![]));
+
+        //the allocated pod is cleaned up when its finalizer can't be released
+        let cluster = FakeCluster::new()
+            .with_pod_status(running)
+            .with_failing_operation("set_pod_finalizers");
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "set_pod_finalizers failed");
+        assert!(cluster.pods().is_empty());
+        assert!(cluster.pvcs().is_empty());
+
+        //a pod which is already gone is released
+        release_protect_finalizer(&FakeCluster::new(), TEST_POD_NAME)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_crash_loop_tolerates_restarts() {
+        let crash_loop = |restart_count: i32| PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.0.0.1".to_string()),
+            container_statuses: Some(vec![ContainerStatus {
+                name: "main".to_string(),
+                restart_count,
+                state: Some(ContainerState {
+                    waiting: Some(k8s_openapi::api::core::v1::ContainerStateWaiting {
+                        reason: Some("CrashLoopBackOff".to_string()),
+                        message: None,
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        //the pod restarted once is still reported as ready
+        let request = AllocationRequest {
+            timeout: 90,
+            restart_policy: Some(RestartPolicy::OnFailure),
+            ..mock_request()
+        };
+        let cluster = FakeCluster::new().with_pod_status(crash_loop(1));
+        let allocation = allocate_with(&cluster, &request, "test_ns").await.unwrap();
+        assert_eq!(allocation.ip, Some("10.0.0.1".to_string()));
+
+        //the pod keeps crashing
+        let cluster = FakeCluster::new().with_pod_status(crash_loop(3));
+        let err = allocate_with(&cluster, &request, "test_ns")
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("container main is waiting (CrashLoopBackOff)"));
+        assert!(cluster.pods().is_empty());
+
+        //the pod is never restarted
+        let mut pod = generate_pod_resource(
+            &AllocationRequest {
+                restart_policy: Some(RestartPolicy::Never),
+                ..mock_request()
+            },
+            "test_ns",
+            TEST_POD_NAME,
+            "",
+            false,
+        )
+        .await
+        .unwrap();
+        pod.status = Some(crash_loop(0));
+        assert_eq!(
+            pod_failure_reason(&pod),
+            Some("container main is waiting (CrashLoopBackOff)".to_string())
+        );
+    }
+}
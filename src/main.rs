@@ -1,80 +1,24 @@
 use anyhow::{anyhow, Result};
-use clap::{Args, Parser, Subcommand};
-use k8s_openapi::api::core::v1::{PersistentVolumeClaim, Pod, VolumeMount};
-use kube::{
-    api::{Api, DeleteParams, ListParams, PostParams},
-    runtime::wait::{await_condition, conditions::is_pod_running},
-    Client, ResourceExt,
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+use env_logger::WriteStyle;
+use log::{debug, LevelFilter};
+use resalloc_kubernetes::cluster::{kube_config, KubeCluster};
+use resalloc_kubernetes::{
+    allocate_with, categorized_error, deallocate_with, delete_all_with, ensure_with,
+    error_category, error_json, error_trace_id, list_with, skips_wait, status_with, write_pod_list,
+    write_pod_status,
 };
-use log::{debug, info};
-use std::collections::BTreeMap;
+use resalloc_kubernetes::{
+    AllocationRequest, Config, CreatedResource, DeallocationRequest, DeleteAllRequest, DeletedPod,
+    EnsureRequest, ErrorCategory, IpOutput, ListRequest, OutputFormat, Reconciliation,
+    StatusOutput, StatusRequest,
+};
+use serde::Serialize;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-use handlebars::{no_escape, Handlebars};
-
-static RAW_VOLUME_MOUNT: &str = r#"volumeMounts:
-{{content}}"#;
-
-static RAW_SECRET_MOUNT: &str = r#"      - mountPath: {{mount_path}}
-        name: {{name}}
-        subPath: {{sub_path}}
-"#;
-
-static RAW_VOLUME_MOUNT_PVC: &str = r#"      - mountPath: {{mount_path}}
-        name: {{volume_name}}
-"#;
-static RAW_POD: &str = r#"
-apiVersion: v1
-kind: Pod
-metadata:
-  name: {{name}}
-  namespace: {{namespace}}
-  labels:
-    app: resalloc-kubernetes
-    has_volume: {{has_volume}}
-spec:
-  {{volume}}
-  containers:
-    - image: {{image}}
-      imagePullPolicy: IfNotPresent
-      name: {{name}}
-      securityContext:
-        privileged: {{privileged}}
-      resources:
-        limits:
-          cpu: {{cpu}}
-          memory: {{memory}}
-        requests:
-          cpu: {{cpu}}
-          memory: {{memory}}
-      {{volume_mount}}"#;
-static RAW_VOLUME_HEADER: &str = "volumes:";
-
-static RAW_VOLUME: &str = r#"
-  - name: {{volume_name}}
-    persistentVolumeClaim:
-      claimName: {{claim_name}}"#;
-
-static RAW_SECRET_VOLUME: &str = r#"
-  - name: {{volume_name}}
-    secret:
-      secretName: {{secret_name}}"#;
-
-static RAW_PVC: &str = r#"apiVersion: v1
-kind: PersistentVolumeClaim
-metadata:
-  name: {{name}}
-  namespace: {{namespace}}
-  labels:
-    app: resalloc-kubernetes
-spec:
-  accessModes:
-  - ReadWriteOnce
-  resources:
-    requests:
-      storage: {{size}}
-  storageClassName: {{class}}"#;
-
 #[derive(Parser)]
 #[command(name = "resalloc-kubernetes")]
 #[command(author = "TommyLike <tommylikehu@gmail.com>")]
@@ -82,10 +26,27 @@ spec:
 #[command(arg_required_else_help = true)]
 #[command(about = "Allocate kubernetes pod for resalloc framework", long_about = None)]
 struct App {
-    #[arg(long)]
-    debug: bool,
-    #[arg(long, global = true)]
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    #[arg(
+        help = "increase log verbosity, can be specified with multiple times: -v info, -vv debug, -vvv trace, RUST_LOG takes precedence when set"
+    )]
+    verbose: u8,
+    #[arg(long, value_enum, global = true, default_value_t = ColorChoice::Auto)]
+    #[arg(help = "colorize log output, 'auto' detects the terminal and respects NO_COLOR")]
+    color: ColorChoice,
+    #[arg(long, global = true, env = "RESALLOC_NAMESPACE")]
+    #[arg(
+        help = "specify the namespace of resources, defaults to the namespace of current kubeconfig context"
+    )]
     namespace: Option<String>,
+    #[arg(long, global = true)]
+    #[arg(
+        help = "specify the kubeconfig context used for credentials, resources are still created in --namespace when specified"
+    )]
+    context: Option<String>,
+    #[arg(long, global = true, env = "RESALLOC_KUBERNETES_CONFIG")]
+    #[arg(help = "specify the yaml config file overriding the built-in presets")]
+    config: Option<PathBuf>,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -93,753 +54,1131 @@ struct App {
 #[derive(Subcommand)]
 enum Commands {
     #[command(about = "Create new pod resource", long_about = None)]
-    Add(Box<CommandAdd>),
+    Add(Box<AllocationRequest>),
     #[command(about = "Delete existing pod resource by IP address", long_about = None)]
-    Delete(CommandDelete),
-}
-
-#[derive(Args)]
-struct CommandAdd {
-    #[arg(long, default_value_t = 90)]
-    #[arg(help = "timeout for waiting pod to be ready")]
-    timeout: u64,
-    #[arg(long)]
-    #[arg(
-        help = "specify the image tag used for generating, for example: docker.io/organization/image:tag"
+    Delete(DeallocationRequest),
+    #[command(about = "List pod resources created by this tool", long_about = None)]
+    List(ListRequest),
+    #[command(
+        about = "Delete all pod resources created by this tool, such as the leaked ones",
+        long_about = None
     )]
-    image_tag: String,
-    #[arg(long)]
-    #[arg(help = "specify the request and limit cpu resource, '1', '2000m' and etc.")]
-    cpu_resource: String,
-    #[arg(long)]
-    #[arg(help = "specify the request and limit memory resource, '1024Mi', '2Gi' and etc.")]
-    memory_resource: String,
-    #[arg(long)]
-    #[arg(
-        help = "specify the node selector for pod resource in the format of 'NAME=VALUE', can be specified with multiple times"
+    DeleteAll(DeleteAllRequest),
+    #[command(about = "Show status of existing pod resource by IP address", long_about = None)]
+    Status(StatusRequest),
+    #[command(
+        about = "Create or delete pod resources of the pool selected by labels to reach the replicas",
+        long_about = None
     )]
-    node_selector: Vec<String>,
-    #[arg(long)]
-    #[arg(help = "run pod in privileged mode")]
-    privileged: bool,
-    #[arg(long)]
-    #[arg(
-        help = "specify the additional labels for pod resource in the format of 'NAME=VALUE', can be specified with multiple times"
-    )]
-    additional_labels: Vec<String>,
-    #[arg(long)]
-    #[arg(
-        help = "specify the additional labels for pvc resource in the format of 'NAME=VALUE', can be specified with multiple times"
-    )]
-    additional_pvc_labels: Vec<String>,
-    #[arg(long)]
-    #[arg(
-        help = "specify the additional persistent volume size, use in group(additional_volume_size, additional_volume_class, additional_volume_mount_path)."
-    )]
-    
-    additional_volume_size: Option<String>,
-    #[arg(long)]
-    #[arg(
-        help = "specify the additional persistent volume class, use in group(additional_volume_size, additional_volume_class, additional_volume_mount_path)."
-    )]
-    additional_volume_class: Option<String>,
-    #[arg(long)]
-    #[arg(
-        help = "specify mount point for persistent volume, use in group(additional_volume_size, additional_volume_class, additional_volume_mount_path)."
-    )]
-    additional_volume_mount_path: Option<String>,
-    #[arg(long, required = false)]
-    #[arg(help = "just dry run and print the create resource in json")]
-    dry_run: bool,
-    #[arg(long, value_parser=parse_volume_mount)]
-    #[arg(help = "specify secret in <mountPath>:<name>:<subPath> form")]
-    secret: Option<VolumeMount>,
+    Ensure(Box<EnsureRequest>),
+}
+
+#[derive(Debug, Serialize)]
+struct AllocationOutput<'a> {
+    ip: &'a str,
+    allocation_id: &'a str,
+    resources: &'a [CreatedResource],
+}
+
+fn values_file_args(path: &Path) -> Result<Vec<OsString>> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        anyhow!(
+            "failed to read values file {}, due to {:?}",
+            path.display(),
+            e
+        )
+    })?;
+    let values: serde_yaml::Mapping = serde_yaml::from_str(&content).map_err(|e| {
+        categorized_error(
+            ErrorCategory::Validation,
+            format!("invalid values file {}, due to {}", path.display(), e),
+        )
+    })?;
+    let command = App::command();
+    let add = command.find_subcommand("add").unwrap();
+
+    let mut args = Vec::new();
+    for (key, value) in values.iter() {
+        let key = key.as_str().unwrap_or_default().replace('_', "-");
+        let arg = add
+            .get_arguments()
+            .find(|a| a.get_long() == Some(key.as_str()) && key != "values")
+            .ok_or_else(|| {
+                categorized_error(
+                    ErrorCategory::Validation,
+                    format!("unknown key '{}' in values file {}", key, path.display()),
+                )
+            })?;
+        let flag = OsString::from(format!("--{}", key));
+        let items = match value {
+            serde_yaml::Value::Sequence(items) => items.clone(),
+            _ => vec![value.clone()],
+        };
+        for item in items.iter() {
+            let value = match item {
+                serde_yaml::Value::Bool(enabled)
+                    if matches!(arg.get_action(), ArgAction::SetTrue) =>
+                {
+                    if *enabled {
+                        args.push(flag.clone());
+                    }
+                    continue;
+                }
+                serde_yaml::Value::Bool(v) => v.to_string(),
+                serde_yaml::Value::Number(v) => v.to_string(),
+                serde_yaml::Value::String(v) => v.clone(),
+                _ => {
+                    return Err(categorized_error(
+                        ErrorCategory::Validation,
+                        format!(
+                            "unsupported value of key '{}' in values file {}",
+                            key,
+                            path.display()
+                        ),
+                    ))
+                }
+            };
+            //e.g. "--wait=false", the value of such flags is never taken from the next argument
+            if arg.is_require_equals_set() {
+                args.push(OsString::from(format!("--{}={}", key, value)));
+            } else {
+                args.push(flag.clone());
+                args.push(OsString::from(value));
+            }
+        }
+    }
+    Ok(args)
 }
 
-fn parse_volume_mount(value: &str) -> Result<VolumeMount, String> {
-    let parts: Vec<&str> = value.split(':').collect();
+fn expand_values_file(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    //values are inserted right after the subcommand, so the explicit flags which come later override them
+    let add_index = match args.iter().position(|a| a == "add") {
+        Some(index) => index,
+        None => return Ok(args),
+    };
+    let mut path = None;
+    for (index, arg) in args.iter().enumerate().skip(add_index + 1) {
+        let arg = arg.to_string_lossy();
+        if arg == "--values" {
+            path = args.get(index + 1).map(PathBuf::from);
+        } else if let Some(p) = arg.strip_prefix("--values=") {
+            path = Some(PathBuf::from(p));
+        }
+    }
+    let path = match path {
+        Some(p) => p,
+        None => return Ok(args),
+    };
+    let mut expanded = args[..=add_index].to_vec();
+    expanded.extend(values_file_args(&path)?);
+    expanded.extend_from_slice(&args[add_index + 1..]);
+    Ok(expanded)
+}
 
-    if parts.len() != 3 {
-        return Err("".to_string());
+fn verbosity_level(verbose: u8) -> LevelFilter {
+    match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
     }
+}
 
-    Ok(VolumeMount {
-        mount_path: parts[0].to_string(),
-        name: parts[1].to_string(),
-        sub_path: Some(parts[2].to_string()),
-        mount_propagation: Default::default(),
-        sub_path_expr: Default::default(),
-        read_only: Default::default(),
-    })
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
 }
 
-#[derive(Args)]
-struct CommandDelete {
-    #[arg(long)]
-    #[arg(help = "specify ip address of pod to delete.")]
-    #[arg(env = "RESALLOC_NAME")]
-    name: String,
+fn log_write_style(color: ColorChoice, no_color: Option<OsString>) -> Option<WriteStyle> {
+    match color {
+        ColorChoice::Always => Some(WriteStyle::Always),
+        ColorChoice::Never => Some(WriteStyle::Never),
+        //see https://no-color.org, any non-empty value disables color
+        ColorChoice::Auto => match no_color {
+            Some(v) if !v.is_empty() => Some(WriteStyle::Never),
+            _ => None,
+        },
+    }
+}
+
+fn logger_builder(verbose: u8, color: ColorChoice) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(verbosity_level(verbose));
+    //RUST_LOG filters are parsed afterwards so they override the verbosity flag
+    builder.parse_env(env_logger::Env::default());
+    if let Some(style) = log_write_style(color, std::env::var_os("NO_COLOR")) {
+        builder.write_style(style);
+    }
+    //logs always go to stderr, stdout is reserved for the allocation result
+    builder.target(env_logger::Target::Stderr);
+    builder
+}
+
+fn default_namespace(config: Result<kube::Config>) -> String {
+    //same as kubectl, the namespace of current context or the in-cluster service account
+    match config {
+        Ok(c) => c.default_namespace,
+        Err(e) => {
+            debug!("failed to infer kubernetes config, due to {:?}", e);
+            "default".to_string()
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
-    let app = App::parse();
+async fn main() {
+    let args = match expand_values_file(std::env::args_os().collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            std::process::exit(error_category(&e).exit_code());
+        }
+    };
+    let app = App::parse_from(args);
+    logger_builder(app.verbose, app.color).init();
+    let error_format = match app.command {
+        Some(Commands::Add(ref add_command)) => add_command.output,
+        Some(Commands::Status(ref status_command)) => match status_command.output {
+            StatusOutput::Json => OutputFormat::Json,
+            _ => OutputFormat::Text,
+        },
+        _ => OutputFormat::Text,
+    };
+    if let Err(e) = run(app).await {
+        write_error(&mut std::io::stderr(), &e, error_format);
+        std::process::exit(error_category(&e).exit_code());
+    }
+}
+
+fn write_error<W: Write>(out: &mut W, err: &anyhow::Error, format: OutputFormat) {
+    let _ = match format {
+        OutputFormat::Text => match error_trace_id(err) {
+            Some(trace_id) => writeln!(out, "Error: {:?}\n\nTrace id: {}", err, trace_id),
+            None => writeln!(out, "Error: {:?}", err),
+        },
+        OutputFormat::Json => writeln!(out, "{}", error_json(err)),
+    };
+}
+
+async fn run(app: App) -> Result<()> {
     let namespace: String = match app.namespace {
         Some(input) => input,
-        None => "default".to_string(),
+        None => default_namespace(kube_config(app.context.as_deref()).await),
     };
-    //log preparation
+    let cluster = KubeCluster::new(&namespace).with_context(app.context.as_deref());
+    let config = Config::load(app.config.as_deref())?;
     //handle kubernetes pod resource
     match app.command {
-        Some(Commands::Add(add_command)) => {
-            generate_new_resource(&add_command, &namespace).await?;
+        Some(Commands::Add(mut add_command)) => {
+            add_command.config = config;
+            let allocation = allocate_with(&cluster, &add_command, &namespace).await?;
+            let mut stdout = std::io::stdout();
+            match (allocation.exit_code, allocation.ip) {
+                (Some(exit_code), _) => print_exit_code(&add_command, &mut stdout, exit_code)?,
+                (None, Some(ref pod_ip)) => {
+                    print_allocation(
+                        &add_command,
+                        &mut stdout,
+                        pod_ip,
+                        &allocation.allocation_id,
+                        &allocation.resources,
+                    )?;
+                    print_allocation_id(
+                        &add_command,
+                        &mut std::io::stderr(),
+                        &allocation.allocation_id,
+                    )?;
+                }
+                (None, None) if skips_wait(&add_command) => {
+                    print_pod_name(&add_command, &mut stdout, &allocation.name)?
+                }
+                //nothing is allocated on dry run
+                (None, None) => {}
+            }
         }
         Some(Commands::Delete(delete_command)) => {
-            delete_resource(&delete_command, &namespace).await?;
+            let deleted = deallocate_with(&cluster, &delete_command).await?;
+            if delete_command.dry_run {
+                write_delete_preview(&mut std::io::stdout(), &deleted)?;
+            }
+        }
+        Some(Commands::Status(status_command)) => {
+            let pod = status_with(&cluster, &status_command).await?;
+            write_pod_status(&mut std::io::stdout(), &status_command, &pod)?;
+        }
+        Some(Commands::DeleteAll(delete_all_command)) => {
+            let deleted = delete_all_with(&cluster, &delete_all_command).await?;
+            if delete_all_command.dry_run {
+                write_delete_preview(&mut std::io::stdout(), &deleted)?;
+            }
+        }
+        Some(Commands::List(list_command)) => {
+            let pods = list_with(&cluster).await?;
+            write_pod_list(&mut std::io::stdout(), &list_command, &pods)?;
+        }
+        Some(Commands::Ensure(mut ensure_command)) => {
+            ensure_command.allocation.config = config;
+            let reconciliation = ensure_with(&cluster, &ensure_command, &namespace).await?;
+            write_reconciliation(&mut std::io::stdout(), &reconciliation)?;
         }
         None => {}
     };
     Ok(())
 }
 
-async fn generate_pvc_resource(
-    add_command: &CommandAdd,
-    namespace: &str,
-    pvc_name: &str,
-) -> Result<PersistentVolumeClaim> {
-    let mut handler = Handlebars::new();
-    handler
-        .register_template_string("pvc_template", RAW_PVC)
-        .unwrap();
-    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
-    let volume_size = add_command.additional_volume_size.clone().unwrap();
-    let volume_class = add_command.additional_volume_class.clone().unwrap();
-    attribute.insert("name", pvc_name.to_string());
-    attribute.insert("namespace", namespace.to_string());
-    attribute.insert("size", volume_size);
-    attribute.insert("class", volume_class);
-    let yaml = handler.render("pvc_template", &attribute).unwrap();
-    let mut pvc: PersistentVolumeClaim = serde_yaml::from_str(&yaml).unwrap();
-
-    //add labels
-    if !add_command.additional_pvc_labels.is_empty() {
-        let additional_labels = add_command.additional_pvc_labels.clone();
-        if let Some(ref mut l) = pvc.metadata.labels {
-            for label in additional_labels.into_iter() {
-                let pair: Vec<&str> = label.split('=').collect();
-                if pair.len() == 2 {
-                    l.insert(pair[0].to_string(), pair[1].to_string());
-                }
-            }
-        }
+fn print_exit_code<W: Write>(
+    add_command: &AllocationRequest,
+    out: &mut W,
+    exit_code: i32,
+) -> Result<()> {
+    match add_command.output {
+        OutputFormat::Text => writeln!(out, "{}", exit_code)?,
+        OutputFormat::Json => writeln!(out, "{}", serde_json::json!({ "exit_code": exit_code }))?,
     }
-    Ok(pvc)
+    Ok(())
 }
 
-fn generate_volume_str(claim_name: &str, volume_name: &str) -> Result<String> {
-    let mut handler = Handlebars::new();
-    handler
-        .register_template_string("vol_template", RAW_VOLUME)
-        .unwrap();
+fn print_pod_name<W: Write>(
+    add_command: &AllocationRequest,
+    out: &mut W,
+    name: &str,
+) -> Result<()> {
+    match add_command.output {
+        OutputFormat::Text => writeln!(out, "{}", name)?,
+        OutputFormat::Json => writeln!(out, "{}", serde_json::json!({ "name": name }))?,
+    }
+    Ok(())
+}
 
-    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
-    attribute.insert("claim_name", claim_name.to_string());
-    attribute.insert("volume_name", volume_name.to_string());
+fn print_allocation<W: Write>(
+    add_command: &AllocationRequest,
+    out: &mut W,
+    pod_ip: &str,
+    allocation_id: &str,
+    resources: &[CreatedResource],
+) -> Result<()> {
+    let line = match add_command.output {
+        OutputFormat::Text => pod_ip.to_string(),
+        OutputFormat::Json => serde_json::to_string(&AllocationOutput {
+            ip: pod_ip,
+            allocation_id,
+            resources,
+        })?,
+    };
+    match add_command.ip_output {
+        IpOutput::Stdout => writeln!(out, "{}", line)?,
+        IpOutput::File(ref path) => write_file_atomically(path, &format!("{}\n", line))?,
+    }
+    if let Some(ref path) = add_command.ip_file {
+        write_file_atomically(path, &format!("{}\n", pod_ip))?;
+    }
+    Ok(())
+}
 
-    Ok(handler.render("vol_template", &attribute).unwrap())
+//stdout carries the ip only in text mode, so the allocation id for deleting the pod goes to stderr,
+//it's included in the json output already
+fn print_allocation_id<W: Write>(
+    add_command: &AllocationRequest,
+    out: &mut W,
+    allocation_id: &str,
+) -> Result<()> {
+    if add_command.output == OutputFormat::Text {
+        writeln!(out, "allocation id: {}", allocation_id)?;
+    }
+    Ok(())
 }
 
-fn generate_volume_secret_str(volume: &str, secret: &str) -> Result<String> {
-    let mut handler = Handlebars::new();
-    handler
-        .register_template_string("vol_secret_template", RAW_SECRET_VOLUME)
-        .unwrap();
-    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
-    attribute.insert("volume_name", volume.to_string());
-    attribute.insert("secret_name", secret.to_string());
+fn write_file_atomically(path: &Path, content: &str) -> Result<()> {
+    //write to a temporary file in the same directory then rename it, so readers never see partial content
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid output file path {}", path.display()))?;
+    let tmp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        Uuid::new_v4()
+    ));
+    std::fs::write(&tmp_path, content)?;
+    if let Err(e) = std::fs::rename(&tmp_path, path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(anyhow!(
+            "failed to write output file {}, due to {:?}",
+            path.display(),
+            e
+        ));
+    }
+    Ok(())
+}
 
-    Ok(handler.render("vol_secret_template", &attribute).unwrap())
+fn write_delete_preview<W: Write>(out: &mut W, deleted: &[DeletedPod]) -> Result<()> {
+    for p in deleted.iter() {
+        writeln!(out, "pod/{}", p.name)?;
+        if let Some(ref pvc_name) = p.pvc {
+            match p.pvc_still_referenced {
+                true => writeln!(
+                    out,
+                    "persistentvolumeclaim/{} skipped (still referenced)",
+                    pvc_name
+                )?,
+                false => writeln!(out, "persistentvolumeclaim/{}", pvc_name)?,
+            }
+        }
+    }
+    Ok(())
 }
 
-fn generate_volume_mount_secret_str(
-    mount_path: &str,
-    sub_path: &str,
-    name: &str,
-) -> Result<String> {
-    let mut handler = Handlebars::new();
-    handler
-        .register_template_string("vol_secret_mount_template", RAW_SECRET_MOUNT)
-        .unwrap();
-    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
-    attribute.insert("mount_path", mount_path.to_string());
-    attribute.insert("sub_path", sub_path.to_string());
-    attribute.insert("name", name.to_string());
-
-    Ok(handler
-        .render("vol_secret_mount_template", &attribute)
-        .unwrap())
+fn write_reconciliation<W: Write>(out: &mut W, reconciliation: &Reconciliation) -> Result<()> {
+    for a in reconciliation.created.iter() {
+        writeln!(
+            out,
+            "pod/{} created {}",
+            a.name,
+            a.ip.as_deref().unwrap_or_default()
+        )?;
+    }
+    for p in reconciliation.deleted.iter() {
+        writeln!(out, "pod/{} deleted", p.name)?;
+    }
+    Ok(())
 }
 
-fn generate_volume_mount_pvc_str(mount_path: &str, name: &str) -> Result<String> {
-    let mut handler = Handlebars::new();
-    handler
-        .register_template_string("vol_mount_template", RAW_VOLUME_MOUNT_PVC)
-        .unwrap();
-    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
-    attribute.insert("mount_path", mount_path.to_string());
-    attribute.insert("volume_name", name.to_string());
+#[cfg(test)]
+mod tests {
+    use crate::default_namespace;
+    use crate::{expand_values_file, App, ColorChoice, Commands};
+    use crate::{log_write_style, logger_builder, verbosity_level};
+    use crate::{print_allocation, print_allocation_id, print_exit_code};
+    use crate::{write_delete_preview, write_error, AllocationOutput};
+    use clap::{CommandFactory, Parser};
+    use env_logger::WriteStyle;
+    use log::LevelFilter;
+    use resalloc_kubernetes::{error_category, generate_pod_resource, parse_ip_output, skips_wait};
+    use resalloc_kubernetes::{
+        AllocationRequest, CreatedResource, DeletedPod, ErrorCategory, IpOutput, WaitFor,
+    };
+    use std::sync::{Arc, Mutex};
 
-    Ok(handler.render("vol_mount_template", &attribute).unwrap())
-}
+    const TEST_POD_NAME: &str = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
 
-fn generate_volume_mount_str(secret_mount: &str, pvc_mount: &str) -> Result<String> {
-    if secret_mount.is_empty() && pvc_mount.is_empty() {
-        return Ok("".to_string());
+    #[test]
+    fn test_allocation_output_json() {
+        let resources = vec![
+            CreatedResource {
+                kind: "PersistentVolumeClaim".to_string(),
+                name: TEST_POD_NAME.to_string(),
+            },
+            CreatedResource {
+                kind: "Pod".to_string(),
+                name: TEST_POD_NAME.to_string(),
+            },
+        ];
+        let output = AllocationOutput {
+            ip: "10.0.0.1",
+            allocation_id: "9a1884fb",
+            resources: &resources,
+        };
+        assert_eq!(
+            serde_json::to_string(&output).unwrap(),
+            format!(
+                r#"{{"ip":"10.0.0.1","allocation_id":"9a1884fb","resources":[{{"kind":"PersistentVolumeClaim","name":"{0}"}},{{"kind":"Pod","name":"{0}"}}]}}"#,
+                TEST_POD_NAME
+            )
+        );
     }
 
-    let mut handler = Handlebars::new();
-    handler
-        .register_template_string("vol_mount_template", RAW_VOLUME_MOUNT)
-        .unwrap();
-    handler.register_escape_fn(no_escape);
-    let mut content = String::from(secret_mount);
-    content += pvc_mount;
-    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
-    attribute.insert("content", content);
+    #[test]
+    fn test_only_ip_written_to_stdout() {
+        let mut mock_command = AllocationRequest {
+            image_tag: "openeuler/openeuler:22.03".to_string(),
+            cpu_resource: "100m".to_string(),
+            memory_resource: "500Mi".to_string(),
+            ..Default::default()
+        };
+        let resources = vec![CreatedResource {
+            kind: "Pod".to_string(),
+            name: TEST_POD_NAME.to_string(),
+        }];
 
-    Ok(handler.render("vol_mount_template", &attribute).unwrap())
-}
-async fn create_simple_pod_yaml(
-    add_command: &CommandAdd,
-    namespace: &str,
-    name: &str,
-    pvc_name: &str,
-    has_volume: bool,
-) -> Result<String> {
-    let mut handler = Handlebars::new();
-    handler
-        .register_template_string("pod_template", RAW_POD)
+        let mut stdout = Vec::new();
+        print_allocation(
+            &mock_command,
+            &mut stdout,
+            "10.0.0.1",
+            "9a1884fb",
+            &resources,
+        )
         .unwrap();
-    handler.register_escape_fn(no_escape);
-
-    let mut vol :Vec<String> = Vec::new();
-    let mut vol_mount_pvc: String = Default::default();
-    let mut vol_mount_secret: String = Default::default();
+        assert_eq!(String::from_utf8(stdout).unwrap(), "10.0.0.1\n");
 
-    if let Some(ref secret) = add_command.secret {
-        vol_mount_secret = generate_volume_mount_secret_str(
-            &secret.mount_path.to_string(),
-            &secret.sub_path.clone().unwrap(),
-            &secret.name,
+        let path = std::env::temp_dir().join(format!("resalloc-ip-{}", uuid::Uuid::new_v4()));
+        mock_command.ip_output = parse_ip_output(&format!("file:{}", path.display())).unwrap();
+        assert_eq!(mock_command.ip_output, IpOutput::File(path.clone()));
+        let mut stdout = Vec::new();
+        print_allocation(
+            &mock_command,
+            &mut stdout,
+            "10.0.0.1",
+            "9a1884fb",
+            &resources,
         )
         .unwrap();
-        vol.push(generate_volume_secret_str(&secret.name, &secret.name).unwrap());
+        assert!(stdout.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "10.0.0.1\n");
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(parse_ip_output("file:").is_err());
+        assert!(parse_ip_output("stderr").is_err());
     }
-    if has_volume {
-        vol.push(generate_volume_str(pvc_name, pvc_name).unwrap());
-        vol_mount_pvc = generate_volume_mount_pvc_str(
-            add_command.additional_volume_mount_path.as_ref().unwrap(),
-            pvc_name,
-        )
-        .unwrap();
+
+    #[test]
+    fn test_allocation_id_written_to_stderr_in_text_mode() {
+        let mut mock_command = AllocationRequest {
+            image_tag: "openeuler/openeuler:22.03".to_string(),
+            cpu_resource: "100m".to_string(),
+            memory_resource: "500Mi".to_string(),
+            ..Default::default()
+        };
+        let mut stderr = Vec::new();
+        print_allocation_id(&mock_command, &mut stderr, "9a1884fb").unwrap();
+        assert_eq!(
+            String::from_utf8(stderr).unwrap(),
+            "allocation id: 9a1884fb\n"
+        );
+
+        mock_command.output = crate::OutputFormat::Json;
+        let mut stderr = Vec::new();
+        print_allocation_id(&mock_command, &mut stderr, "9a1884fb").unwrap();
+        assert!(stderr.is_empty());
     }
 
-    let vol_mount = generate_volume_mount_str(&vol_mount_secret, &vol_mount_pvc).unwrap();
+    #[test]
+    fn test_ip_written_to_ip_file() {
+        let path = std::env::temp_dir().join(format!("resalloc-ip-{}", uuid::Uuid::new_v4()));
+        let mock_command = AllocationRequest {
+            image_tag: "openeuler/openeuler:22.03".to_string(),
+            cpu_resource: "100m".to_string(),
+            memory_resource: "500Mi".to_string(),
+            output: crate::OutputFormat::Json,
+            ip_file: Some(path.clone()),
+            ..Default::default()
+        };
+
+        let mut stdout = Vec::new();
+        print_allocation(&mock_command, &mut stdout, "10.0.0.1", "9a1884fb", &[]).unwrap();
+        assert!(!stdout.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "10.0.0.1\n");
+        std::fs::remove_file(&path).unwrap();
+    }
 
-    let mut attribute: BTreeMap<&str, String> = BTreeMap::new();
-    attribute.insert("name", name.to_string());
-    attribute.insert("namespace", namespace.to_string());
-    attribute.insert("image", add_command.image_tag.clone());
-    attribute.insert("cpu", add_command.cpu_resource.clone());
-    attribute.insert("memory", add_command.memory_resource.clone());
-    attribute.insert("privileged", add_command.privileged.to_string());
-    if vol.len() != 0 {
-        let mut vols :String = RAW_VOLUME_HEADER.to_string();
-        for v in vol.iter() {
-            vols = format!("{}{}", vols, v)
+    #[test]
+    fn test_verbose_maps_to_log_level() {
+        let cases = [
+            (vec![], LevelFilter::Warn),
+            (vec!["-v"], LevelFilter::Info),
+            (vec!["-vv"], LevelFilter::Debug),
+            (vec!["--verbose", "-vv"], LevelFilter::Trace),
+            (vec!["-vvvv"], LevelFilter::Trace),
+        ];
+        for (verbose, level) in cases {
+            let mut args = vec!["resalloc-kubernetes", "delete", "--name", "10.0.0.1"];
+            args.extend(verbose);
+            let app = App::try_parse_from(args).unwrap();
+            assert_eq!(verbosity_level(app.verbose), level);
         }
-        attribute.insert("volume", vols);
     }
-    attribute.insert("volume_mount", vol_mount);
-    attribute.insert("has_volume", has_volume.to_string());
-    let s = handler.render("pod_template", &attribute).unwrap();
-    debug!("render pod yaml: {}", s);
-    Ok(s)
-}
 
-async fn generate_pod_resource(
-    add_command: &CommandAdd,
-    namespace: &str,
-    name: &str,
-    pvc_name: &str,
-    create_volume: bool,
-) -> Result<Pod> {
-    let yaml =
-        create_simple_pod_yaml(add_command, namespace, name, pvc_name, create_volume).await?;
-    let mut pod: Pod = serde_yaml::from_str(&yaml).unwrap();
-
-    //add labels
-    if !add_command.additional_labels.is_empty() {
-        let additional_labels = add_command.additional_labels.clone();
-        if let Some(ref mut l) = pod.metadata.labels {
-            for label in additional_labels.into_iter() {
-                let pair: Vec<&str> = label.split('=').collect();
-                if pair.len() == 2 {
-                    l.insert(pair[0].to_string(), pair[1].to_string());
-                }
-            }
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
         }
-    }
 
-    //add node selector
-    if !add_command.node_selector.is_empty() {
-        if let Some(ref mut spec) = pod.spec {
-            let node_selector = add_command.node_selector.clone();
-            match spec.node_selector {
-                Some(_) => {
-                    return Err(anyhow!(
-                        "generated pod resource node selector should be empty"
-                    ));
-                }
-                None => {
-                    let mut container = BTreeMap::new();
-                    for s in node_selector.into_iter() {
-                        let pair: Vec<&str> = s.split('=').collect();
-                        if pair.len() == 2 {
-                            container.insert(pair[0].to_string(), pair[1].to_string());
-                        }
-                    }
-                    spec.node_selector = Some(container)
-                }
-            }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
     }
 
-    Ok(pod)
-}
+    #[test]
+    fn test_color_never_disables_escape_codes() {
+        assert_eq!(
+            log_write_style(ColorChoice::Never, None),
+            Some(WriteStyle::Never)
+        );
+        assert_eq!(
+            log_write_style(ColorChoice::Always, None),
+            Some(WriteStyle::Always)
+        );
+        assert_eq!(
+            log_write_style(ColorChoice::Auto, Some("1".into())),
+            Some(WriteStyle::Never)
+        );
+        assert_eq!(log_write_style(ColorChoice::Auto, Some("".into())), None);
+        assert_eq!(log_write_style(ColorChoice::Auto, None), None);
 
-async fn cleanup(
-    pods_api: &Api<Pod>,
-    pvc_api: &Api<PersistentVolumeClaim>,
-    name: &str,
-    additional_volume: bool,
-) -> Result<()> {
-    //pods unready, delete them
-    delete_pod_by_name(pods_api.clone(), name).await?;
-    if additional_volume {
-        delete_pvc_by_name(pvc_api.clone(), name).await?;
+        let buffer = SharedBuffer::default();
+        let logger = logger_builder(1, ColorChoice::Never)
+            .target(env_logger::Target::Pipe(Box::new(buffer.clone())))
+            .build();
+        log::Log::log(
+            &logger,
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target("resalloc_kubernetes")
+                .args(format_args!("pod is not ready"))
+                .build(),
+        );
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("pod is not ready"));
+        assert!(!output.contains('\x1b'));
     }
-    Ok(())
-}
 
-async fn generate_new_resource(add_command: &CommandAdd, namespace: &str) -> Result<()> {
-    //check persistent volume argument
-    let mut additional_volume = false;
-    let name = format!("resalloc-{}", Uuid::new_v4());
-    let pp = PostParams::default();
-    let mut pvc = None;
+    fn parse_add_command(args: Vec<std::ffi::OsString>) -> Box<AllocationRequest> {
+        match App::parse_from(expand_values_file(args).unwrap()).command {
+            Some(Commands::Add(add_command)) => add_command,
+            _ => panic!("expected add command"),
+        }
+    }
 
-    if add_command.additional_volume_size.is_some()
-        && add_command.additional_volume_class.is_some()
-        && add_command.additional_volume_mount_path.is_some()
-    {
-        additional_volume = true;
+    //add command with the required flags followed by the flags under test
+    fn add_args(extra: &[&str]) -> Vec<std::ffi::OsString> {
+        [
+            "resalloc-kubernetes",
+            "add",
+            "--image-tag",
+            "openeuler/openeuler:latest",
+            "--cpu-resource",
+            "100m",
+            "--memory-resource",
+            "500Mi",
+        ]
+        .iter()
+        .chain(extra)
+        .map(|a| a.into())
+        .collect()
+    }
 
-        pvc = Some(generate_pvc_resource(add_command, namespace, &name).await?);
+    fn parse_add(extra: &[&str]) -> Box<AllocationRequest> {
+        parse_add_command(add_args(extra))
     }
-    let pod =
-        generate_pod_resource(add_command, namespace, &name, &name, additional_volume).await?;
 
-    if add_command.dry_run {
-        if pvc.is_some() {
-            info!("---");
-            info!("{}", serde_yaml::to_string(&pvc).unwrap());
-        }
-        info!("---");
-        info!("{}", serde_yaml::to_string(&pod).unwrap());
-        return Ok(());
-    }
-
-    let client = Client::try_default().await?;
-    let pods_api: Api<Pod> = Api::namespaced(client.clone(), namespace);
-    let pvc_api: Api<PersistentVolumeClaim> = Api::namespaced(client, namespace);
-
-    // generate pvc resource
-    if let Some(p) = pvc {
-        pvc_api.create(&pp, &p).await?;
-    }
-    // generate pod resource
-    pods_api.create(&pp, &pod).await?;
-    //wait pod to be ready
-    let running = await_condition(pods_api.clone(), &name, is_pod_running());
-    match tokio::time::timeout(std::time::Duration::from_secs(add_command.timeout), running).await {
-        Ok(res) => match res {
-            Err(e) => {
-                cleanup(&pods_api, &pvc_api, &name, additional_volume).await?;
-                Err(anyhow!(
-                    "failed to creating new pod resource in kubernetes, due to {:?}",
-                    e
-                ))
-            }
-            Ok(_) => {
-                //check pod ip address
-                match pods_api.get(&name).await {
-                    Err(e) => {
-                        cleanup(&pods_api, &pvc_api, &name, additional_volume).await?;
-                        Err(anyhow!(
-                            "failed to getting new pod resource in kubernetes, due to {:?}",
-                            e
-                        ))
-                    }
-                    Ok(current) => {
-                        if let Some(status) = current.status {
-                            if let Some(pod_ip) = status.pod_ip {
-                                println!("{}", &pod_ip);
-                                return Ok(());
-                            }
-                        }
-                        cleanup(&pods_api, &pvc_api, &name, additional_volume).await?;
-                        Err(anyhow!("container ip address empty"))
-                    }
-                }
-            }
-        },
-        Err(e) => {
-            cleanup(&pods_api, &pvc_api, &name, additional_volume).await?;
-            Err(anyhow!(
-                "failed to creating new pod resource in kubernetes, due to {:?}",
-                e
-            ))
-        }
+    #[tokio::test]
+    async fn test_values_file_matches_flags() {
+        let values = r#"image-tag: openeuler/openeuler:22.03
+cpu_resource: 1
+memory-resource: 500Mi
+privileged: true
+node-selector:
+  - kubernetes.io/arch=arm64
+additional-labels:
+  - failure-domain.beta.kubernetes.io/region=region1
+additional-volume-size: 10Gi
+additional-volume-class: test_pvc
+additional-volume-mount-path: /etc/test_mount
+secret: /home/copr/server.crt:copr-secrets:server-crt
+"#;
+        let path = std::env::temp_dir().join(format!("resalloc-values-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, values).unwrap();
+
+        let from_flags = parse_add_command(
+            [
+                "resalloc-kubernetes",
+                "add",
+                "--image-tag",
+                "openeuler/openeuler:22.03",
+                "--cpu-resource",
+                "1",
+                "--memory-resource",
+                "500Mi",
+                "--privileged",
+                "--node-selector",
+                "kubernetes.io/arch=arm64",
+                "--additional-labels",
+                "failure-domain.beta.kubernetes.io/region=region1",
+                "--additional-volume-size",
+                "10Gi",
+                "--additional-volume-class",
+                "test_pvc",
+                "--additional-volume-mount-path",
+                "/etc/test_mount",
+                "--secret",
+                "/home/copr/server.crt:copr-secrets:server-crt",
+            ]
+            .iter()
+            .map(|a| a.into())
+            .collect(),
+        );
+        let from_values = parse_add_command(
+            [
+                "resalloc-kubernetes",
+                "add",
+                "--values",
+                path.to_str().unwrap(),
+            ]
+            .iter()
+            .map(|a| a.into())
+            .collect(),
+        );
+        let pod_from_flags =
+            generate_pod_resource(&from_flags, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let pod_from_values =
+            generate_pod_resource(&from_values, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        assert_eq!(
+            serde_yaml::to_string(&pod_from_flags).unwrap(),
+            serde_yaml::to_string(&pod_from_values).unwrap()
+        );
+
+        //explicit flags override the values file
+        let overridden = parse_add_command(
+            [
+                "resalloc-kubernetes",
+                "add",
+                "--values",
+                path.to_str().unwrap(),
+                "--cpu-resource",
+                "2",
+            ]
+            .iter()
+            .map(|a| a.into())
+            .collect(),
+        );
+        assert_eq!(overridden.cpu_resource, "2");
+        assert_eq!(overridden.memory_resource, "500Mi");
+
+        //flags requiring equals are expanded with them
+        std::fs::write(
+            &path,
+            "image-tag: openeuler/openeuler:22.03\ncpu-resource: 1\nmemory-resource: 500Mi\nwait: false\n",
+        )
+        .unwrap();
+        let without_wait = parse_add_command(
+            [
+                "resalloc-kubernetes",
+                "add",
+                "--values",
+                path.to_str().unwrap(),
+            ]
+            .iter()
+            .map(|a| a.into())
+            .collect(),
+        );
+        assert_eq!(without_wait.wait, Some(false));
+        assert!(skips_wait(&without_wait));
+
+        std::fs::write(
+            &path,
+            "image-tag: openeuler/openeuler:22.03\nunknown-key: 1\n",
+        )
+        .unwrap();
+        let err = expand_values_file(
+            [
+                "resalloc-kubernetes",
+                "add",
+                "--values",
+                path.to_str().unwrap(),
+            ]
+            .iter()
+            .map(|a| a.into())
+            .collect(),
+        )
+        .unwrap_err();
+        assert_eq!(error_category(&err), ErrorCategory::Validation);
+        assert!(err.to_string().contains("unknown-key"));
+        std::fs::remove_file(&path).unwrap();
     }
-}
 
-async fn delete_resource(delete_command: &CommandDelete, namespace: &str) -> Result<()> {
-    info!("starting to delete {} resource", &delete_command.name);
-    let client = Client::try_default().await?;
+    #[tokio::test]
+    async fn test_default_namespace_from_kubeconfig_context() {
+        let kubeconfig = kube::config::Kubeconfig::from_yaml(
+            r#"apiVersion: v1
+kind: Config
+clusters:
+- name: test
+  cluster:
+    server: https://127.0.0.1:6443
+users:
+- name: test
+  user:
+    token: test-token
+contexts:
+- name: test
+  context:
+    cluster: test
+    user: test
+    namespace: copr-builders
+current-context: test
+"#,
+        )
+        .unwrap();
+        let config = kube::Config::from_custom_kubeconfig(kubeconfig, &Default::default())
+            .await
+            .map_err(|e| e.into());
+        assert_eq!(default_namespace(config), "copr-builders");
 
-    let pods_api: Api<Pod> = Api::namespaced(client.clone(), namespace);
-    let pvc_api: Api<PersistentVolumeClaim> = Api::namespaced(client, namespace);
+        assert_eq!(
+            default_namespace(Err(anyhow::anyhow!("no kubeconfig"))),
+            "default"
+        );
+    }
 
-    //get pod by ip address
-    let list_params =
-        ListParams::default().fields(&format!("status.podIP={}", delete_command.name));
-    let pods = pods_api.list(&list_params).await?;
-    if pods.items.is_empty() {
-        return Err(anyhow!(
-            "failed to get get any pods within {} address",
-            &delete_command.name
-        ));
+    #[test]
+    fn test_context_does_not_override_namespace() {
+        let app = App::try_parse_from([
+            "resalloc-kubernetes",
+            "delete",
+            "--name",
+            "10.0.0.1",
+            "--context",
+            "test",
+            "--namespace",
+            "builders",
+        ])
+        .unwrap();
+        assert_eq!(app.context.as_deref(), Some("test"));
+        assert_eq!(app.namespace.as_deref(), Some("builders"));
+
+        let app = App::try_parse_from([
+            "resalloc-kubernetes",
+            "--context",
+            "test",
+            "delete",
+            "--name",
+            "10.0.0.1",
+        ])
+        .unwrap();
+        assert_eq!(app.context.as_deref(), Some("test"));
+        assert_eq!(app.namespace, None);
     }
 
-    // delete pod and pvc
-    for p in pods {
-        if let Some(ref labels) = p.metadata.labels {
-            //confirm it's created by our applications
-            if let Some(app) = labels.get("app") {
-                if app == "resalloc-kubernetes" {
-                    delete_pod_by_name(pods_api.clone(), &p.name_any()).await?;
-                    info!("pod {} has been deleted", &p.name_any());
-
-                    //delete pvc if needed
-                    if let Some(has_volume) = labels.get("has_volume") {
-                        if has_volume == "true" {
-                            delete_pvc_by_name(pvc_api.clone(), &p.name_any()).await?;
-                            info!("pod's pvc {} has been deleted", &p.name_any());
-                        }
-                    }
-                }
+    #[test]
+    fn test_delete_by_uuid_flag() {
+        let app = App::try_parse_from([
+            "resalloc-kubernetes",
+            "delete",
+            "--uuid",
+            "9a1884fb-8a7b-459f-aefe-c54ac1188d71",
+        ])
+        .unwrap();
+        match app.command {
+            Some(Commands::Delete(delete_command)) => {
+                assert_eq!(delete_command.target(), format!("pod {}", TEST_POD_NAME))
             }
+            _ => panic!("expected delete command"),
         }
     }
-    Ok(())
-}
 
-async fn delete_pod_by_name(pods_api: Api<Pod>, name: &str) -> Result<()> {
-    let delete_params = DeleteParams::default();
-    pods_api.delete(name, &delete_params).await?;
-    Ok(())
-}
+    #[test]
+    fn test_delete_preview_skips_shared_pvc() {
+        let deleted = vec![
+            DeletedPod {
+                name: "resalloc-1".to_string(),
+                pvc: Some("shared".to_string()),
+                pvc_still_referenced: true,
+            },
+            DeletedPod {
+                name: "resalloc-2".to_string(),
+                pvc: Some("resalloc-2".to_string()),
+                pvc_still_referenced: false,
+            },
+        ];
+        let mut output = Vec::new();
+        write_delete_preview(&mut output, &deleted).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "pod/resalloc-1\npersistentvolumeclaim/shared skipped (still referenced)\npod/resalloc-2\npersistentvolumeclaim/resalloc-2\n"
+        );
+    }
 
-async fn delete_pvc_by_name(pvc_api: Api<PersistentVolumeClaim>, name: &str) -> Result<()> {
-    let delete_params = DeleteParams::default();
-    pvc_api.delete(name, &delete_params).await?;
-    Ok(())
-}
+    #[test]
+    fn test_exit_code_written_to_stdout() {
+        //exit code of succeeded pod is printed
+        let mock_command = AllocationRequest::default();
+        let mut output = Vec::new();
+        print_exit_code(&mock_command, &mut output, 0).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "0\n");
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::CommandAdd;
-    use crate::{generate_pod_resource, generate_pvc_resource};
+    #[test]
+    fn test_one_handler_per_probe() {
+        //only one handler is allowed per probe
+        let err = App::try_parse_from([
+            "resalloc-kubernetes",
+            "add",
+            "--image-tag",
+            "openeuler/openeuler:22.03",
+            "--cpu-resource",
+            "100m",
+            "--memory-resource",
+            "500Mi",
+            "--readiness-grpc",
+            "9090",
+            "--readiness-tcp",
+            "22",
+        ])
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
 
     #[tokio::test]
-    async fn test_pod_template_witout_volume() {
-        let yaml_str = r#"apiVersion: v1
-kind: Pod
-metadata:
-  labels:
-    app: resalloc-kubernetes
-    has_volume: 'false'
-  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-  namespace: test_ns
-spec:
-  containers:
-  - image: openeuler/openeuler:22.03
-    imagePullPolicy: IfNotPresent
-    name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-    resources:
-      limits:
-        cpu: 100m
-        memory: 500Mi
-      requests:
-        cpu: 100m
-        memory: 500Mi
-    securityContext:
-      privileged: false
-"#;
+    async fn test_emptydir_flag_composes_with_other_mounts() {
+        let add_command = parse_add_command(
+            [
+                "resalloc-kubernetes",
+                "add",
+                "--image-tag",
+                "openeuler/openeuler:latest",
+                "--cpu-resource",
+                "100m",
+                "--memory-resource",
+                "500Mi",
+                "--secret",
+                "/etc/copr:copr-secrets",
+                "--additional-volume-size",
+                "10Gi",
+                "--additional-volume-class",
+                "test_pvc",
+                "--additional-volume-mount-path",
+                "/var/lib/mock",
+                "--emptydir",
+                "/scratch:1Gi",
+                "--emptydir",
+                "/work",
+            ]
+            .iter()
+            .map(|a| a.into())
+            .collect(),
+        );
+        let pod =
+            generate_pod_resource(&add_command, "test_ns", TEST_POD_NAME, TEST_POD_NAME, true)
+                .await
+                .unwrap();
+        let spec = pod.spec.unwrap();
+        let mount_paths: Vec<String> = spec.containers[0]
+            .volume_mounts
+            .clone()
+            .unwrap()
+            .into_iter()
+            .map(|m| m.mount_path)
+            .collect();
+        assert_eq!(
+            mount_paths,
+            vec!["/etc/copr", "/var/lib/mock", "/scratch", "/work"]
+        );
+        let size_limits: Vec<Option<String>> = spec
+            .volumes
+            .unwrap()
+            .into_iter()
+            .filter_map(|v| v.empty_dir)
+            .map(|e| e.size_limit.map(|q| q.0))
+            .collect();
+        assert_eq!(size_limits, vec![Some("1Gi".to_string()), None]);
+    }
 
-        let mock_command = CommandAdd {
-            timeout: 120,
-            image_tag: "openeuler/openeuler:22.03".to_string(),
-            cpu_resource: "100m".to_string(),
-            memory_resource: "500Mi".to_string(),
-            node_selector: Vec::new(),
-            privileged: false,
-            additional_pvc_labels: Vec::new(),
-            additional_labels: Vec::new(),
-            additional_volume_class: None,
-            additional_volume_size: None,
-            additional_volume_mount_path: None,
-            dry_run: false,
-            secret: None,
-        };
-        let name = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
-        let namespace = "test_ns";
-        let pod_generated = generate_pod_resource(&mock_command, namespace, name, "", false)
+    #[tokio::test]
+    async fn test_image_pull_policy_flag() {
+        //existing behavior is kept when not specified
+        let pod = generate_pod_resource(&parse_add(&[]), "test_ns", TEST_POD_NAME, "", false)
             .await
             .unwrap();
-
-        assert_eq!(pod_generated.metadata.name.as_ref().unwrap(), name);
         assert_eq!(
-            pod_generated.metadata.namespace.as_ref().unwrap(),
-            namespace
+            pod.spec.unwrap().containers[0].image_pull_policy.as_deref(),
+            Some("IfNotPresent")
+        );
+        let pod = generate_pod_resource(
+            &parse_add(&["--image-pull-policy", "Always"]),
+            "test_ns",
+            TEST_POD_NAME,
+            "",
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            pod.spec.unwrap().containers[0].image_pull_policy.as_deref(),
+            Some("Always")
         );
-        assert_eq!(serde_yaml::to_string(&pod_generated).unwrap(), yaml_str);
+
+        let err = App::try_parse_from(add_args(&["--image-pull-policy", "Sometimes"]))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), clap::error::ErrorKind::InvalidValue);
+        assert!(err
+            .to_string()
+            .contains("[possible values: Always, IfNotPresent, Never]"));
     }
 
     #[tokio::test]
-    async fn test_pod_template_with_labels() {
-        let yaml_str = r#"apiVersion: v1
-kind: Pod
-metadata:
-  labels:
-    app: resalloc-kubernetes
-    failure-domain.beta.kubernetes.io/region: region1
-    has_volume: 'false'
-  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-  namespace: test_ns
-spec:
-  containers:
-  - image: openeuler/openeuler:22.03
-    imagePullPolicy: IfNotPresent
-    name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-    resources:
-      limits:
-        cpu: 100m
-        memory: 500Mi
-      requests:
-        cpu: 100m
-        memory: 500Mi
-    securityContext:
-      privileged: false
-"#;
+    async fn test_restart_policy_flag() {
+        //kubernetes defaults to Always when the field is omitted
+        let pod = generate_pod_resource(&parse_add(&[]), "test_ns", TEST_POD_NAME, "", false)
+            .await
+            .unwrap();
+        assert_eq!(pod.spec.unwrap().restart_policy, None);
+        for policy in ["Always", "OnFailure", "Never"] {
+            let pod = generate_pod_resource(
+                &parse_add(&["--restart-policy", policy]),
+                "test_ns",
+                TEST_POD_NAME,
+                "",
+                false,
+            )
+            .await
+            .unwrap();
+            assert_eq!(pod.spec.unwrap().restart_policy.as_deref(), Some(policy));
+        }
 
-        let mock_command = CommandAdd {
-            timeout: 120,
-            image_tag: "openeuler/openeuler:22.03".to_string(),
-            cpu_resource: "100m".to_string(),
-            memory_resource: "500Mi".to_string(),
-            node_selector: Vec::new(),
-            privileged: false,
-            additional_pvc_labels: Vec::new(),
-            additional_labels: vec![("failure-domain.beta.kubernetes.io/region=region1".to_string())],
-            additional_volume_class: None,
-            additional_volume_size: None,
-            additional_volume_mount_path: None,
-            dry_run: false,
-            secret: None,
+        let err = App::try_parse_from(add_args(&["--restart-policy", "never"]))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), clap::error::ErrorKind::InvalidValue);
+        assert!(err
+            .to_string()
+            .contains("[possible values: Always, OnFailure, Never]"));
+    }
+
+    #[tokio::test]
+    async fn test_gpu_flags() {
+        let gpu_quantities = |pod: k8s_openapi::api::core::v1::Pod| {
+            let resources = pod.spec.unwrap().containers[0].resources.clone().unwrap();
+            [resources.requests.unwrap(), resources.limits.unwrap()]
+                .into_iter()
+                .map(|quantities| {
+                    quantities
+                        .into_iter()
+                        .filter(|(key, _)| key.ends_with("/gpu"))
+                        .map(|(key, value)| format!("{}={}", key, value.0))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
         };
-        let name = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
-        let namespace = "test_ns";
-        let pod_generated = generate_pod_resource(&mock_command, namespace, name, "", false)
+
+        //no gpu resource unless a positive count is given
+        for extra in [&[][..], &["--gpu", "0"][..]] {
+            let pod = generate_pod_resource(&parse_add(extra), "test_ns", TEST_POD_NAME, "", false)
+                .await
+                .unwrap();
+            assert_eq!(gpu_quantities(pod), vec![Vec::<String>::new(); 2]);
+        }
+        for vendor in ["nvidia", "nvidia.com/gpu"] {
+            let pod = generate_pod_resource(
+                &parse_add(&["--gpu", "2", "--gpu-vendor", vendor]),
+                "test_ns",
+                TEST_POD_NAME,
+                "",
+                false,
+            )
             .await
             .unwrap();
-
-        assert_eq!(pod_generated.metadata.name.as_ref().unwrap(), name);
+            assert_eq!(
+                gpu_quantities(pod),
+                vec![vec!["nvidia.com/gpu=2".to_string()]; 2]
+            );
+        }
+        let pod = generate_pod_resource(
+            &parse_add(&["--gpu", "1", "--gpu-vendor", "amd.com/gpu"]),
+            "test_ns",
+            TEST_POD_NAME,
+            "",
+            false,
+        )
+        .await
+        .unwrap();
         assert_eq!(
-            pod_generated.metadata.namespace.as_ref().unwrap(),
-            namespace
+            gpu_quantities(pod),
+            vec![vec!["amd.com/gpu=1".to_string()]; 2]
         );
-        assert_eq!(serde_yaml::to_string(&pod_generated).unwrap(), yaml_str);
+
+        //the count must be a non negative integer
+        for count in ["-1", "1.5", "two"] {
+            assert!(App::try_parse_from(add_args(&["--gpu", count])).is_err());
+        }
     }
-    
-    #[tokio::test]
-    async fn test_pod_template_with_volume_and_secret() {
-        let yaml_str = r#"apiVersion: v1
-kind: Pod
-metadata:
-  labels:
-    app: resalloc-kubernetes
-    has_volume: 'true'
-  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-  namespace: test_ns
-spec:
-  containers:
-  - image: openeuler/openeuler:22.03
-    imagePullPolicy: IfNotPresent
-    name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-    resources:
-      limits:
-        cpu: 100m
-        memory: 500Mi
-      requests:
-        cpu: 100m
-        memory: 500Mi
-    securityContext:
-      privileged: false
-    volumeMounts:
-    - mountPath: /home/copr/server.crt
-      name: copr-secrets
-      subPath: server-crt
-    - mountPath: /etc/test_mount
-      name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-  volumes:
-  - name: copr-secrets
-    secret:
-      secretName: copr-secrets
-  - name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-    persistentVolumeClaim:
-      claimName: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-"#;
 
-        let mock_command = CommandAdd {
-            timeout: 120,
-            image_tag: "openeuler/openeuler:22.03".to_string(),
-            cpu_resource: "100m".to_string(),
-            memory_resource: "500Mi".to_string(),
-            node_selector: Vec::new(),
-            privileged: false,
-            additional_pvc_labels: Vec::new(),
-            additional_labels: Vec::new(),
-            additional_volume_class: Some("test_pvc".to_string()),
-            additional_volume_size: Some("10Gi".to_string()),
-            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
-            dry_run: false,
-            secret: Some(k8s_openapi::api::core::v1::VolumeMount {
-                mount_path: "/home/copr/server.crt".to_string(),
-                mount_propagation: None,
-                name: "copr-secrets".to_string(),
-                read_only: None,
-                sub_path: Some("server-crt".to_string()),
-                sub_path_expr: None,
-            }),
+    #[test]
+    fn test_add_flags_from_environment() {
+        //the variables are checked on the command rather than set, tests share the environment
+        let command = App::command();
+        let env_of = |command: &clap::Command, long: &str| {
+            command
+                .get_arguments()
+                .find(|a| a.get_long() == Some(long))
+                .and_then(|a| a.get_env())
+                .map(|e| e.to_str().unwrap().to_string())
         };
-        let name = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
-        let namespace = "test_ns";
-        let pvc_name = name;
-        let pod_generated = generate_pod_resource(&mock_command, namespace, name, pvc_name, true)
-            .await
-            .unwrap();
+        assert_eq!(
+            env_of(&command, "namespace").as_deref(),
+            Some("RESALLOC_NAMESPACE")
+        );
+        let add = command.find_subcommand("add").unwrap();
+        for (long, var) in [
+            ("image-tag", "RESALLOC_IMAGE_TAG"),
+            ("cpu-resource", "RESALLOC_CPU_RESOURCE"),
+            ("memory-resource", "RESALLOC_MEMORY_RESOURCE"),
+            ("cost-center", "RESALLOC_COST_CENTER"),
+            ("trace-id", "RESALLOC_TRACE_ID"),
+        ] {
+            assert_eq!(env_of(add, long).as_deref(), Some(var));
+        }
+    }
 
-        assert_eq!(pod_generated.metadata.name.as_ref().unwrap(), name);
+    #[tokio::test]
+    async fn test_wait_condition_alias() {
+        assert_eq!(parse_add(&[]).wait_for, WaitFor::Running);
         assert_eq!(
-            pod_generated.metadata.namespace.as_ref().unwrap(),
-            namespace
+            parse_add(&["--wait-condition", "ready"]).wait_for,
+            WaitFor::Ready
         );
-        assert_eq!(serde_yaml::to_string(&pod_generated).unwrap(), yaml_str);
+        assert_eq!(parse_add(&["--wait-for", "ready"]).wait_for, WaitFor::Ready);
     }
 
     #[tokio::test]
-    async fn test_pod_template_with_volume() {
-        let pvc_yaml_str = r#"apiVersion: v1
-kind: PersistentVolumeClaim
-metadata:
-  labels:
-    app: resalloc-kubernetes
-    failure-domain.beta.kubernetes.io/region: region1
-  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-  namespace: test_ns
-spec:
-  accessModes:
-  - ReadWriteOnce
-  resources:
-    requests:
-      storage: 10Gi
-  storageClassName: test_pvc
-"#;
-        let pod_yaml_str = r#"apiVersion: v1
-kind: Pod
-metadata:
-  labels:
-    app: resalloc-kubernetes
-    has_volume: 'true'
-  name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-  namespace: test_ns
-spec:
-  containers:
-  - image: openeuler/openeuler:22.03
-    imagePullPolicy: IfNotPresent
-    name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-    resources:
-      limits:
-        cpu: '1'
-        memory: 500Mi
-      requests:
-        cpu: '1'
-        memory: 500Mi
-    securityContext:
-      privileged: false
-    volumeMounts:
-    - mountPath: /etc/test_mount
-      name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-  volumes:
-  - name: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-    persistentVolumeClaim:
-      claimName: resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71
-"#;
-        let mock_command = CommandAdd {
-            timeout: 120,
+    async fn test_wait_false_skips_wait() {
+        let parse_add = |extra: &[&str]| {
+            App::try_parse_from(add_args(extra)).map(|app| match app.command {
+                Some(Commands::Add(add_command)) => skips_wait(&add_command),
+                _ => panic!("expected add command"),
+            })
+        };
+        assert!(!parse_add(&[]).unwrap());
+        assert!(parse_add(&["--no-wait"]).unwrap());
+        assert!(parse_add(&["--wait=false"]).unwrap());
+        assert!(!parse_add(&["--wait=true"]).unwrap());
+        assert!(parse_add(&["--wait=false", "--no-wait"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_error_reports_trace_id() {
+        //invalid request fails before reaching the cluster
+        let request = AllocationRequest {
             image_tag: "openeuler/openeuler:22.03".to_string(),
-            cpu_resource: "1".to_string(),
+            cpu_resource: "100m".to_string(),
             memory_resource: "500Mi".to_string(),
-            node_selector: Vec::new(),
-            privileged: false,
-            additional_pvc_labels: vec![("failure-domain.beta.kubernetes.io/region=region1".to_string())],
-            additional_labels: Vec::new(),
-            additional_volume_class: Some("test_pvc".to_string()),
             additional_volume_size: Some("10Gi".to_string()),
-            additional_volume_mount_path: Some("/etc/test_mount".to_string()),
-            dry_run: false,
-            secret: None,
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+            ..Default::default()
         };
-
-        let name = "resalloc-9a1884fb-8a7b-459f-aefe-c54ac1188d71";
-        let namespace = "test_ns";
-        let pvc_name = name;
-        let pod_generated = generate_pod_resource(&mock_command, namespace, name, pvc_name, true)
+        let err = resalloc_kubernetes::allocate(&request, "test_ns")
             .await
-            .unwrap();
+            .unwrap_err();
 
-        let pvc = generate_pvc_resource(&mock_command, namespace, pvc_name)
-            .await
-            .unwrap();
-        assert_eq!(pod_generated.metadata.name.as_ref().unwrap(), name);
-        assert_eq!(
-            pod_generated.metadata.namespace.as_ref().unwrap(),
-            namespace
-        );
-        assert_eq!(serde_yaml::to_string(&pod_generated).unwrap(), pod_yaml_str);
-        assert_eq!(serde_yaml::to_string(&pvc).unwrap(), pvc_yaml_str);
+        let mut stderr = Vec::new();
+        write_error(&mut stderr, &err, crate::OutputFormat::Text);
+        let output = String::from_utf8(stderr).unwrap();
+        assert!(output.starts_with("Error: "));
+        assert!(output.ends_with("\n\nTrace id: 4bf92f3577b34da6a3ce929d0e0e4736\n"));
+
+        let mut stderr = Vec::new();
+        write_error(&mut stderr, &err, crate::OutputFormat::Json);
+        let json: serde_json::Value = serde_json::from_slice(&stderr).unwrap();
+        assert_eq!(json["trace_id"], "4bf92f3577b34da6a3ce929d0e0e4736");
     }
 }